@@ -0,0 +1,78 @@
+use brotlic::parallel::ParallelDecompressorOptions;
+use brotlic::seekable::SeekableCompressorOptions;
+use brotlic::{BrotliEncoderOptions, DecompressorReader, Quality};
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg32;
+use std::io::{self, Cursor, Write};
+
+fn seekable_compress(input: &[u8], frame_size: usize) -> Vec<u8> {
+    let mut compressor = SeekableCompressorOptions::new()
+        .encoder_options(BrotliEncoderOptions::new().quality(Quality::new(5).unwrap()))
+        .frame_size(frame_size)
+        .build(Vec::new())
+        .unwrap();
+
+    compressor.write_all(input).unwrap();
+    compressor.into_inner().unwrap()
+}
+
+fn sequential_decompress(compressed: &[u8], output: &mut Vec<u8>) {
+    output.clear();
+    let mut decompressor = DecompressorReader::new(compressed);
+    io::copy(&mut decompressor, output).unwrap();
+}
+
+fn parallel_decompress(compressed: &[u8], threads: usize, output: &mut [u8]) {
+    ParallelDecompressorOptions::new()
+        .threads(threads)
+        .decompress_into(Cursor::new(compressed), output)
+        .unwrap();
+}
+
+pub fn bench(c: &mut Criterion) {
+    let input_size = 1 << 24;
+    let input = gen_medium_entropy(input_size);
+
+    let mut group = c.benchmark_group("decompression");
+    group.throughput(Throughput::Bytes(input_size as u64));
+    group.sample_size(20);
+
+    for frame_size in [1 << 18, 1 << 20] {
+        let compressed = seekable_compress(&input, frame_size);
+        let mut output = Vec::with_capacity(input_size);
+
+        sequential_decompress(&compressed, &mut output);
+        assert_eq!(output, input);
+
+        group.bench_with_input(
+            BenchmarkId::new("sequential", frame_size),
+            &frame_size,
+            |b, &_size| {
+                b.iter(|| sequential_decompress(&compressed, &mut output));
+            },
+        );
+
+        for threads in [1, 2, 4, 8] {
+            let id = format!("{}_threads", threads);
+
+            group.bench_with_input(
+                BenchmarkId::new(id, frame_size),
+                &frame_size,
+                |b, &_size| {
+                    b.iter(|| parallel_decompress(&compressed, threads, &mut output));
+                },
+            );
+        }
+    }
+}
+
+fn gen_medium_entropy(len: usize) -> Vec<u8> {
+    let mut res = Vec::with_capacity(len);
+    let mut rng = Pcg32::seed_from_u64(len as u64);
+    res.resize_with(len, || rng.gen_range(0..128));
+    res
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
@@ -38,13 +38,17 @@ fn main() {
             "brotli/c/enc/metablock.c",
             "brotli/c/enc/static_dict.c",
             "brotli/c/enc/utf8_util.c",
+            "src/decoded_size.c",
         ])
         .include("brotli/c/include")
         .define("BROTLI_BUILD_ENC_EXTRA_API", None)
+        // report failed allocations as errors instead of exiting the process
+        .define("BROTLI_ENCODER_CLEANUP_ON_OOM", None)
         .warnings(false)
         .flag_if_supported("-fcommon")
         .compile("brotli");
 
     println!("cargo:include={}", include_dir.display());
     println!("cargo:rerun-if-changed=brotli/c");
+    println!("cargo:rerun-if-changed=src/decoded_size.c");
 }
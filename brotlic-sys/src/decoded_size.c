@@ -0,0 +1,34 @@
+/* Accessors for decoder state that is not exposed by the brotli API.
+
+   The brotli API does not report how many decoded bytes are still pending in
+   the ring buffer, so the decoded size is read from the private decoder state.
+   Its layout is not stable across brotli releases, hence the accessor is pinned
+   to the vendored release and checks the types of the fields it reads. Review
+   the fields below against dec/state.h before updating the brotli submodule. */
+
+#include <stddef.h>
+
+#include "../brotli/c/common/version.h"
+#include "../brotli/c/dec/state.h"
+
+#if BROTLI_VERSION != BROTLI_MAKE_HEX_VERSION(1, 1, 0)
+#error BrotlicDecoderGetDecodedSize is only verified against brotli 1.1.0
+#endif
+
+/* Fails to compile if the condition does not hold. */
+#define BROTLIC_STATIC_ASSERT(name, cond) \
+  typedef char brotlic_static_assert_##name[(cond) ? 1 : -1]
+
+#define BROTLIC_FIELD_SIZE(field) sizeof(((BrotliDecoderState*)0)->field)
+
+BROTLIC_STATIC_ASSERT(rb_roundtrips, BROTLIC_FIELD_SIZE(rb_roundtrips) ==
+                                         sizeof(size_t));
+BROTLIC_STATIC_ASSERT(ringbuffer_size,
+                      BROTLIC_FIELD_SIZE(ringbuffer_size) == sizeof(int));
+BROTLIC_STATIC_ASSERT(pos, BROTLIC_FIELD_SIZE(pos) == sizeof(int));
+
+/* Returns the number of bytes decoded so far, regardless of how much of it was
+   already pushed to the output. */
+size_t BrotlicDecoderGetDecodedSize(const BrotliDecoderState* s) {
+  return s->rb_roundtrips * (size_t)s->ringbuffer_size + (size_t)s->pos;
+}
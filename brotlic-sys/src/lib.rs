@@ -39,6 +39,30 @@ pub type brotli_alloc_func = Option<extern "C" fn(opaque: *mut c_void, size: usi
 #[doc = " @param address memory region pointer returned by ::brotli_alloc_func, or @c 0"]
 pub type brotli_free_func = Option<extern "C" fn(opaque: *mut c_void, address: *mut c_void)>;
 
+#[doc = " Callback to fire on metadata block start."]
+#[doc = ""]
+#[doc = " After this callback is fired, if @p size is not @c 0, it is followed by"]
+#[doc = " ::brotli_decoder_metadata_chunk_func as more metadata block contents become"]
+#[doc = " accessible."]
+#[doc = ""]
+#[doc = " @param opaque callback handle"]
+#[doc = " @param size size of metadata block"]
+pub type brotli_decoder_metadata_start_func =
+    Option<extern "C" fn(opaque: *mut c_void, size: usize)>;
+
+#[doc = " Callback to fire on metadata block chunk becomes available."]
+#[doc = ""]
+#[doc = " This function can be invoked multiple times per metadata block; block should"]
+#[doc = " be considered finished when sum of @p size matches the announced metadata"]
+#[doc = " block size. Chunks contents pointed by @p data are transient and shouln not"]
+#[doc = " be accessed after leaving the callback."]
+#[doc = ""]
+#[doc = " @param opaque callback handle"]
+#[doc = " @param data pointer to metadata contents"]
+#[doc = " @param size size of metadata block chunk, at least @c 1"]
+pub type brotli_decoder_metadata_chunk_func =
+    Option<extern "C" fn(opaque: *mut c_void, data: *const u8, size: usize)>;
+
 #[doc = " Default compression mode."]
 #[doc = ""]
 #[doc = " In this mode compressor does not know anything in advance about the"]
@@ -813,4 +837,25 @@ extern "C" {
     #[doc = ""]
     #[doc = " Look at BROTLI_VERSION for more information."]
     pub fn BrotliDecoderVersion() -> u32;
+
+    #[doc = " Sets callback for receiving metadata blocks."]
+    #[doc = ""]
+    #[doc = " @param state decoder instance"]
+    #[doc = " @param start_func callback on metadata block start"]
+    #[doc = " @param chunk_func callback on metadata block chunk"]
+    #[doc = " @param opaque callback handle"]
+    pub fn BrotliDecoderSetMetadataCallbacks(
+        state: *mut BrotliDecoderState,
+        start_func: brotli_decoder_metadata_start_func,
+        chunk_func: brotli_decoder_metadata_chunk_func,
+        opaque: *mut c_void,
+    );
+
+    #[doc = " Gets the number of bytes decoded so far, including bytes that were not yet"]
+    #[doc = " pushed to the output."]
+    #[doc = ""]
+    #[doc = " This is not part of the brotli API, it is provided by brotlic-sys. It is"]
+    #[doc = " safe to call from within metadata callbacks. It reads the private decoder"]
+    #[doc = " state, so it only builds against the vendored brotli release."]
+    pub fn BrotlicDecoderGetDecodedSize(state: *const BrotliDecoderState) -> usize;
 }
@@ -4,26 +4,63 @@ use std::io::BufReader;
 
 use clap::{arg, Command};
 
-use brotlic::{CompressorWriter, DecompressorReader};
+#[cfg(feature = "precompress")]
+use brotlic::precompress::PrecompressOptions;
+use brotlic::{BrotliEncoderOptions, CompressorWriter, DecompressorReader, Quality, WindowSize};
 
 fn main() {
-    let matches = Command::new("br")
+    let command = Command::new("br")
         .version("0.1")
         .about("File brotli compression tool")
-        .arg(arg!(<FILE> "The file to compress"))
+        .arg(arg!(<FILE> "The file to compress, or the directory to precompress"))
         .arg(arg!(-d --decompress))
-        .get_matches();
+        .arg(arg!(-q --quality <QUALITY> "The compression quality, from 0 to 11").required(false))
+        .arg(arg!(-w --window <BITS> "The window size in bits, from 10 to 24").required(false));
+
+    #[cfg(feature = "precompress")]
+    let command = command.arg(arg!(
+        -p --precompress "Writes a .br sibling for each static asset in the directory"
+    ));
+
+    let matches = command.get_matches();
 
     let path = matches.value_of("FILE").expect("supplied by clap");
     let compress = !matches.is_present("decompress");
 
+    let quality = matches.value_of("quality").map(|quality| {
+        let quality = quality.parse().expect("quality is not a number");
+        Quality::new(quality).expect("quality out of range")
+    });
+    let window_size = matches.value_of("window").map(|bits| {
+        let bits = bits.parse().expect("window size is not a number");
+        WindowSize::new(bits).expect("window size out of range")
+    });
+
+    #[cfg(feature = "precompress")]
+    if matches.is_present("precompress") {
+        precompress(path, quality, window_size);
+        return;
+    }
+
     if compress {
         let mut input_file = File::open(path).expect("failed to open input file");
 
         let mut output_file = {
             let write_path = [path, ".br"].concat();
+            let mut options = BrotliEncoderOptions::new();
+
+            if let Some(quality) = quality {
+                options.quality(quality);
+            }
 
-            CompressorWriter::new(File::create(write_path).expect("failed to create output file"))
+            if let Some(window_size) = window_size {
+                options.window_size(window_size);
+            }
+
+            CompressorWriter::with_encoder(
+                options.build().expect("invalid encoder options"),
+                File::create(write_path).expect("failed to create output file"),
+            )
         };
 
         io::copy(&mut input_file, &mut output_file).expect("io error");
@@ -43,3 +80,28 @@ fn main() {
         io::copy(&mut input_file, &mut output_file).expect("io error");
     }
 }
+
+#[cfg(feature = "precompress")]
+fn precompress(path: &str, quality: Option<Quality>, window_size: Option<WindowSize>) {
+    let mut options = PrecompressOptions::new();
+
+    if let Some(quality) = quality {
+        options.quality(quality);
+    }
+
+    if let Some(window_size) = window_size {
+        options.window_size(window_size);
+    }
+
+    let report = options
+        .precompress_dir(path)
+        .expect("failed to precompress directory");
+
+    println!(
+        "{} compressed, {} not smaller, {} unchanged, {} removed",
+        report.compressed(),
+        report.skipped(),
+        report.unchanged(),
+        report.removed()
+    );
+}
@@ -0,0 +1,218 @@
+//! Helpers shared by the async wrappers of every runtime.
+//!
+//! The wrappers of each runtime only adapt its traits, passing the methods of the wrapped reader
+//! or writer to the helpers below, which drive the state machines shared with the blocking
+//! wrappers.
+
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+
+use crate::{decode, encode};
+
+/// The state machine of an async reader.
+pub(crate) trait ReaderState {
+    /// Returns if the next call to [`Self::read`] reads from its input.
+    fn needs_input(&self) -> bool {
+        true
+    }
+
+    fn read(&mut self, input: &[u8], buf: &mut [u8]) -> io::Result<(usize, Option<usize>)>;
+}
+
+impl ReaderState for encode::ReaderState {
+    fn needs_input(&self) -> bool {
+        self.needs_input()
+    }
+
+    fn read(&mut self, input: &[u8], buf: &mut [u8]) -> io::Result<(usize, Option<usize>)> {
+        self.read(input, buf)
+    }
+}
+
+impl ReaderState for decode::ReaderState {
+    fn read(&mut self, input: &[u8], buf: &mut [u8]) -> io::Result<(usize, Option<usize>)> {
+        self.read(input, buf)
+    }
+}
+
+/// The state machine of an async writer, which buffers its output.
+pub(crate) trait WriterState {
+    fn pending(&self) -> &[u8];
+
+    fn consume(&mut self, amt: usize);
+
+    fn write(&mut self, input: &[u8]) -> io::Result<usize>;
+
+    fn flush(&mut self) -> io::Result<bool>;
+
+    fn finish(&mut self) -> io::Result<bool>;
+}
+
+impl WriterState for encode::AsyncWriterState {
+    fn pending(&self) -> &[u8] {
+        self.pending()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.consume(amt)
+    }
+
+    fn write(&mut self, input: &[u8]) -> io::Result<usize> {
+        self.write(input)
+    }
+
+    fn flush(&mut self) -> io::Result<bool> {
+        self.flush()
+    }
+
+    fn finish(&mut self) -> io::Result<bool> {
+        self.finish()
+    }
+}
+
+impl WriterState for decode::AsyncWriterState {
+    fn pending(&self) -> &[u8] {
+        self.pending()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.consume(amt)
+    }
+
+    fn write(&mut self, input: &[u8]) -> io::Result<usize> {
+        self.write(input)
+    }
+
+    fn flush(&mut self) -> io::Result<bool> {
+        self.flush()
+    }
+
+    fn finish(&mut self) -> io::Result<bool> {
+        self.finish()
+    }
+}
+
+/// Reads from `inner` through `state` into `buf`, using `poll_fill_buf` and `consume` of the
+/// wrapped reader. Returns the number of bytes written to `buf`.
+pub(crate) fn poll_read<S, R, F, C>(
+    state: &mut S,
+    mut inner: Pin<&mut R>,
+    cx: &mut Context<'_>,
+    buf: &mut [u8],
+    mut poll_fill_buf: F,
+    mut consume: C,
+) -> Poll<io::Result<usize>>
+where
+    S: ReaderState,
+    F: for<'a> FnMut(Pin<&'a mut R>, &mut Context<'_>) -> Poll<io::Result<&'a [u8]>>,
+    C: FnMut(Pin<&mut R>, usize),
+{
+    loop {
+        let input = match state.needs_input() {
+            true => ready!(poll_fill_buf(inner.as_mut(), cx))?,
+            false => &[],
+        };
+
+        let (bytes_read, bytes_written) = state.read(input, buf)?;
+        consume(inner.as_mut(), bytes_read);
+
+        if let Some(bytes_written) = bytes_written {
+            return Poll::Ready(Ok(bytes_written));
+        }
+    }
+}
+
+/// Writes `buf` through `state` to `inner`, using `poll_write` of the wrapped writer.
+pub(crate) fn poll_write<S, W, F>(
+    state: &mut S,
+    mut inner: Pin<&mut W>,
+    cx: &mut Context<'_>,
+    buf: &[u8],
+    mut poll_write: F,
+) -> Poll<io::Result<usize>>
+where
+    S: WriterState,
+    F: FnMut(Pin<&mut W>, &mut Context<'_>, &[u8]) -> Poll<io::Result<usize>>,
+{
+    loop {
+        ready!(poll_pending(state, |pending| poll_write(inner.as_mut(), cx, pending)))?;
+
+        let bytes_read = state.write(buf)?;
+
+        if bytes_read > 0 || buf.is_empty() || state.pending().is_empty() {
+            return Poll::Ready(Ok(bytes_read));
+        }
+    }
+}
+
+/// Flushes `state` into `inner`, then flushes `inner` using `poll_flush` of the wrapped writer.
+pub(crate) fn poll_flush<S, W, F, G>(
+    state: &mut S,
+    inner: Pin<&mut W>,
+    cx: &mut Context<'_>,
+    poll_write: F,
+    poll_flush: G,
+) -> Poll<io::Result<()>>
+where
+    S: WriterState,
+    F: FnMut(Pin<&mut W>, &mut Context<'_>, &[u8]) -> Poll<io::Result<usize>>,
+    G: FnOnce(Pin<&mut W>, &mut Context<'_>) -> Poll<io::Result<()>>,
+{
+    poll_complete(state, inner, cx, S::flush, poll_write, poll_flush)
+}
+
+/// Finishes `state` into `inner`, then closes `inner` using `poll_close` of the wrapped writer.
+pub(crate) fn poll_close<S, W, F, G>(
+    state: &mut S,
+    inner: Pin<&mut W>,
+    cx: &mut Context<'_>,
+    poll_write: F,
+    poll_close: G,
+) -> Poll<io::Result<()>>
+where
+    S: WriterState,
+    F: FnMut(Pin<&mut W>, &mut Context<'_>, &[u8]) -> Poll<io::Result<usize>>,
+    G: FnOnce(Pin<&mut W>, &mut Context<'_>) -> Poll<io::Result<()>>,
+{
+    poll_complete(state, inner, cx, S::finish, poll_write, poll_close)
+}
+
+/// Writes the output of `complete` until it returns `true`, then completes `inner`.
+fn poll_complete<S, W, F, G>(
+    state: &mut S,
+    mut inner: Pin<&mut W>,
+    cx: &mut Context<'_>,
+    complete: fn(&mut S) -> io::Result<bool>,
+    mut poll_write: F,
+    poll_inner: G,
+) -> Poll<io::Result<()>>
+where
+    S: WriterState,
+    F: FnMut(Pin<&mut W>, &mut Context<'_>, &[u8]) -> Poll<io::Result<usize>>,
+    G: FnOnce(Pin<&mut W>, &mut Context<'_>) -> Poll<io::Result<()>>,
+{
+    loop {
+        ready!(poll_pending(state, |pending| poll_write(inner.as_mut(), cx, pending)))?;
+
+        if complete(state)? {
+            return poll_inner(inner, cx);
+        }
+    }
+}
+
+/// Writes all pending output of `state` using `poll_write`.
+fn poll_pending<S, F>(state: &mut S, mut poll_write: F) -> Poll<io::Result<()>>
+where
+    S: WriterState,
+    F: FnMut(&[u8]) -> Poll<io::Result<usize>>,
+{
+    while !state.pending().is_empty() {
+        match ready!(poll_write(state.pending()))? {
+            0 => return Poll::Ready(Err(io::ErrorKind::WriteZero.into())),
+            n => state.consume(n),
+        }
+    }
+
+    Poll::Ready(Ok(()))
+}
@@ -0,0 +1,17 @@
+//! Module that contains compression and decompression readers over [`BufRead`]
+//!
+//! The readers in this module read their input directly from the buffer of the underlying
+//! [`BufRead`], without buffering it once more. They are the same types that are exported from the
+//! crate root, [`encode`] and [`decode`]. To wrap a plain [`Read`] that does not implement
+//! [`BufRead`], such as a [`File`], see the readers in the [`read`] module instead.
+//!
+//! [`BufRead`]: https://doc.rust-lang.org/stable/std/io/trait.BufRead.html
+//! [`Read`]: https://doc.rust-lang.org/stable/std/io/trait.Read.html
+//! [`File`]: https://doc.rust-lang.org/stable/std/fs/struct.File.html
+//! [`encode`]: crate::encode
+//! [`decode`]: crate::decode
+//! [`read`]: crate::read
+
+pub use crate::decode::DecompressorReader;
+pub use crate::decode::MultiDecompressorReader;
+pub use crate::encode::CompressorReader;
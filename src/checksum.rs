@@ -0,0 +1,325 @@
+//! Module that contains integrity checksums for brotli streams
+//!
+//! Brotli has no built-in checksum, so corruption of the compressed data may go unnoticed. This
+//! module provides opt-in checksums of the uncompressed data that are carried within metadata
+//! blocks of the stream. Since decoders skip metadata blocks, streams with checksums can still be
+//! decompressed by any brotli decoder.
+//!
+//! Checksums are written by [`CompressorReader`] and [`CompressorWriter`] if enabled by
+//! [`BrotliEncoderOptions::checksum`]. They are verified by [`DecompressorReader`] and
+//! [`DecompressorWriter`] if enabled by [`verify_checksums`].
+//!
+//! Every stream starts with a checksum of no data, which declares the algorithm, so that the
+//! decompressed data only needs to be hashed with that algorithm while it is verified.
+//!
+//! This module requires the `checksum` feature.
+//!
+//! [`CompressorReader`]: crate::encode::CompressorReader
+//! [`CompressorWriter`]: crate::encode::CompressorWriter
+//! [`BrotliEncoderOptions::checksum`]: crate::encode::BrotliEncoderOptions::checksum
+//! [`DecompressorReader`]: crate::decode::DecompressorReader
+//! [`DecompressorWriter`]: crate::decode::DecompressorWriter
+//! [`verify_checksums`]: crate::decode::BrotliDecoderOptions::verify_checksums
+
+use std::collections::VecDeque;
+use std::error::Error;
+use std::{fmt, io};
+
+use xxhash_rust::xxh64::Xxh64;
+
+use crate::decode::{BrotliDecoder, Metadata};
+
+const MAGIC: &[u8; 4] = b"BRCS";
+const VERSION: u8 = 1;
+const RECORD_LEN: usize = 22;
+
+/// The checksum algorithm used to verify the integrity of the uncompressed data.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Checksum {
+    /// CRC-32C (Castagnoli), hardware accelerated on most platforms.
+    Crc32c = 1,
+
+    /// 64-bit xxHash, a fast non-cryptographic hash with a lower collision probability.
+    Xxh64 = 2,
+}
+
+/// Determines when checksums are written to the compression stream.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ChecksumMode {
+    /// A single checksum of all uncompressed data is written once the stream is finished.
+    Trailer,
+
+    /// A checksum of all uncompressed data so far is written on every flush and once the stream
+    /// is finished. This allows corruption to be detected early on long-lived streams.
+    Flush,
+}
+
+/// An error returned if the checksums of a compression stream failed to verify.
+///
+/// This error is wrapped in an [`io::Error`] of kind [`io::ErrorKind::InvalidData`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ChecksumError {
+    /// The checksum of the decompressed data does not match the one in the stream.
+    Mismatch,
+
+    /// The stream finished without a checksum covering all of the decompressed data, or its data
+    /// was not preceded by a checksum declaring the algorithm.
+    Missing,
+}
+
+impl Error for ChecksumError {}
+
+impl fmt::Display for ChecksumError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChecksumError::Mismatch => f.write_str("checksum mismatch"),
+            ChecksumError::Missing => f.write_str("checksum missing from the stream"),
+        }
+    }
+}
+
+impl From<ChecksumError> for io::Error {
+    fn from(err: ChecksumError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
+
+#[derive(Clone)]
+enum Hasher {
+    Crc32c(u32),
+    Xxh64(Xxh64),
+}
+
+impl fmt::Debug for Hasher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Hasher::Crc32c(_) => f.write_str("Crc32c"),
+            Hasher::Xxh64(_) => f.write_str("Xxh64"),
+        }
+    }
+}
+
+impl Hasher {
+    fn new(checksum: Checksum) -> Self {
+        match checksum {
+            Checksum::Crc32c => Hasher::Crc32c(0),
+            Checksum::Xxh64 => Hasher::Xxh64(Xxh64::new(0)),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Crc32c(crc) => *crc = crc32c::crc32c_append(*crc, data),
+            Hasher::Xxh64(xxh) => xxh.update(data),
+        }
+    }
+
+    fn digest(&self) -> u64 {
+        match self {
+            Hasher::Crc32c(crc) => *crc as u64,
+            Hasher::Xxh64(xxh) => xxh.digest(),
+        }
+    }
+
+    fn checksum(&self) -> Checksum {
+        match self {
+            Hasher::Crc32c(_) => Checksum::Crc32c,
+            Hasher::Xxh64(_) => Checksum::Xxh64,
+        }
+    }
+}
+
+/// Computes the checksums written by the compressor wrappers.
+#[derive(Debug, Clone)]
+pub(crate) struct ChecksumWriter {
+    checksum: Checksum,
+    mode: ChecksumMode,
+    hasher: Hasher,
+    len: u64,
+    recorded_len: Option<u64>,
+}
+
+impl ChecksumWriter {
+    pub(crate) fn new(checksum: Checksum, mode: ChecksumMode) -> Self {
+        ChecksumWriter {
+            checksum,
+            mode,
+            hasher: Hasher::new(checksum),
+            len: 0,
+            recorded_len: None,
+        }
+    }
+
+    pub(crate) fn mode(&self) -> ChecksumMode {
+        self.mode
+    }
+
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        self.hasher.update(data);
+        self.len += data.len() as u64;
+    }
+
+    /// Returns the contents of the metadata block recording the checksum of all data so far, or
+    /// `None` if it was already recorded.
+    pub(crate) fn record(&mut self) -> Option<Vec<u8>> {
+        if self.recorded_len == Some(self.len) {
+            return None;
+        }
+
+        self.recorded_len = Some(self.len);
+
+        let mut record = Vec::with_capacity(RECORD_LEN);
+        record.extend_from_slice(MAGIC);
+        record.push(VERSION);
+        record.push(self.checksum as u8);
+        record.extend_from_slice(&self.len.to_le_bytes());
+        record.extend_from_slice(&self.hasher.digest().to_le_bytes());
+
+        Some(record)
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Record {
+    offset: usize,
+    checksum: Checksum,
+    len: u64,
+    digest: u64,
+}
+
+impl Record {
+    fn parse(metadata: &Metadata) -> Option<Record> {
+        let data = metadata.data();
+
+        if data.len() != RECORD_LEN || !data.starts_with(MAGIC) || data[4] != VERSION {
+            return None;
+        }
+
+        let checksum = match data[5] {
+            1 => Checksum::Crc32c,
+            2 => Checksum::Xxh64,
+            _ => return None,
+        };
+
+        Some(Record {
+            offset: metadata.offset(),
+            checksum,
+            len: u64::from_le_bytes(data[6..14].try_into().unwrap()),
+            digest: u64::from_le_bytes(data[14..22].try_into().unwrap()),
+        })
+    }
+}
+
+/// Verifies the checksums read by the decompressor wrappers.
+///
+/// The data is hashed with the algorithm of the first checksum, which the compressor wrappers
+/// write at the very start of the stream to declare it.
+#[derive(Debug, Clone)]
+pub(crate) struct ChecksumVerifier {
+    hasher: Option<Hasher>,
+    len: u64,
+    verified_len: Option<u64>,
+    records: VecDeque<Record>,
+    metadata: Option<VecDeque<Metadata>>,
+}
+
+impl ChecksumVerifier {
+    /// Creates a verifier for `decoder` and enables capturing of its metadata blocks. Metadata
+    /// blocks other than checksums are retained only if the decoder was already capturing them.
+    pub(crate) fn new(decoder: &mut BrotliDecoder) -> Self {
+        let metadata = decoder.is_capturing_metadata().then(VecDeque::new);
+        decoder.capture_metadata(true);
+
+        ChecksumVerifier {
+            hasher: None,
+            len: 0,
+            verified_len: None,
+            records: VecDeque::new(),
+            metadata,
+        }
+    }
+
+    /// Moves all metadata blocks captured by `decoder` into this verifier.
+    pub(crate) fn drain(&mut self, decoder: &mut BrotliDecoder) {
+        while let Some(metadata) = decoder.pop_metadata() {
+            match Record::parse(&metadata) {
+                Some(record) => self.records.push_back(record),
+                None => {
+                    if let Some(queue) = &mut self.metadata {
+                        queue.push_back(metadata);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Moves all metadata blocks captured by `decoder` into this verifier and returns the oldest
+    /// one that is not a checksum.
+    pub(crate) fn pop_with(&mut self, decoder: &mut BrotliDecoder) -> Option<Metadata> {
+        self.drain(decoder);
+        self.metadata.as_mut()?.pop_front()
+    }
+
+    /// Hashes decompressed `data`, verifying every checksum it reaches. Checksums must be drained
+    /// from the decoder before the data they follow is passed.
+    ///
+    /// Data that is not preceded by a checksum declaring the algorithm cannot be verified, which
+    /// is reported as [`ChecksumError::Missing`].
+    pub(crate) fn update(&mut self, mut data: &[u8]) -> Result<(), ChecksumError> {
+        loop {
+            self.verify_reached()?;
+
+            if data.is_empty() {
+                return Ok(());
+            }
+
+            let len = match self.records.front() {
+                Some(record) => ((record.offset as u64 - self.len) as usize).min(data.len()),
+                None => data.len(),
+            };
+
+            let hasher = self.hasher.as_mut().ok_or(ChecksumError::Missing)?;
+            hasher.update(&data[..len]);
+            self.len += len as u64;
+            data = &data[len..];
+        }
+    }
+
+    /// Verifies that the stream finished with a checksum of all decompressed data.
+    pub(crate) fn finish(&mut self) -> Result<(), ChecksumError> {
+        self.verify_reached()?;
+
+        if !self.records.is_empty() {
+            Err(ChecksumError::Mismatch)
+        } else if self.verified_len != Some(self.len) {
+            Err(ChecksumError::Missing)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn verify_reached(&mut self) -> Result<(), ChecksumError> {
+        while let Some(&record) = self.records.front() {
+            if (record.offset as u64) > self.len {
+                return Ok(());
+            }
+
+            let hasher = self
+                .hasher
+                .get_or_insert_with(|| Hasher::new(record.checksum));
+
+            if record.offset as u64 != self.len
+                || record.len != self.len
+                || record.checksum != hasher.checksum()
+                || record.digest != hasher.digest()
+            {
+                return Err(ChecksumError::Mismatch);
+            }
+
+            self.verified_len = Some(self.len);
+            self.records.pop_front();
+        }
+
+        Ok(())
+    }
+}
@@ -6,15 +6,26 @@
 //! [`Read`]: https://doc.rust-lang.org/stable/std/io/trait.Read.html
 //! [`Write`]: https://doc.rust-lang.org/stable/std/io/trait.Write.html
 
-use std::alloc::GlobalAlloc;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::VecDeque;
 use std::error::Error;
-use std::ffi::CStr;
+use std::ffi::{c_void, CStr};
 use std::io::{BufRead, Read, Write};
+use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
 use std::{fmt, io, ptr, slice};
 
 use brotlic_sys::*;
+#[cfg(feature = "stream")]
+use bytes::Bytes;
 
-use crate::{IntoInnerError, SetParameterError};
+#[cfg(feature = "checksum")]
+use crate::checksum::ChecksumVerifier;
+use crate::header::Header;
+use crate::{
+    AllocError, BuildError, DictionaryType, IntoInnerError, OutputBuffer, SetParameterError,
+};
 
 /// A reference to a brotli decoder.
 ///
@@ -27,6 +38,19 @@ pub struct BrotliDecoder {
     // this field is read read across FFI boundaries
     #[allow(dead_code)]
     alloc: Option<Box<Box<dyn GlobalAlloc>>>,
+
+    // attached dictionaries are referenced by the decoder state across FFI boundaries
+    dictionaries: Vec<Arc<[u8]>>,
+
+    // metadata callbacks write into this queue across FFI boundaries
+    metadata: Option<Box<MetadataQueue>>,
+
+    // checksums are verified by the decompressor wrappers
+    #[cfg(feature = "checksum")]
+    verify_checksums: bool,
+
+    // limits are enforced by the decompressor wrappers
+    limits: Option<Box<Limits>>,
 }
 
 unsafe impl Send for BrotliDecoder {}
@@ -37,28 +61,68 @@ impl BrotliDecoder {
     ///
     /// # Panics
     ///
-    /// Panics if the decoder fails to be allocated or initialized
+    /// Panics if the decoder fails to be allocated or initialized, see [`Self::try_new`] for a
+    /// fallible alternative.
     #[doc(alias = "BrotliDecoderCreateInstance")]
     pub fn new() -> Self {
+        match BrotliDecoder::try_new() {
+            Ok(decoder) => decoder,
+            Err(err) => panic!("BrotliDecoderCreateInstance returned NULL: {}", err),
+        }
+    }
+
+    /// Constructs a new brotli decoder instance using allocator `alloc`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the decoder fails to be allocated or initialized, see [`Self::try_new_in`] for a
+    /// fallible alternative.
+    #[doc(alias = "BrotliDecoderCreateInstance")]
+    pub fn new_in<A>(alloc: A) -> Self
+    where
+        A: GlobalAlloc + 'static,
+    {
+        match BrotliDecoder::try_new_in(alloc) {
+            Ok(decoder) => decoder,
+            Err(err) => panic!("BrotliDecoderCreateInstance returned NULL: {}", err),
+        }
+    }
+
+    /// Attempts to construct a new brotli decoder instance.
+    ///
+    /// # Errors
+    ///
+    /// An [`AllocError`] will be returned if the decoder fails to be allocated or initialized.
+    #[doc(alias = "BrotliDecoderCreateInstance")]
+    pub fn try_new() -> Result<Self, AllocError> {
         let instance = unsafe { BrotliDecoderCreateInstance(None, None, ptr::null_mut()) };
 
         if !instance.is_null() {
-            BrotliDecoder {
+            Ok(BrotliDecoder {
                 state: instance,
                 alloc: None,
-            }
+                dictionaries: Vec::new(),
+                metadata: None,
+                #[cfg(feature = "checksum")]
+                verify_checksums: false,
+                limits: None,
+            })
         } else {
-            panic!("BrotliDecoderCreateInstance returned NULL: failed to allocate or initialize");
+            Err(AllocError)
         }
     }
 
-    /// Constructs a new brotli decoder instance using allocator `alloc`.
+    /// Attempts to construct a new brotli decoder instance using allocator `alloc`.
     ///
-    /// # Panics
+    /// Allocations refused by `alloc`, for example because a memory budget is exhausted, are
+    /// reported as an [`AllocError`] instead of aborting the process. Allocations refused later
+    /// on, while decompressing, are reported as an [`DecodeError`].
     ///
-    /// Panics if the decoder fails to be allocated or initialized
+    /// # Errors
+    ///
+    /// An [`AllocError`] will be returned if the decoder fails to be allocated or initialized.
     #[doc(alias = "BrotliDecoderCreateInstance")]
-    pub fn new_in<A>(alloc: A) -> Self
+    pub fn try_new_in<A>(alloc: A) -> Result<Self, AllocError>
     where
         A: GlobalAlloc + 'static,
     {
@@ -69,12 +133,17 @@ impl BrotliDecoder {
         };
 
         if !instance.is_null() {
-            BrotliDecoder {
+            Ok(BrotliDecoder {
                 state: instance,
                 alloc: Some(alloc),
-            }
+                dictionaries: Vec::new(),
+                metadata: None,
+                #[cfg(feature = "checksum")]
+                verify_checksums: false,
+                limits: None,
+            })
         } else {
-            panic!("BrotliDecoderCreateInstance returned NULL: failed to allocate or initialize");
+            Err(AllocError)
         }
     }
 
@@ -102,12 +171,71 @@ impl BrotliDecoder {
         &mut self,
         input: &[u8],
         output: &mut [u8],
+    ) -> Result<DecodeResult, DecodeError> {
+        // SAFETY: the decoder only ever writes initialized bytes to `output`
+        let output = unsafe { &mut *(output as *mut [u8] as *mut [MaybeUninit<u8>]) };
+
+        self.decompress_uninit(input, output)
+    }
+
+    /// Decompresses the input stream into the spare capacity of `output`.
+    ///
+    /// This works like [`Self::decompress`], except that the decompressed bytes are appended to
+    /// `output` without initializing its spare capacity first. At most `output.capacity() -
+    /// output.len()` bytes are written, so [`Vec::reserve`] should be used to make room for more
+    /// output.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use brotlic::BrotliDecoder;
+    /// use brotlic::decode::DecoderInfo;
+    ///
+    /// let compressed = brotlic::compress_to_vec(
+    ///     b"hello world",
+    ///     brotlic::Quality::default(),
+    ///     brotlic::WindowSize::default(),
+    ///     brotlic::CompressionMode::Text,
+    /// )?;
+    ///
+    /// let mut decoder = BrotliDecoder::new();
+    /// let mut output = Vec::with_capacity(64);
+    /// let res = decoder.decompress_into_vec(&compressed, &mut output)?;
+    ///
+    /// assert_eq!(res.info, DecoderInfo::Finished);
+    /// assert_eq!(output, b"hello world");
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    #[doc(alias = "BrotliDecoderDecompressStream")]
+    pub fn decompress_into_vec(
+        &mut self,
+        input: &[u8],
+        output: &mut Vec<u8>,
+    ) -> Result<DecodeResult, DecodeError> {
+        let len = output.len();
+        let res = self.decompress_uninit(input, output.spare_capacity_mut())?;
+
+        // SAFETY: the decoder initialized the first `bytes_written` bytes of the spare capacity
+        unsafe { output.set_len(len + res.bytes_written) };
+
+        Ok(res)
+    }
+
+    pub(crate) fn decompress_uninit(
+        &mut self,
+        input: &[u8],
+        output: &mut [MaybeUninit<u8>],
     ) -> Result<DecodeResult, DecodeError> {
         let mut input_ptr = input.as_ptr();
         let mut input_len = input.len();
-        let mut output_ptr = output.as_mut_ptr();
+        let mut output_ptr = output.as_mut_ptr() as *mut u8;
         let mut output_len = output.len();
 
+        // allocations refused by an earlier operation must not be reported for this one
+        if let Some(memory) = self.memory_limit() {
+            memory.exceeded.store(false, Ordering::Relaxed);
+        }
+
         let result = unsafe {
             BrotliDecoderDecompressStream(
                 self.state,
@@ -149,6 +277,30 @@ impl BrotliDecoder {
         Ok((res.bytes_read, res.info))
     }
 
+    pub(crate) fn output_limits(&self) -> OutputLimits {
+        self.limits
+            .as_ref()
+            .map(|limits| limits.output)
+            .unwrap_or_default()
+    }
+
+    /// Converts `err` into an [`io::Error`], reporting allocations refused due to the memory limit
+    /// during the last operation as [`LimitError::Memory`].
+    pub(crate) fn map_error(&self, err: DecodeError) -> io::Error {
+        match self.memory_limit() {
+            Some(memory) if memory.exceeded.load(Ordering::Relaxed) => LimitError::Memory.into(),
+            _ => err.into(),
+        }
+    }
+
+    fn memory_limit(&self) -> Option<&MemoryLimit> {
+        self.limits.as_ref()?.memory.as_deref()
+    }
+
+    fn limits_mut(&mut self) -> &mut Limits {
+        self.limits.get_or_insert_with(Box::default)
+    }
+
     /// Checks if the decoder has more output.
     #[doc(alias = "BrotliDecoderHasMoreOutput")]
     pub fn has_output(&self) -> bool {
@@ -161,6 +313,8 @@ impl BrotliDecoder {
     /// that this function can return `Some(&[u8])` multiple times. Only when the method returns
     /// `None` is when there is no more output available by the decoder.
     ///
+    /// For a safe alternative, see [`Self::next_output`] and [`Self::drain_output`].
+    ///
     /// # Safety
     ///
     /// For every consecutive call of this function, the previous slice becomes invalidated.
@@ -176,6 +330,155 @@ impl BrotliDecoder {
         }
     }
 
+    /// Checks if the decoder has more output and if so, returns a slice to its internal output
+    /// buffer.
+    ///
+    /// This is the safe counterpart of [`Self::take_output`]. The slice mutably borrows the
+    /// decoder, so the borrow checker ensures it is no longer used once the decoder is used again.
+    /// Like with `take_output`, each byte returned is considered "consumed" and output may be
+    /// returned in multiple chunks, until this method returns `None`.
+    #[doc(alias = "BrotliDecoderTakeOutput")]
+    pub fn next_output(&mut self) -> Option<&[u8]> {
+        // SAFETY: the slice borrows the decoder, so it cannot outlive the next call
+        unsafe { self.take_output() }
+    }
+
+    /// Passes all output of the decoder to `f`, one chunk at a time, without copying it.
+    ///
+    /// Output is drained until the decoder has no more output, or until `f` returns an error,
+    /// which is returned. Bytes passed to `f` are considered "consumed", even if it fails.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use brotlic::decode::{BrotliDecoder, DecodeError};
+    /// use brotlic::BrotliEncoderOptions;
+    ///
+    /// let compressed = BrotliEncoderOptions::new().compress_to_vec(b"hello world")?;
+    /// let mut input = compressed.as_slice();
+    /// let mut decoder = BrotliDecoder::new();
+    /// let mut decompressed = Vec::new();
+    ///
+    /// while !decoder.is_finished() {
+    ///     let (bytes_read, _) = decoder.give_input(input)?;
+    ///     input = &input[bytes_read..];
+    ///
+    ///     decoder.drain_output(|chunk| {
+    ///         decompressed.extend_from_slice(chunk);
+    ///         Ok::<(), DecodeError>(())
+    ///     })?;
+    /// }
+    ///
+    /// assert_eq!(decompressed, b"hello world");
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn drain_output<E, F>(&mut self, mut f: F) -> Result<(), E>
+    where
+        F: FnMut(&[u8]) -> Result<(), E>,
+    {
+        while let Some(output) = self.next_output() {
+            f(output)?;
+        }
+
+        Ok(())
+    }
+
+    /// Attaches a custom dictionary of type `dictionary_type` to the decoder.
+    ///
+    /// The decoder keeps a reference to `data` until it is dropped. Streams compressed using a
+    /// custom dictionary can only be decoded by attaching the very same dictionary. Dictionaries
+    /// must be attached before any input is processed. Usually it is more convenient to use
+    /// [`BrotliDecoderOptions::dictionary`] instead.
+    ///
+    /// # Errors
+    ///
+    /// An [`Err`] will be returned if the dictionary is corrupted, too many dictionaries were
+    /// attached or decoding already started.
+    #[doc(alias = "BrotliDecoderAttachDictionary")]
+    pub fn attach_dictionary<D>(
+        &mut self,
+        dictionary_type: DictionaryType,
+        data: D,
+    ) -> Result<(), SetParameterError>
+    where
+        D: Into<Arc<[u8]>>,
+    {
+        let data = data.into();
+        let r = unsafe {
+            BrotliDecoderAttachDictionary(
+                self.state,
+                dictionary_type as BrotliSharedDictionaryType,
+                data.len(),
+                data.as_ptr(),
+            )
+        };
+
+        if r != 0 {
+            self.dictionaries.push(data);
+            Ok(())
+        } else {
+            Err(SetParameterError::InvalidDictionary)
+        }
+    }
+
+    /// Enables or disables capturing of metadata blocks.
+    ///
+    /// By default, the decoder silently skips metadata blocks. Once enabled, every metadata block
+    /// that is decoded is queued as a [`Metadata`] event that can be retrieved with
+    /// [`Self::pop_metadata`]. Empty metadata blocks are never captured, as they are also used as
+    /// padding whenever the stream is flushed. Disabling capturing discards any queued events.
+    #[doc(alias = "BrotliDecoderSetMetadataCallbacks")]
+    pub fn capture_metadata(&mut self, capture: bool) {
+        if capture == self.metadata.is_some() {
+            return;
+        }
+
+        if capture {
+            let mut queue = Box::new(MetadataQueue {
+                state: self.state,
+                partial: None,
+                remaining: 0,
+                events: VecDeque::new(),
+            });
+            let queue_ptr: *mut MetadataQueue = queue.as_mut();
+
+            unsafe {
+                BrotliDecoderSetMetadataCallbacks(
+                    self.state,
+                    Some(metadata_start),
+                    Some(metadata_chunk),
+                    queue_ptr as _,
+                );
+            }
+
+            self.metadata = Some(queue);
+        } else {
+            unsafe {
+                BrotliDecoderSetMetadataCallbacks(self.state, None, None, ptr::null_mut());
+            }
+
+            self.metadata = None;
+        }
+    }
+
+    /// Removes the oldest captured metadata block from the queue and returns it, or [`None`] if
+    /// the queue is empty.
+    ///
+    /// Metadata blocks are only captured if enabled by [`Self::capture_metadata`]. A metadata block
+    /// is queued as soon as it has been fully decoded, which may happen before all decompressed
+    /// output preceding it has been taken from the decoder.
+    pub fn pop_metadata(&mut self) -> Option<Metadata> {
+        self.metadata.as_mut()?.events.pop_front()
+    }
+
+    pub(crate) fn is_capturing_metadata(&self) -> bool {
+        self.metadata.is_some()
+    }
+
+    pub(crate) fn peek_metadata(&self) -> Option<&Metadata> {
+        self.metadata.as_ref()?.events.front()
+    }
+
     /// Returns the version of the C brotli decoder library.
     #[doc(alias = "BrotliDecoderVersion")]
     pub fn version() -> u32 {
@@ -304,6 +607,72 @@ impl Drop for BrotliDecoder {
     }
 }
 
+/// A metadata block captured by a [`BrotliDecoder`].
+///
+/// Metadata blocks carry data within the compressed stream that is not part of the decompressed
+/// output, such as the blocks emitted by [`CompressorWriter::write_metadata`].
+///
+/// [`CompressorWriter::write_metadata`]: crate::encode::CompressorWriter::write_metadata
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Metadata {
+    offset: usize,
+    data: Vec<u8>,
+}
+
+impl Metadata {
+    /// Returns the offset of this metadata block in the decompressed output. This is the number of
+    /// decompressed bytes that precede the metadata block in the stream.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Returns the contents of this metadata block.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Unwraps this `Metadata`, returning the contents of the metadata block.
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+struct MetadataQueue {
+    state: *const BrotliDecoderState,
+    partial: Option<Metadata>,
+    remaining: usize,
+    events: VecDeque<Metadata>,
+}
+
+extern "C" fn metadata_start(opaque: *mut c_void, size: usize) {
+    let queue = unsafe { &mut *(opaque as *mut MetadataQueue) };
+
+    if size > 0 {
+        let offset = unsafe { BrotlicDecoderGetDecodedSize(queue.state) };
+
+        queue.partial = Some(Metadata {
+            offset,
+            data: Vec::with_capacity(size),
+        });
+        queue.remaining = size;
+    }
+}
+
+extern "C" fn metadata_chunk(opaque: *mut c_void, data: *const u8, size: usize) {
+    let queue = unsafe { &mut *(opaque as *mut MetadataQueue) };
+
+    if let Some(partial) = &mut queue.partial {
+        let chunk = unsafe { slice::from_raw_parts(data, size) };
+
+        partial.data.extend_from_slice(chunk);
+        queue.remaining -= size;
+
+        if queue.remaining == 0 {
+            queue.events.extend(queue.partial.take());
+        }
+    }
+}
+
 /// Decompression options to be used for a [`BrotliDecoder`].
 ///
 /// # Examples
@@ -320,6 +689,13 @@ impl Drop for BrotliDecoder {
 pub struct BrotliDecoderOptions {
     disable_ring_buffer_reallocation: Option<bool>,
     large_window_size: Option<bool>,
+    dictionaries: Vec<(DictionaryType, Arc<[u8]>)>,
+    capture_metadata: bool,
+    #[cfg(feature = "checksum")]
+    verify_checksums: bool,
+    max_output_size: Option<u64>,
+    max_ratio: Option<u64>,
+    max_memory: Option<usize>,
 }
 
 impl BrotliDecoderOptions {
@@ -331,6 +707,13 @@ impl BrotliDecoderOptions {
         BrotliDecoderOptions {
             disable_ring_buffer_reallocation: None,
             large_window_size: None,
+            dictionaries: Vec::new(),
+            capture_metadata: false,
+            #[cfg(feature = "checksum")]
+            verify_checksums: false,
+            max_output_size: None,
+            max_ratio: None,
+            max_memory: None,
         }
     }
 
@@ -356,6 +739,167 @@ impl BrotliDecoderOptions {
         self
     }
 
+    /// Attaches a custom dictionary of type `dictionary_type` to the decoder.
+    ///
+    /// This is required to decode streams that were compressed using a custom dictionary, such as
+    /// a [`PreparedDictionary`]. The dictionary attached must be the same one that was used for
+    /// compression. This can be called multiple times to attach several dictionaries. The `data` is
+    /// shared, not copied, by every decoder built from these options.
+    ///
+    /// Decoding a stream without its dictionary, or with a different one, typically fails with
+    /// [`DecodeError::FormatDictionary`], [`DecodeError::FormatDistance`] or
+    /// [`DecodeError::CompoundDictionary`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::{Read, Write};
+    /// use std::sync::Arc;
+    /// use brotlic::encode::PreparedDictionary;
+    /// use brotlic::{BrotliDecoderOptions, BrotliEncoderOptions, DictionaryType, Quality};
+    /// use brotlic::{CompressorWriter, DecompressorReader};
+    ///
+    /// let dictionary: Arc<[u8]> = Arc::from(b"<!DOCTYPE html><html><head>".as_slice());
+    /// let prepared =
+    ///     PreparedDictionary::new(DictionaryType::Raw, dictionary.clone(), Quality::best())?;
+    ///
+    /// let encoder = BrotliEncoderOptions::new()
+    ///     .prepared_dictionary(Arc::new(prepared))
+    ///     .build()?;
+    /// let mut compressor = CompressorWriter::with_encoder(encoder, Vec::new());
+    /// compressor.write_all(b"<!DOCTYPE html><html><head><title>brotlic</title>")?;
+    /// let compressed = compressor.into_inner()?;
+    ///
+    /// let decoder = BrotliDecoderOptions::new()
+    ///     .dictionary(DictionaryType::Raw, dictionary)
+    ///     .build()?;
+    /// let mut decompressor = DecompressorReader::with_decoder(decoder, compressed.as_slice());
+    /// let mut decompressed = String::new();
+    /// decompressor.read_to_string(&mut decompressed)?;
+    ///
+    /// assert_eq!(decompressed, "<!DOCTYPE html><html><head><title>brotlic</title>");
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    ///
+    /// [`PreparedDictionary`]: crate::encode::PreparedDictionary
+    pub fn dictionary<D>(&mut self, dictionary_type: DictionaryType, data: D) -> &mut Self
+    where
+        D: Into<Arc<[u8]>>,
+    {
+        self.dictionaries.push((dictionary_type, data.into()));
+        self
+    }
+
+    /// Flag that determines if the decoder captures metadata blocks. By default, this is turned
+    /// off and metadata blocks are skipped. For more information see
+    /// [`BrotliDecoder::capture_metadata`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::{Read, Write};
+    /// use brotlic::{BrotliDecoderOptions, CompressorWriter, DecompressorReader};
+    ///
+    /// let mut compressor = CompressorWriter::new(Vec::new());
+    /// compressor.write_all(b"hello")?;
+    /// compressor.write_metadata(b"marker")?;
+    /// compressor.write_all(b" world")?;
+    /// let compressed = compressor.into_inner()?;
+    ///
+    /// let decoder = BrotliDecoderOptions::new().capture_metadata(true).build()?;
+    /// let mut decompressor = DecompressorReader::with_decoder(decoder, compressed.as_slice());
+    /// decompressor.read_to_end(&mut Vec::new())?;
+    ///
+    /// let metadata = decompressor.pop_metadata().unwrap();
+    /// assert_eq!(metadata.offset(), 5);
+    /// assert_eq!(metadata.data(), b"marker");
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn capture_metadata(&mut self, capture_metadata: bool) -> &mut Self {
+        self.capture_metadata = capture_metadata;
+        self
+    }
+
+    /// Flag that determines if checksums written by [`BrotliEncoderOptions::checksum`] are
+    /// verified. By default, this is turned off and checksums are skipped like any other metadata.
+    ///
+    /// Checksums are verified by [`DecompressorReader`] and [`DecompressorWriter`], they are not
+    /// verified when using the [`BrotliDecoder`] directly. Once enabled, a stream that finishes
+    /// without a checksum of all of its data is rejected with [`ChecksumError::Missing`] and a
+    /// checksum that does not match the decompressed data is rejected with
+    /// [`ChecksumError::Mismatch`]. Both are returned as an [`io::Error`] of kind
+    /// [`io::ErrorKind::InvalidData`]. For more information see [`crate::checksum`].
+    ///
+    /// This method requires the `checksum` feature.
+    ///
+    /// [`BrotliEncoderOptions::checksum`]: crate::encode::BrotliEncoderOptions::checksum
+    /// [`ChecksumError::Missing`]: crate::checksum::ChecksumError::Missing
+    /// [`ChecksumError::Mismatch`]: crate::checksum::ChecksumError::Mismatch
+    #[cfg(feature = "checksum")]
+    pub fn verify_checksums(&mut self, verify_checksums: bool) -> &mut Self {
+        self.verify_checksums = verify_checksums;
+        self
+    }
+
+    /// Sets the maximum number of bytes the decompression stream may decompress to.
+    ///
+    /// Brotli can reach compression ratios beyond 1:1,000,000, so a small input can decompress to
+    /// an enormous output. The limit is checked incrementally by [`DecompressorReader`] and
+    /// [`DecompressorWriter`], which fail with [`LimitError::OutputSize`] as soon as the total
+    /// output exceeds `max_output_size` bytes. It is not enforced when using the [`BrotliDecoder`]
+    /// directly.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::{self, Read, Write};
+    /// use brotlic::{BrotliDecoderOptions, CompressorWriter, DecompressorReader};
+    ///
+    /// let mut compressor = CompressorWriter::new(Vec::new());
+    /// compressor.write_all(&[0; 1 << 20])?;
+    /// let compressed = compressor.into_inner()?;
+    ///
+    /// let decoder = BrotliDecoderOptions::new().max_output_size(1 << 16).build()?;
+    /// let mut decompressor = DecompressorReader::with_decoder(decoder, compressed.as_slice());
+    /// let err = decompressor.read_to_end(&mut Vec::new()).unwrap_err();
+    ///
+    /// assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn max_output_size(&mut self, max_output_size: u64) -> &mut Self {
+        self.max_output_size = Some(max_output_size);
+        self
+    }
+
+    /// Sets the maximum ratio of decompressed to compressed bytes.
+    ///
+    /// The ratio is checked incrementally by [`DecompressorReader`] and [`DecompressorWriter`]
+    /// against the compressed bytes consumed so far. They fail with [`LimitError::Ratio`] as soon
+    /// as the total output exceeds `max_ratio` times the total input. It is not enforced when
+    /// using the [`BrotliDecoder`] directly.
+    pub fn max_ratio(&mut self, max_ratio: u64) -> &mut Self {
+        self.max_ratio = Some(max_ratio);
+        self
+    }
+
+    /// Sets the maximum number of bytes the decoder may have allocated at any time.
+    ///
+    /// The limit applies to the sum of all live allocations rather than to each allocation. This
+    /// includes the decoder state itself, so the limit should leave some headroom: decoding
+    /// requires a ring buffer of up to the window size of the stream, plus some tables. Once an
+    /// allocation would exceed it, the allocation is refused and decompression fails.
+    /// [`DecompressorReader`] and [`DecompressorWriter`] report this as [`LimitError::Memory`],
+    /// while the [`BrotliDecoder`] reports it as one of its allocation errors.
+    ///
+    /// # Panics
+    ///
+    /// Building the decoder panics if the decoder state alone exceeds `max_memory`. Use
+    /// [`Self::try_build`] to handle this as an error instead.
+    pub fn max_memory(&mut self, max_memory: usize) -> &mut Self {
+        self.max_memory = Some(max_memory);
+        self
+    }
+
     /// Creates a brotli decoder using the specified settings.
     ///
     /// # Errors
@@ -363,30 +907,81 @@ impl BrotliDecoderOptions {
     /// If any of the preconditions of the parameters are violated, an error is returned.
     #[doc(alias = "BrotliDecoderSetParameter")]
     pub fn build(&self) -> Result<BrotliDecoder, SetParameterError> {
-        let mut decoder = BrotliDecoder::new();
+        unwrap_alloc(self.try_build())
+    }
+
+    /// Creates a brotli decoder with the specified settings using allocator `alloc`.
+    ///
+    /// # Errors
+    ///
+    /// If any of the preconditions of the parameters are violated, an error is returned.
+    #[doc(alias = "BrotliDecoderSetParameter")]
+    pub fn build_in<A>(&self, alloc: A) -> Result<BrotliDecoder, SetParameterError>
+    where
+        A: GlobalAlloc + 'static,
+    {
+        unwrap_alloc(self.try_build_in(alloc))
+    }
+
+    /// Attempts to create a brotli decoder using the specified settings.
+    ///
+    /// Unlike [`Self::build`], this does not panic if the decoder fails to be allocated, which
+    /// includes the decoder state alone exceeding [`Self::max_memory`].
+    ///
+    /// # Errors
+    ///
+    /// An [`Err`] will be returned if the decoder fails to be allocated or initialized, or if any
+    /// of the preconditions of the parameters are violated.
+    #[doc(alias = "BrotliDecoderSetParameter")]
+    pub fn try_build(&self) -> Result<BrotliDecoder, BuildError> {
+        let mut decoder = match self.max_memory {
+            Some(max_memory) => self.new_limited(System, max_memory)?,
+            None => BrotliDecoder::try_new()?,
+        };
 
         self.configure(&mut decoder)?;
 
         Ok(decoder)
     }
 
-    /// Creates a brotli decoder with the specified settings using allocator `alloc`.
+    /// Attempts to create a brotli decoder with the specified settings using allocator `alloc`.
+    ///
+    /// Unlike [`Self::build_in`], this does not panic if the decoder fails to be allocated, which
+    /// includes the decoder state alone exceeding [`Self::max_memory`].
     ///
     /// # Errors
     ///
-    /// If any of the preconditions of the parameters are violated, an error is returned.
+    /// An [`Err`] will be returned if the decoder fails to be allocated or initialized, or if any
+    /// of the preconditions of the parameters are violated.
     #[doc(alias = "BrotliDecoderSetParameter")]
-    pub fn build_in<A>(&self, alloc: A) -> Result<BrotliDecoder, SetParameterError>
+    pub fn try_build_in<A>(&self, alloc: A) -> Result<BrotliDecoder, BuildError>
     where
         A: GlobalAlloc + 'static,
     {
-        let mut decoder = BrotliDecoder::new_in(alloc);
+        let mut decoder = match self.max_memory {
+            Some(max_memory) => self.new_limited(alloc, max_memory)?,
+            None => BrotliDecoder::try_new_in(alloc)?,
+        };
 
         self.configure(&mut decoder)?;
 
         Ok(decoder)
     }
 
+    fn new_limited<A>(&self, alloc: A, max_memory: usize) -> Result<BrotliDecoder, AllocError>
+    where
+        A: GlobalAlloc + 'static,
+    {
+        let memory = Arc::new(MemoryLimit::new(max_memory));
+        let mut decoder = BrotliDecoder::try_new_in(LimitedAlloc {
+            inner: alloc,
+            memory: memory.clone(),
+        })?;
+
+        decoder.limits_mut().memory = Some(memory);
+        Ok(decoder)
+    }
+
     fn configure(&self, decoder: &mut BrotliDecoder) -> Result<(), SetParameterError> {
         if let Some(disable_ring_buffer_reallocation) = self.disable_ring_buffer_reallocation {
             let key = BrotliDecoderParameter_BROTLI_DECODER_PARAM_DISABLE_RING_BUFFER_REALLOCATION;
@@ -402,10 +997,37 @@ impl BrotliDecoderOptions {
             decoder.set_param(key, value)?;
         }
 
+        for (dictionary_type, data) in &self.dictionaries {
+            decoder.attach_dictionary(*dictionary_type, data.clone())?;
+        }
+
+        decoder.capture_metadata(self.capture_metadata);
+        #[cfg(feature = "checksum")]
+        {
+            decoder.verify_checksums = self.verify_checksums;
+        }
+        if self.max_output_size.is_some() || self.max_ratio.is_some() {
+            let limits = decoder.limits_mut();
+
+            limits.output.max_output_size = self.max_output_size;
+            limits.output.max_ratio = self.max_ratio;
+        }
+
         Ok(())
     }
 }
 
+/// Unwraps the result of a fallible build, panicking if the decoder failed to be allocated.
+fn unwrap_alloc(
+    res: Result<BrotliDecoder, BuildError>,
+) -> Result<BrotliDecoder, SetParameterError> {
+    match res {
+        Ok(decoder) => Ok(decoder),
+        Err(BuildError::SetParameter(err)) => Err(err),
+        Err(BuildError::Alloc(err)) => panic!("BrotliDecoderCreateInstance returned NULL: {}", err),
+    }
+}
+
 impl Default for BrotliDecoderOptions {
     fn default() -> Self {
         BrotliDecoderOptions::new()
@@ -461,7 +1083,17 @@ pub enum DecodeError {
     FormatPadding1 = BrotliDecoderErrorCode_BROTLI_DECODER_ERROR_FORMAT_PADDING_1 as isize,
     FormatPadding2 = BrotliDecoderErrorCode_BROTLI_DECODER_ERROR_FORMAT_PADDING_2 as isize,
     FormatDistance = BrotliDecoderErrorCode_BROTLI_DECODER_ERROR_FORMAT_DISTANCE as isize,
+    /// The stream references data of an attached custom dictionary that could not be copied.
+    ///
+    /// This usually means that the stream was compressed using a different dictionary than the
+    /// one attached via [`BrotliDecoderOptions::dictionary`].
     CompoundDictionary = BrotliDecoderErrorCode_BROTLI_DECODER_ERROR_COMPOUND_DICTIONARY as isize,
+    /// The stream references dictionary words, but the decoder has no word dictionary available.
+    ///
+    /// This only happens when the built-in dictionary of brotli is compiled without its data,
+    /// which brotlic never does. A stream compressed with a custom dictionary that is decoded
+    /// without attaching it via [`BrotliDecoderOptions::dictionary`] fails with
+    /// [`DecodeError::FormatDictionary`] or [`DecodeError::FormatDistance`] instead.
     DictionaryNotSet = BrotliDecoderErrorCode_BROTLI_DECODER_ERROR_DICTIONARY_NOT_SET as isize,
     InvalidArguments = BrotliDecoderErrorCode_BROTLI_DECODER_ERROR_INVALID_ARGUMENTS as isize,
     AllocContextModes = BrotliDecoderErrorCode_BROTLI_DECODER_ERROR_ALLOC_CONTEXT_MODES as isize,
@@ -480,6 +1112,12 @@ impl fmt::Display for DecodeError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         if *self == DecodeError::UnknownError {
             write!(f, "decode error: unknown error")
+        } else if *self == DecodeError::CompoundDictionary {
+            write!(
+                f,
+                "brotli decoder error: invalid reference into an attached dictionary, the stream \
+                was likely compressed with a different dictionary"
+            )
         } else {
             let str = unsafe {
                 let error_code = *self as BrotliDecoderErrorCode;
@@ -497,13 +1135,134 @@ impl fmt::Display for DecodeError {
 
 impl From<DecodeError> for io::Error {
     fn from(err: DecodeError) -> Self {
-        io::Error::new(io::ErrorKind::Other, err)
+        io::Error::other(err)
     }
 }
 
-/// Wraps a reader and decompresses its output.
-///
-/// # Examples
+/// An error returned if a limit set in [`BrotliDecoderOptions`] was exceeded.
+///
+/// This error is wrapped in an [`io::Error`] of kind [`io::ErrorKind::InvalidData`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum LimitError {
+    /// The decompressed output exceeded [`BrotliDecoderOptions::max_output_size`].
+    OutputSize,
+
+    /// The ratio of decompressed to compressed bytes exceeded
+    /// [`BrotliDecoderOptions::max_ratio`].
+    Ratio,
+
+    /// An allocation would have brought the memory the decoder has allocated in total above
+    /// [`BrotliDecoderOptions::max_memory`].
+    Memory,
+}
+
+impl Error for LimitError {}
+
+impl fmt::Display for LimitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LimitError::OutputSize => f.write_str("decompressed output size limit exceeded"),
+            LimitError::Ratio => f.write_str("decompression ratio limit exceeded"),
+            LimitError::Memory => f.write_str("decoder memory limit exceeded"),
+        }
+    }
+}
+
+impl From<LimitError> for io::Error {
+    fn from(err: LimitError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
+
+/// Limits of a decoder, enforced by the decompressor wrappers.
+#[derive(Debug, Clone, Default)]
+struct Limits {
+    output: OutputLimits,
+    memory: Option<Arc<MemoryLimit>>,
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+pub(crate) struct OutputLimits {
+    max_output_size: Option<u64>,
+    max_ratio: Option<u64>,
+}
+
+impl OutputLimits {
+    pub(crate) fn check(&self, total_in: u64, total_out: u64) -> Result<(), LimitError> {
+        if self.max_output_size.is_some_and(|max| total_out > max) {
+            return Err(LimitError::OutputSize);
+        }
+
+        if self
+            .max_ratio
+            .is_some_and(|max| total_out > total_in.saturating_mul(max))
+        {
+            return Err(LimitError::Ratio);
+        }
+
+        Ok(())
+    }
+}
+
+/// Tracks the memory allocated by a decoder.
+#[derive(Debug)]
+struct MemoryLimit {
+    max: usize,
+    allocated: AtomicUsize,
+    exceeded: AtomicBool,
+}
+
+impl MemoryLimit {
+    fn new(max: usize) -> Self {
+        MemoryLimit {
+            max,
+            allocated: AtomicUsize::new(0),
+            exceeded: AtomicBool::new(false),
+        }
+    }
+}
+
+/// An allocator that refuses allocations beyond its memory limit.
+struct LimitedAlloc<A> {
+    inner: A,
+    memory: Arc<MemoryLimit>,
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for LimitedAlloc<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let size = layout.size();
+        let res = self
+            .memory
+            .allocated
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |allocated| {
+                allocated
+                    .checked_add(size)
+                    .filter(|&allocated| allocated <= self.memory.max)
+            });
+
+        if res.is_err() {
+            self.memory.exceeded.store(true, Ordering::Relaxed);
+            return ptr::null_mut();
+        }
+
+        let ptr = self.inner.alloc(layout);
+
+        if ptr.is_null() {
+            self.memory.allocated.fetch_sub(size, Ordering::Relaxed);
+        }
+
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.inner.dealloc(ptr, layout);
+        self.memory.allocated.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
+
+/// Wraps a reader and decompresses its output.
+///
+/// # Examples
 ///
 /// Suppose the file `test.brotli` contains brotli compressed data. Let's try to decompress it:
 ///
@@ -524,7 +1283,9 @@ impl From<DecodeError> for io::Error {
 #[derive(Debug)]
 pub struct DecompressorReader<R: BufRead> {
     inner: R,
-    decoder: BrotliDecoder,
+    // boxed, so that errors returning the reader stay small
+    state: Box<ReaderState>,
+    header: Option<Option<Box<Header>>>,
 }
 
 impl<R: BufRead> DecompressorReader<R> {
@@ -532,27 +1293,45 @@ impl<R: BufRead> DecompressorReader<R> {
     ///
     /// # Panics
     ///
-    /// Panics if the decoder fails to be allocated or initialized
+    /// Panics if the decoder fails to be allocated or initialized, see [`Self::try_new`] for a
+    /// fallible alternative.
     pub fn new(inner: R) -> Self {
-        DecompressorReader {
-            inner,
-            decoder: BrotliDecoder::new(),
-        }
+        DecompressorReader::with_decoder(BrotliDecoder::new(), inner)
     }
 
     /// Creates a new `DecompressorReader<R>` with a newly created decoder using allocator `alloc`.
     ///
     /// # Panics
     ///
-    /// Panics if the decoder fails to be allocated or initialized
+    /// Panics if the decoder fails to be allocated or initialized, see [`Self::try_new_in`] for
+    /// a fallible alternative.
     pub fn new_in<A>(inner: R, alloc: A) -> Self
     where
         A: GlobalAlloc + 'static,
     {
-        DecompressorReader {
-            inner,
-            decoder: BrotliDecoder::new_in(alloc),
-        }
+        DecompressorReader::with_decoder(BrotliDecoder::new_in(alloc), inner)
+    }
+
+    /// Attempts to create a new `DecompressorReader<R>` with a newly created decoder.
+    ///
+    /// # Errors
+    ///
+    /// An [`AllocError`] will be returned if the decoder fails to be allocated or initialized.
+    pub fn try_new(inner: R) -> Result<Self, AllocError> {
+        Ok(DecompressorReader::with_decoder(BrotliDecoder::try_new()?, inner))
+    }
+
+    /// Attempts to create a new `DecompressorReader<R>` with a newly created decoder using
+    /// allocator `alloc`.
+    ///
+    /// # Errors
+    ///
+    /// An [`AllocError`] will be returned if the decoder fails to be allocated or initialized.
+    pub fn try_new_in<A>(inner: R, alloc: A) -> Result<Self, AllocError>
+    where
+        A: GlobalAlloc + 'static,
+    {
+        Ok(DecompressorReader::with_decoder(BrotliDecoder::try_new_in(alloc)?, inner))
     }
 
     /// Creates a new `DecompressorReader<R>` with a specified decoder.
@@ -572,7 +1351,11 @@ impl<R: BufRead> DecompressorReader<R> {
     /// # Ok::<(), brotlic::SetParameterError>(())
     /// ```
     pub fn with_decoder(decoder: BrotliDecoder, inner: R) -> Self {
-        DecompressorReader { inner, decoder }
+        DecompressorReader {
+            inner,
+            state: Box::new(ReaderState::new(decoder)),
+            header: None,
+        }
     }
 
     /// Gets a reference to the underlying reader
@@ -589,11 +1372,14 @@ impl<R: BufRead> DecompressorReader<R> {
 
     /// Unwraps this `DecompressorReader<R>`, returning the underlying reader.
     ///
+    /// The underlying reader is positioned right after the decompression stream, so that any
+    /// trailing data can be read from it.
+    ///
     /// # Errors
     ///
     /// An [`Err`] will be returned if the decompression stream has not been finished.
     pub fn into_inner(self) -> Result<R, IntoInnerError<DecompressorReader<R>>> {
-        if self.decoder.is_finished() {
+        if self.state.decoder().is_finished() {
             Ok(self.inner)
         } else {
             Err(IntoInnerError::new(
@@ -603,43 +1389,787 @@ impl<R: BufRead> DecompressorReader<R> {
         }
     }
 
+    /// Removes the oldest captured metadata block from the queue and returns it. For more
+    /// information see [`BrotliDecoder::pop_metadata`].
+    pub fn pop_metadata(&mut self) -> Option<Metadata> {
+        self.state.pop_metadata()
+    }
+
+    /// Returns the number of bytes consumed from the underlying reader.
+    ///
+    /// The decoder never consumes input past the end of the stream. Once the stream is finished,
+    /// this is the length of the compressed stream, and the underlying reader is positioned right
+    /// after it. Any trailing data, such as another stream or the rest of a container format, can
+    /// then be read from [`Self::get_mut`] or [`Self::into_inner`].
+    pub fn bytes_consumed(&self) -> u64 {
+        self.state.total_in()
+    }
+
+    /// Reads and parses the [`Header`] of the stream, as written by [`HeaderBuilder`].
+    ///
+    /// The header is stored in the first metadata block of the stream. This reads just enough
+    /// input to determine if the stream starts with a header, no decompressed data is lost in the
+    /// process. This must be called before any data is read, otherwise `Ok(None)` is returned.
+    /// Subsequent calls return the same header without reading any further input.
+    ///
+    /// # Errors
+    ///
+    /// An [`Err`] of kind [`io::ErrorKind::InvalidData`] will be returned if the header is
+    /// malformed. Any error from the decoder or the underlying reader is returned as well.
+    ///
+    /// [`HeaderBuilder`]: crate::header::HeaderBuilder
+    pub fn header(&mut self) -> io::Result<Option<&Header>> {
+        if self.header.is_none() {
+            let header = self.read_header()?;
+            self.header = Some(header.map(Box::new));
+        }
+
+        Ok(self.header.as_ref().and_then(Option::as_deref))
+    }
+
+    fn read_header(&mut self) -> io::Result<Option<Header>> {
+        let capturing = self.state.decoder().is_capturing_metadata();
+
+        self.state.decoder_mut().capture_metadata(true);
+        let res = self.decode_first_metadata();
+
+        let metadata = if capturing {
+            self.state.decoder().peek_metadata().cloned()
+        } else {
+            let decoder = self.state.decoder_mut();
+            let metadata = decoder.pop_metadata();
+            decoder.capture_metadata(false);
+            metadata
+        };
+
+        res?;
+
+        match metadata {
+            Some(metadata) if metadata.offset() == 0 => Ok(Header::parse(metadata.data())?),
+            _ => Ok(None),
+        }
+    }
+
+    fn decode_first_metadata(&mut self) -> io::Result<()> {
+        loop {
+            let decoder = self.state.decoder();
+
+            if decoder.peek_metadata().is_some() || decoder.has_output() {
+                return Ok(());
+            }
+
+            let input = self.inner.fill_buf()?;
+            let eof = input.is_empty();
+            let (bytes_read, info) = self.state.give_input(input)?;
+            self.inner.consume(bytes_read);
+
+            match info {
+                DecoderInfo::NeedsMoreInput if eof => {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+                DecoderInfo::NeedsMoreInput => continue,
+                DecoderInfo::NeedsMoreOutput | DecoderInfo::Finished => return Ok(()),
+            }
+        }
+    }
+
     /// Disassembles this `DecompressorReader<R>`, returning the underlying reader and decoder.
     ///
     /// `into_parts` makes no attempt to validate that the decompression stream finished and cannot
     /// fail.
     pub fn into_parts(self) -> (R, BrotliDecoder) {
-        (self.inner, self.decoder)
+        (self.inner, self.state.into_decoder())
     }
 }
 
 impl<R: BufRead> Read for DecompressorReader<R> {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.header.get_or_insert(None);
+
+        let buffer = self.state.buffer();
+
+        if !buffer.is_empty() {
+            let len = buffer.len().min(buf.len());
+            buf[..len].copy_from_slice(&buffer[..len]);
+            self.state.consume(len);
+
+            return Ok(len);
+        }
+
         loop {
             let input = self.inner.fill_buf()?;
-            let eof = input.is_empty();
-            let DecodeResult {
-                bytes_read,
-                bytes_written,
-                info,
-            } = self.decoder.decompress(input, buf)?;
+            let (bytes_read, bytes_written) = self.state.read(input, buf)?;
             self.inner.consume(bytes_read);
 
-            match info {
-                _ if bytes_written > 0 => return Ok(bytes_written),
-                DecoderInfo::Finished => return Ok(0),
-                DecoderInfo::NeedsMoreInput if eof => {
-                    return Err(io::ErrorKind::UnexpectedEof.into());
+            if let Some(bytes_written) = bytes_written {
+                return Ok(bytes_written);
+            }
+        }
+    }
+}
+
+/// Reads decompressed data directly from the internal output buffer of the decoder, without
+/// copying it.
+///
+/// # Examples
+///
+/// ```
+/// use std::io::{BufRead, Write};
+/// use brotlic::{CompressorWriter, DecompressorReader};
+///
+/// let mut compressor = CompressorWriter::new(Vec::new());
+/// compressor.write_all(b"first line\nsecond line\n")?;
+/// let compressed = compressor.into_inner()?;
+///
+/// let decompressor = DecompressorReader::new(compressed.as_slice());
+/// let lines = decompressor.lines().collect::<Result<Vec<_>, _>>()?;
+///
+/// assert_eq!(lines, ["first line", "second line"]);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+impl<R: BufRead> BufRead for DecompressorReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.header.get_or_insert(None);
+
+        loop {
+            let input = self.inner.fill_buf()?;
+            let (bytes_read, filled) = self.state.fill(input)?;
+            self.inner.consume(bytes_read);
+
+            if filled {
+                return Ok(self.state.buffer());
+            }
+        }
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.state.consume(amt);
+    }
+}
+
+/// Wraps a reader and decompresses a series of concatenated compression streams.
+///
+/// A [`DecompressorReader<R>`] stops reading at the end of the first compression stream.
+/// `MultiDecompressorReader<R>` instead continues with the next stream, or member, that directly
+/// follows it, until the underlying reader reaches its end. The decompressed members are read as
+/// one continuous output, much like `MultiGzDecoder` does for gzip in the `flate2` crate.
+///
+/// Every member is decompressed by a fresh decoder built from the same [`BrotliDecoderOptions`],
+/// so limits such as [`BrotliDecoderOptions::max_output_size`] and checksum verification apply
+/// to each member individually.
+///
+/// # Examples
+///
+/// ```
+/// use std::io::{Read, Write};
+/// use brotlic::{CompressorWriter, MultiDecompressorReader};
+///
+/// let mut compressed = Vec::new();
+///
+/// for part in ["hello ", "world"] {
+///     let mut compressor = CompressorWriter::new(Vec::new());
+///     compressor.write_all(part.as_bytes())?;
+///     compressed.extend(compressor.into_inner()?);
+/// }
+///
+/// let mut decompressor = MultiDecompressorReader::new(compressed.as_slice());
+/// let mut output = String::new();
+/// decompressor.read_to_string(&mut output)?;
+///
+/// assert_eq!(output, "hello world");
+/// assert_eq!(decompressor.members(), 2);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug)]
+pub struct MultiDecompressorReader<R: BufRead> {
+    inner: R,
+    options: Box<BrotliDecoderOptions>,
+    state: Box<ReaderState>,
+    members: u64,
+    member_offset: u64,
+}
+
+impl<R: BufRead> MultiDecompressorReader<R> {
+    /// Creates a new `MultiDecompressorReader<R>` decompressing members with default options.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the decoder fails to be allocated or initialized
+    pub fn new(inner: R) -> Self {
+        match MultiDecompressorReader::try_new(inner) {
+            Ok(decompressor) => decompressor,
+            Err(err) => panic!("BrotliDecoderCreateInstance returned NULL: {}", err),
+        }
+    }
+
+    /// Attempts to create a new `MultiDecompressorReader<R>` decompressing members with default
+    /// options.
+    ///
+    /// # Errors
+    ///
+    /// An [`AllocError`] will be returned if the decoder fails to be allocated or initialized.
+    pub fn try_new(inner: R) -> Result<Self, AllocError> {
+        Ok(MultiDecompressorReader {
+            inner,
+            options: Box::new(BrotliDecoderOptions::new()),
+            state: Box::new(ReaderState::new(BrotliDecoder::try_new()?)),
+            members: 0,
+            member_offset: 0,
+        })
+    }
+
+    /// Creates a new `MultiDecompressorReader<R>` decompressing each member with a decoder built
+    /// from `options`.
+    ///
+    /// # Errors
+    ///
+    /// An [`Err`] will be returned if the decoder options are invalid or the decoder fails to be
+    /// allocated or initialized.
+    pub fn with_options(options: &BrotliDecoderOptions, inner: R) -> Result<Self, BuildError> {
+        Ok(MultiDecompressorReader {
+            inner,
+            options: Box::new(options.clone()),
+            state: Box::new(ReaderState::new(options.try_build()?)),
+            members: 0,
+            member_offset: 0,
+        })
+    }
+
+    /// Gets a reference to the underlying reader
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Gets a mutable reference to the underlying reader.
+    ///
+    /// It is inadvisable to directly read from the underlying reader.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Unwraps this `MultiDecompressorReader<R>`, returning the underlying reader.
+    ///
+    /// # Errors
+    ///
+    /// An [`Err`] will be returned if the current member has not been finished.
+    pub fn into_inner(self) -> Result<R, IntoInnerError<MultiDecompressorReader<R>>> {
+        if self.state.decoder().is_finished() {
+            Ok(self.inner)
+        } else {
+            Err(IntoInnerError::new(
+                self,
+                io::ErrorKind::UnexpectedEof.into(),
+            ))
+        }
+    }
+
+    /// Returns the number of members that have been decompressed completely.
+    pub fn members(&self) -> u64 {
+        self.members + self.state.decoder().is_finished() as u64
+    }
+
+    /// Returns the number of bytes consumed from the underlying reader, over all members.
+    ///
+    /// Right after a member is finished, this is the offset at which it ends within the
+    /// underlying reader.
+    pub fn bytes_consumed(&self) -> u64 {
+        self.member_offset + self.state.total_in()
+    }
+
+    /// Returns the offset within the underlying reader at which the current member starts.
+    pub fn member_offset(&self) -> u64 {
+        self.member_offset
+    }
+
+    fn next_member(&mut self) -> io::Result<()> {
+        let decoder = self.options.try_build()?;
+
+        self.members += 1;
+        self.member_offset += self.state.total_in();
+        *self.state = ReaderState::new(decoder);
+
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Read for MultiDecompressorReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let input = self.inner.fill_buf()?;
+            let (bytes_read, bytes_written) = self.state.read(input, buf)?;
+            self.inner.consume(bytes_read);
+
+            match bytes_written {
+                Some(0) if !buf.is_empty() && self.state.decoder().is_finished() => {
+                    if self.inner.fill_buf()?.is_empty() {
+                        return Ok(0);
+                    }
+
+                    self.next_member()?;
                 }
-                DecoderInfo::NeedsMoreInput => continue,
-                DecoderInfo::NeedsMoreOutput if buf.is_empty() => return Ok(0),
-                DecoderInfo::NeedsMoreOutput => panic!(
-                    "decoder needs output despite not giving any while having the chance to do so"
-                ),
-            };
+                Some(bytes_written) => return Ok(bytes_written),
+                None => {}
+            }
+        }
+    }
+}
+
+/// The checksum verification of a decompressor wrapper, if enabled by
+/// [`BrotliDecoderOptions::verify_checksums`].
+#[derive(Debug, Default)]
+pub(crate) struct ChecksumState {
+    #[cfg(feature = "checksum")]
+    verifier: Option<Box<ChecksumVerifier>>,
+}
+
+#[cfg(feature = "checksum")]
+impl ChecksumState {
+    /// Creates the verification for `decoder`, capturing its metadata blocks if enabled.
+    pub(crate) fn new(decoder: &mut BrotliDecoder) -> Self {
+        ChecksumState {
+            verifier: decoder
+                .verify_checksums
+                .then(|| Box::new(ChecksumVerifier::new(decoder))),
+        }
+    }
+
+    /// Removes the oldest captured metadata block that is not a checksum from the queue and
+    /// returns it.
+    pub(crate) fn pop_metadata(&mut self, decoder: &mut BrotliDecoder) -> Option<Metadata> {
+        match &mut self.verifier {
+            Some(verifier) => verifier.pop_with(decoder),
+            None => decoder.pop_metadata(),
+        }
+    }
+
+    /// Moves the checksums captured by `decoder` into the verifier. This must happen before the
+    /// data they follow is passed to [`Self::update`].
+    pub(crate) fn drain(&mut self, decoder: &mut BrotliDecoder) {
+        if let Some(verifier) = &mut self.verifier {
+            verifier.drain(decoder);
+        }
+    }
+
+    /// Hashes decompressed `data`, verifying every checksum it reaches.
+    pub(crate) fn update(&mut self, data: &[u8]) -> io::Result<()> {
+        match &mut self.verifier {
+            Some(verifier) => Ok(verifier.update(data)?),
+            None => Ok(()),
+        }
+    }
+
+    /// Verifies that the stream of the finished `decoder` had a checksum of all decompressed data.
+    pub(crate) fn finish(&mut self, decoder: &mut BrotliDecoder) -> io::Result<()> {
+        match &mut self.verifier {
+            Some(verifier) => {
+                verifier.drain(decoder);
+                Ok(verifier.finish()?)
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(not(feature = "checksum"))]
+impl ChecksumState {
+    pub(crate) fn new(_decoder: &mut BrotliDecoder) -> Self {
+        ChecksumState::default()
+    }
+
+    pub(crate) fn pop_metadata(&mut self, decoder: &mut BrotliDecoder) -> Option<Metadata> {
+        decoder.pop_metadata()
+    }
+
+    pub(crate) fn drain(&mut self, _decoder: &mut BrotliDecoder) {}
+
+    pub(crate) fn update(&mut self, _data: &[u8]) -> io::Result<()> {
+        Ok(())
+    }
+
+    pub(crate) fn finish(&mut self, _decoder: &mut BrotliDecoder) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl ChecksumState {
+    /// Drains the checksums captured by `decoder`, then hashes `data`.
+    pub(crate) fn update_with(
+        &mut self,
+        decoder: &mut BrotliDecoder,
+        data: &[u8],
+    ) -> io::Result<()> {
+        self.drain(decoder);
+        self.update(data)
+    }
+}
+
+/// The state machine of a decompressing reader, shared by [`DecompressorReader`] and the async
+/// readers.
+#[derive(Debug)]
+pub(crate) struct ReaderState {
+    decoder: BrotliDecoder,
+    checksum: ChecksumState,
+    buffer: OutputBuffer,
+    total_in: u64,
+    total_out: u64,
+}
+
+impl ReaderState {
+    pub(crate) fn new(mut decoder: BrotliDecoder) -> Self {
+        let checksum = ChecksumState::new(&mut decoder);
+
+        ReaderState {
+            decoder,
+            checksum,
+            buffer: OutputBuffer::default(),
+            total_in: 0,
+            total_out: 0,
+        }
+    }
+
+    pub(crate) fn decoder(&self) -> &BrotliDecoder {
+        &self.decoder
+    }
+
+    pub(crate) fn decoder_mut(&mut self) -> &mut BrotliDecoder {
+        &mut self.decoder
+    }
+
+    pub(crate) fn into_decoder(self) -> BrotliDecoder {
+        self.decoder
+    }
+
+    pub(crate) fn total_in(&self) -> u64 {
+        self.total_in
+    }
+
+    pub(crate) fn pop_metadata(&mut self) -> Option<Metadata> {
+        self.checksum.pop_metadata(&mut self.decoder)
+    }
+
+    fn give_input(&mut self, input: &[u8]) -> io::Result<(usize, DecoderInfo)> {
+        let (bytes_read, info) = self
+            .decoder
+            .give_input(input)
+            .map_err(|err| self.decoder.map_error(err))?;
+        self.total_in += bytes_read as u64;
+
+        Ok((bytes_read, info))
+    }
+
+    /// Returns the output taken from the decoder that has not been consumed yet.
+    pub(crate) fn buffer(&self) -> &[u8] {
+        self.buffer.get()
+    }
+
+    pub(crate) fn consume(&mut self, amt: usize) {
+        self.buffer.consume(amt);
+    }
+
+    /// Decompresses `input` into the internal output buffer of the decoder, where an empty
+    /// `input` signals the end of the input. Returns the number of bytes read from `input` and
+    /// whether [`Self::buffer`] is filled, which it is not only at the end of the stream. If the
+    /// buffer is not filled yet, this must be called again with refilled input.
+    pub(crate) fn fill(&mut self, input: &[u8]) -> io::Result<(usize, bool)> {
+        if !self.buffer().is_empty() {
+            return Ok((0, true));
+        }
+
+        let eof = input.is_empty();
+        let (bytes_read, info) = match self.decoder.has_output() {
+            true => (0, DecoderInfo::NeedsMoreOutput),
+            false => self.give_input(input)?,
+        };
+
+        // SAFETY: the output remains valid until the decoder is used again, which only happens
+        // once the buffer is empty
+        if let Some(output) = unsafe { self.decoder.take_output() } {
+            unsafe { self.buffer.set(output) };
+
+            self.total_out += output.len() as u64;
+            self.decoder
+                .output_limits()
+                .check(self.total_in, self.total_out)?;
+
+            // draining metadata does not invalidate the output of the decoder
+            self.checksum
+                .update_with(&mut self.decoder, self.buffer.get())?;
+
+            return Ok((bytes_read, true));
+        }
+
+        self.decoder
+            .output_limits()
+            .check(self.total_in, self.total_out)?;
+
+        match info {
+            DecoderInfo::Finished => {
+                self.checksum.finish(&mut self.decoder)?;
+
+                Ok((bytes_read, true))
+            }
+            DecoderInfo::NeedsMoreInput if eof => Err(io::ErrorKind::UnexpectedEof.into()),
+            DecoderInfo::NeedsMoreInput | DecoderInfo::NeedsMoreOutput => Ok((bytes_read, false)),
+        }
+    }
+
+    /// Decompresses `input` into `buf`, where an empty `input` signals the end of the input.
+    /// Returns the number of bytes read from `input` and, once the read is complete, the number of
+    /// bytes written to `buf`. If the read is incomplete, this must be called again with refilled
+    /// input.
+    pub(crate) fn read(
+        &mut self,
+        input: &[u8],
+        buf: &mut [u8],
+    ) -> io::Result<(usize, Option<usize>)> {
+        let eof = input.is_empty();
+        let DecodeResult {
+            bytes_read,
+            bytes_written,
+            info,
+        } = self
+            .decoder
+            .decompress(input, buf)
+            .map_err(|err| self.decoder.map_error(err))?;
+
+        self.total_in += bytes_read as u64;
+        self.total_out += bytes_written as u64;
+        self.decoder
+            .output_limits()
+            .check(self.total_in, self.total_out)?;
+
+        self.checksum
+            .update_with(&mut self.decoder, &buf[..bytes_written])?;
+
+        if bytes_written == 0 && info == DecoderInfo::Finished {
+            self.checksum.finish(&mut self.decoder)?;
+        }
+
+        let bytes_written = match info {
+            _ if bytes_written > 0 => Some(bytes_written),
+            DecoderInfo::Finished => Some(0),
+            DecoderInfo::NeedsMoreInput if eof => {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            DecoderInfo::NeedsMoreInput => None,
+            DecoderInfo::NeedsMoreOutput if buf.is_empty() => Some(0),
+            DecoderInfo::NeedsMoreOutput => panic!(
+                "decoder needs output despite not giving any while having the chance to do so"
+            ),
+        };
+
+        Ok((bytes_read, bytes_written))
+    }
+}
+
+/// The state machine of an async decompressing writer.
+///
+/// Unlike [`DecompressorWriter`], an async writer may only write part of the decoder output at a
+/// time, so output is decompressed into a buffer from which it is written as the writer allows.
+#[cfg(any(feature = "tokio", feature = "futures-io"))]
+#[derive(Debug)]
+pub(crate) struct AsyncWriterState {
+    decoder: BrotliDecoder,
+    checksum: ChecksumState,
+    total_in: u64,
+    total_out: u64,
+    output: Box<[u8]>,
+    pos: usize,
+    len: usize,
+}
+
+#[cfg(any(feature = "tokio", feature = "futures-io"))]
+impl AsyncWriterState {
+    const OUTPUT_SIZE: usize = 32 * 1024;
+
+    pub(crate) fn new(mut decoder: BrotliDecoder) -> Self {
+        let checksum = ChecksumState::new(&mut decoder);
+
+        AsyncWriterState {
+            decoder,
+            checksum,
+            total_in: 0,
+            total_out: 0,
+            output: vec![0; Self::OUTPUT_SIZE].into_boxed_slice(),
+            pos: 0,
+            len: 0,
+        }
+    }
+
+    pub(crate) fn decoder(&self) -> &BrotliDecoder {
+        &self.decoder
+    }
+
+    pub(crate) fn into_decoder(self) -> BrotliDecoder {
+        self.decoder
+    }
+
+    pub(crate) fn pop_metadata(&mut self) -> Option<Metadata> {
+        self.checksum.pop_metadata(&mut self.decoder)
+    }
+
+    /// Returns the output that is yet to be written.
+    pub(crate) fn pending(&self) -> &[u8] {
+        &self.output[self.pos..self.len]
+    }
+
+    /// Marks `amt` bytes of the pending output as written.
+    pub(crate) fn consume(&mut self, amt: usize) {
+        self.pos += amt;
+    }
+
+    /// Decompresses `input`, returning the number of bytes read from it. Must only be called once
+    /// all pending output was written.
+    pub(crate) fn write(&mut self, input: &[u8]) -> io::Result<usize> {
+        let DecodeResult {
+            bytes_read,
+            bytes_written,
+            info,
+        } = self
+            .decoder
+            .decompress(input, &mut self.output)
+            .map_err(|err| self.decoder.map_error(err))?;
+
+        self.pos = 0;
+        self.len = bytes_written;
+        self.total_in += bytes_read as u64;
+        self.total_out += bytes_written as u64;
+        self.decoder
+            .output_limits()
+            .check(self.total_in, self.total_out)?;
+
+        self.checksum
+            .update_with(&mut self.decoder, &self.output[..bytes_written])?;
+
+        if info == DecoderInfo::Finished {
+            self.checksum.finish(&mut self.decoder)?;
+        }
+
+        Ok(bytes_read)
+    }
+
+    /// Advances decompressing all input given so far. Returns `true` once complete, which is only
+    /// the case when all pending output was written. Must only be called once all pending output
+    /// was written.
+    pub(crate) fn flush(&mut self) -> io::Result<bool> {
+        self.write(&[])?;
+
+        Ok(self.len == 0)
+    }
+
+    /// Advances finishing the decompression stream, like [`Self::flush`].
+    ///
+    /// # Errors
+    ///
+    /// An [`Err`] of kind [`io::ErrorKind::UnexpectedEof`] will be returned if the decompression
+    /// stream is incomplete.
+    pub(crate) fn finish(&mut self) -> io::Result<bool> {
+        if !self.flush()? {
+            Ok(false)
+        } else if self.decoder.is_finished() {
+            Ok(true)
+        } else {
+            Err(io::ErrorKind::UnexpectedEof.into())
         }
     }
 }
 
+/// The state machine of a decompressing stream.
+///
+/// Unlike the state of an async writer, output is not decompressed into a buffer but taken from
+/// the decoder chunk by chunk, so every chunk is copied exactly once.
+#[cfg(feature = "stream")]
+#[derive(Debug)]
+pub(crate) struct ChunkState {
+    decoder: BrotliDecoder,
+    checksum: ChecksumState,
+    total_in: u64,
+    total_out: u64,
+}
+
+#[cfg(feature = "stream")]
+impl ChunkState {
+    pub(crate) fn new(mut decoder: BrotliDecoder) -> Self {
+        let checksum = ChecksumState::new(&mut decoder);
+
+        ChunkState {
+            decoder,
+            checksum,
+            total_in: 0,
+            total_out: 0,
+        }
+    }
+
+    pub(crate) fn decoder(&self) -> &BrotliDecoder {
+        &self.decoder
+    }
+
+    pub(crate) fn into_decoder(self) -> BrotliDecoder {
+        self.decoder
+    }
+
+    pub(crate) fn pop_metadata(&mut self) -> Option<Metadata> {
+        self.checksum.pop_metadata(&mut self.decoder)
+    }
+
+    /// Takes the next chunk of output from the decoder, or returns `None` if it has no output.
+    pub(crate) fn take_output(&mut self) -> io::Result<Option<Bytes>> {
+        self.checksum.drain(&mut self.decoder);
+
+        let limits = self.decoder.output_limits();
+
+        let output = match self.decoder.next_output() {
+            Some(output) => output,
+            None => return Ok(None),
+        };
+
+        self.total_out += output.len() as u64;
+        limits.check(self.total_in, self.total_out)?;
+        self.checksum.update(output)?;
+
+        Ok(Some(Bytes::copy_from_slice(output)))
+    }
+
+    /// Decompresses `input`, returning the number of bytes read from it. Must only be called once
+    /// all output was taken.
+    ///
+    /// # Errors
+    ///
+    /// An [`Err`] of kind [`io::ErrorKind::InvalidData`] will be returned if `input` follows the
+    /// end of the decompression stream.
+    pub(crate) fn write(&mut self, input: &[u8]) -> io::Result<usize> {
+        if self.decoder.is_finished() && !input.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "data after the end of the decompression stream",
+            ));
+        }
+
+        let (bytes_read, _info) = self
+            .decoder
+            .give_input(input)
+            .map_err(|err| self.decoder.map_error(err))?;
+        self.total_in += bytes_read as u64;
+
+        Ok(bytes_read)
+    }
+
+    /// Verifies that the decompression stream is finished. Must only be called once all output
+    /// was taken.
+    ///
+    /// # Errors
+    ///
+    /// An [`Err`] of kind [`io::ErrorKind::UnexpectedEof`] will be returned if the decompression
+    /// stream is incomplete.
+    pub(crate) fn finish(&mut self) -> io::Result<()> {
+        if !self.decoder.is_finished() {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+
+        self.checksum.finish(&mut self.decoder)
+    }
+}
+
 /// Wraps a writer and decompresses its output.
 ///
 /// `DecompressorWriter<R>` wraps a writer and adds brotli decompression to the output.
@@ -668,6 +2198,9 @@ pub struct DecompressorWriter<W: Write> {
     inner: W,
     decoder: BrotliDecoder,
     panicked: bool,
+    checksum: ChecksumState,
+    total_in: u64,
+    total_out: u64,
 }
 
 impl<W: Write> DecompressorWriter<W> {
@@ -675,12 +2208,16 @@ impl<W: Write> DecompressorWriter<W> {
     ///
     /// # Panics
     ///
-    /// Panics if the decoder fails to be allocated or initialized
+    /// Panics if the decoder fails to be allocated or initialized, see [`Self::try_new`] for a
+    /// fallible alternative.
     pub fn new(inner: W) -> DecompressorWriter<W> {
         DecompressorWriter {
             inner,
             decoder: BrotliDecoder::new(),
             panicked: false,
+            checksum: ChecksumState::default(),
+            total_in: 0,
+            total_out: 0,
         }
     }
 
@@ -688,7 +2225,8 @@ impl<W: Write> DecompressorWriter<W> {
     ///
     /// # Panics
     ///
-    /// Panics if the decoder fails to be allocated or initialized
+    /// Panics if the decoder fails to be allocated or initialized, see [`Self::try_new_in`] for
+    /// a fallible alternative.
     pub fn new_in<A>(inner: W, alloc: A) -> Self
     where
         A: GlobalAlloc + 'static,
@@ -697,9 +2235,34 @@ impl<W: Write> DecompressorWriter<W> {
             inner,
             decoder: BrotliDecoder::new_in(alloc),
             panicked: false,
+            checksum: ChecksumState::default(),
+            total_in: 0,
+            total_out: 0,
         }
     }
 
+    /// Attempts to create a new `DecompressorWriter<W>` with a newly created decoder.
+    ///
+    /// # Errors
+    ///
+    /// An [`AllocError`] will be returned if the decoder fails to be allocated or initialized.
+    pub fn try_new(inner: W) -> Result<Self, AllocError> {
+        Ok(DecompressorWriter::with_decoder(BrotliDecoder::try_new()?, inner))
+    }
+
+    /// Attempts to create a new `DecompressorWriter<W>` with a newly created decoder using
+    /// allocator `alloc`.
+    ///
+    /// # Errors
+    ///
+    /// An [`AllocError`] will be returned if the decoder fails to be allocated or initialized.
+    pub fn try_new_in<A>(inner: W, alloc: A) -> Result<Self, AllocError>
+    where
+        A: GlobalAlloc + 'static,
+    {
+        Ok(DecompressorWriter::with_decoder(BrotliDecoder::try_new_in(alloc)?, inner))
+    }
+
     /// Creates a new `DecompressorWriter<W>` with a specified decoder.
     ///
     /// # Examples
@@ -715,11 +2278,16 @@ impl<W: Write> DecompressorWriter<W> {
     /// let mut writer = DecompressorWriter::with_decoder(decoder, Vec::new());
     /// Ok::<(), brotlic::SetParameterError>(())
     /// ```
-    pub fn with_decoder(decoder: BrotliDecoder, inner: W) -> Self {
+    pub fn with_decoder(mut decoder: BrotliDecoder, inner: W) -> Self {
+        let checksum = ChecksumState::new(&mut decoder);
+
         DecompressorWriter {
             inner,
             decoder,
             panicked: false,
+            checksum,
+            total_in: 0,
+            total_out: 0,
         }
     }
 
@@ -756,6 +2324,12 @@ impl<W: Write> DecompressorWriter<W> {
         }
     }
 
+    /// Removes the oldest captured metadata block from the queue and returns it. For more
+    /// information see [`BrotliDecoder::pop_metadata`].
+    pub fn pop_metadata(&mut self) -> Option<Metadata> {
+        self.checksum.pop_metadata(&mut self.decoder)
+    }
+
     /// Disassembles this `DecompressorWriter<W>`, returning the underlying writer and decoder.
     ///
     /// If the underlying writer panicked, it is not known what portion of the data was written.
@@ -777,22 +2351,36 @@ impl<W: Write> DecompressorWriter<W> {
     }
 
     fn flush_decoder_output(&mut self) -> io::Result<()> {
-        while let Some(output) = unsafe { self.decoder.take_output() } {
+        self.checksum.drain(&mut self.decoder);
+
+        let limits = self.decoder.output_limits();
+
+        self.decoder.drain_output(|output| {
+            self.total_out += output.len() as u64;
+            limits.check(self.total_in, self.total_out)?;
+            self.checksum.update(output)?;
+
             self.panicked = true;
             let r = self.inner.write_all(output);
             self.panicked = false;
-            r?;
-        }
-
-        Ok(())
+            r
+        })
     }
 }
 
 impl<W: Write> Write for DecompressorWriter<W> {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        let (bytes_read, _decoder_result) = self.decoder.give_input(buf)?;
+        let (bytes_read, _decoder_result) = self
+            .decoder
+            .give_input(buf)
+            .map_err(|err| self.decoder.map_error(err))?;
+        self.total_in += bytes_read as u64;
         self.flush_decoder_output()?;
 
+        if self.decoder.is_finished() {
+            self.checksum.finish(&mut self.decoder)?;
+        }
+
         Ok(bytes_read)
     }
 
@@ -5,15 +5,26 @@
 //! [`Read`]: https://doc.rust-lang.org/stable/std/io/trait.Read.html
 //! [`Write`]: https://doc.rust-lang.org/stable/std/io/trait.Write.html
 
+#[cfg(feature = "checksum")]
+use crate::checksum::{Checksum, ChecksumMode, ChecksumWriter};
 use crate::{
-    BlockSize, CompressionMode, IntoInnerError, LargeWindowSize, Quality, SetParameterError,
-    WindowSize,
+    AllocError, BlockSize, BuildError, CompressError, CompressionMode, DictionaryType,
+    IntoInnerError, LargeWindowSize, OutputBuffer, Quality, SetParameterError, WindowSize,
 };
 use brotlic_sys::*;
+#[cfg(feature = "stream")]
+use bytes::Bytes;
 use std::alloc::GlobalAlloc;
 use std::error::Error;
 use std::io::{BufRead, Read, Write};
-use std::{fmt, io, mem, ptr, slice};
+use std::os::raw::c_int;
+use std::sync::Arc;
+use std::{cmp, fmt, io, mem, ptr, slice};
+
+/// The maximum number of bytes that a single metadata block can hold (16 MiB).
+pub const MAX_METADATA_SIZE: usize = 1 << 24;
+
+const METADATA_BUFFER_SIZE: usize = 32 * 1024;
 
 /// A reference to a brotli encoder.
 ///
@@ -26,6 +37,13 @@ pub struct BrotliEncoder {
     // this field is read read across FFI boundaries
     #[allow(dead_code)]
     alloc: Option<Box<Box<dyn GlobalAlloc>>>,
+
+    // attached dictionaries are referenced by the encoder state across FFI boundaries
+    dictionaries: Vec<Arc<PreparedDictionary>>,
+
+    // checksums are computed by the compressor wrappers
+    #[cfg(feature = "checksum")]
+    checksum: Option<(Checksum, ChecksumMode)>,
 }
 
 unsafe impl Send for BrotliEncoder {}
@@ -36,28 +54,66 @@ impl BrotliEncoder {
     ///
     /// # Panics
     ///
-    /// Panics if the encoder fails to be allocated or initialized
+    /// Panics if the encoder fails to be allocated or initialized, see [`Self::try_new`] for a
+    /// fallible alternative.
     #[doc(alias = "BrotliEncoderCreateInstance")]
     pub fn new() -> Self {
+        match BrotliEncoder::try_new() {
+            Ok(encoder) => encoder,
+            Err(err) => panic!("BrotliEncoderCreateInstance returned NULL: {}", err),
+        }
+    }
+
+    /// Constructs a new brotli encoder instance using allocator `alloc`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the encoder fails to be allocated or initialized, see [`Self::try_new_in`] for a
+    /// fallible alternative.
+    #[doc(alias = "BrotliEncoderCreateInstance")]
+    pub fn new_in<A>(alloc: A) -> Self
+    where
+        A: GlobalAlloc + 'static,
+    {
+        match BrotliEncoder::try_new_in(alloc) {
+            Ok(encoder) => encoder,
+            Err(err) => panic!("BrotliEncoderCreateInstance returned NULL: {}", err),
+        }
+    }
+
+    /// Attempts to construct a new brotli encoder instance.
+    ///
+    /// # Errors
+    ///
+    /// An [`AllocError`] will be returned if the encoder fails to be allocated or initialized.
+    #[doc(alias = "BrotliEncoderCreateInstance")]
+    pub fn try_new() -> Result<Self, AllocError> {
         let instance = unsafe { BrotliEncoderCreateInstance(None, None, ptr::null_mut()) };
 
         if !instance.is_null() {
-            BrotliEncoder {
+            Ok(BrotliEncoder {
                 state: instance,
                 alloc: None,
-            }
+                dictionaries: Vec::new(),
+                #[cfg(feature = "checksum")]
+                checksum: None,
+            })
         } else {
-            panic!("BrotliEncoderCreateInstance returned NULL: failed to allocate or initialize");
+            Err(AllocError)
         }
     }
 
-    /// Constructs a new brotli encoder instance using allocator `alloc`.
+    /// Attempts to construct a new brotli encoder instance using allocator `alloc`.
     ///
-    /// # Panics
+    /// Allocations refused by `alloc`, for example because a memory budget is exhausted, are
+    /// reported as an [`AllocError`] instead of aborting the process. Allocations refused later
+    /// on, while compressing, are reported as an [`EncodeError`].
+    ///
+    /// # Errors
     ///
-    /// Panics if the encoder fails to be allocated or initialized
+    /// An [`AllocError`] will be returned if the encoder fails to be allocated or initialized.
     #[doc(alias = "BrotliEncoderCreateInstance")]
-    pub fn new_in<A>(alloc: A) -> Self
+    pub fn try_new_in<A>(alloc: A) -> Result<Self, AllocError>
     where
         A: GlobalAlloc + 'static,
     {
@@ -68,12 +124,15 @@ impl BrotliEncoder {
         };
 
         if !instance.is_null() {
-            BrotliEncoder {
+            Ok(BrotliEncoder {
                 state: instance,
                 alloc: Some(alloc),
-            }
+                dictionaries: Vec::new(),
+                #[cfg(feature = "checksum")]
+                checksum: None,
+            })
         } else {
-            panic!("BrotliEncoderCreateInstance returned NULL: failed to allocate or initialize");
+            Err(AllocError)
         }
     }
 
@@ -146,6 +205,34 @@ impl BrotliEncoder {
         Ok(self.compress(input, &mut [], op)?.bytes_read)
     }
 
+    /// Emits `metadata` as a metadata block into the compression stream.
+    ///
+    /// Metadata is opaque to brotli: it does not affect the decompressed output, but it is carried
+    /// verbatim within the compression stream. Decoders skip metadata blocks, so streams with
+    /// metadata remain readable by any brotli decoder. A single metadata block can hold at most
+    /// [`MAX_METADATA_SIZE`] bytes. The stream is soft-flushed before the metadata block is
+    /// emitted.
+    ///
+    /// This is a low-level API, for a higher level abstraction see
+    /// [`CompressorWriter::write_metadata`]. Like [`Self::give_input`], this returns the number of
+    /// bytes read from `metadata`, while output is read from the internal buffer via
+    /// [`Self::take_output`]. Until emitting the metadata block is complete, this method must be
+    /// called again with the remaining metadata and no other operation may be performed. Emitting
+    /// is complete when all metadata has been consumed and a call with the empty remainder left
+    /// the encoder without output.
+    ///
+    /// # Errors
+    ///
+    /// An [`Err`] will be returned if `metadata` exceeds [`MAX_METADATA_SIZE`], the remaining
+    /// metadata changed in between calls or the encoder is currently flushing or finished.
+    pub fn emit_metadata(&mut self, metadata: &[u8]) -> Result<usize, EncodeError> {
+        if metadata.len() > MAX_METADATA_SIZE {
+            return Err(EncodeError);
+        }
+
+        self.give_input(metadata, BrotliOperation::EmitMetadata)
+    }
+
     /// Attempts the flush the encoding stream.
     ///
     /// Actual flush is performed when all output has been successfully read. Use
@@ -182,6 +269,8 @@ impl BrotliEncoder {
     /// that this function can return `Some(&[u8])` multiple times. Only when the method returns
     /// `None` is when there is no more output available by the encoder.
     ///
+    /// For a safe alternative, see [`Self::next_output`] and [`Self::drain_output`].
+    ///
     /// # Safety
     ///
     /// For every consecutive call of this function, the previous slice becomes invalidated.
@@ -197,6 +286,120 @@ impl BrotliEncoder {
         }
     }
 
+    /// Checks if the encoder has more output and if so, returns a slice to its internal output
+    /// buffer.
+    ///
+    /// This is the safe counterpart of [`Self::take_output`]. The slice mutably borrows the
+    /// encoder, so the borrow checker ensures it is no longer used once the encoder is used again.
+    /// Like with `take_output`, each byte returned is considered "consumed" and output may be
+    /// returned in multiple chunks, until this method returns `None`.
+    #[doc(alias = "BrotliEncoderTakeOutput")]
+    pub fn next_output(&mut self) -> Option<&[u8]> {
+        // SAFETY: the slice borrows the encoder, so it cannot outlive the next call
+        unsafe { self.take_output() }
+    }
+
+    /// Passes all output of the encoder to `f`, one chunk at a time, without copying it.
+    ///
+    /// Output is drained until the encoder has no more output, or until `f` returns an error,
+    /// which is returned. Bytes passed to `f` are considered "consumed", even if it fails.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use brotlic::encode::{BrotliEncoder, BrotliOperation, EncodeError};
+    ///
+    /// let mut encoder = BrotliEncoder::new();
+    /// let mut input = b"hello world".as_slice();
+    /// let mut compressed = Vec::new();
+    ///
+    /// while !encoder.is_finished() {
+    ///     let bytes_read = encoder.give_input(input, BrotliOperation::Finish)?;
+    ///     input = &input[bytes_read..];
+    ///
+    ///     encoder.drain_output(|chunk| {
+    ///         compressed.extend_from_slice(chunk);
+    ///         Ok::<(), EncodeError>(())
+    ///     })?;
+    /// }
+    ///
+    /// assert!(!compressed.is_empty());
+    /// # Ok::<(), EncodeError>(())
+    /// ```
+    pub fn drain_output<E, F>(&mut self, mut f: F) -> Result<(), E>
+    where
+        F: FnMut(&[u8]) -> Result<(), E>,
+    {
+        while let Some(output) = self.next_output() {
+            f(output)?;
+        }
+
+        Ok(())
+    }
+
+    /// Emits `metadata` as a metadata block, passing the compressed stream to `f` in chunks.
+    ///
+    /// Unlike [`Self::emit_metadata`], which hands brotli no output buffer so it copies at most 16
+    /// bytes at a time into its internal buffer, the metadata is copied straight into a buffer
+    /// that is passed to `f` whenever it fills up.
+    pub(crate) fn write_metadata<F>(&mut self, metadata: &[u8], mut f: F) -> io::Result<()>
+    where
+        F: FnMut(&[u8]) -> io::Result<()>,
+    {
+        if metadata.len() > MAX_METADATA_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "metadata exceeds the maximum size of a metadata block",
+            ));
+        }
+
+        // leaves room for the header of the metadata block
+        let mut buf = vec![0; cmp::min(metadata.len() + 8, METADATA_BUFFER_SIZE)];
+        let mut remaining = metadata;
+
+        self.drain_output(&mut f)?;
+
+        loop {
+            let res = self.compress(remaining, &mut buf, BrotliOperation::EmitMetadata)?;
+            remaining = &remaining[res.bytes_read..];
+
+            if res.bytes_written > 0 {
+                f(&buf[..res.bytes_written])?;
+            }
+
+            if remaining.is_empty() && !self.has_output() {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Attaches a prepared dictionary to the encoder.
+    ///
+    /// The encoder keeps a reference to `dictionary` until it is dropped, so the same prepared
+    /// dictionary can be shared by many encoders. Multiple raw dictionaries and at most one
+    /// serialized dictionary can be attached. Dictionaries must be attached before any input is
+    /// processed. Usually it is more convenient to use
+    /// [`BrotliEncoderOptions::prepared_dictionary`] instead.
+    ///
+    /// # Errors
+    ///
+    /// An [`Err`] will be returned if the encoder rejected the dictionary, e.g. because too many
+    /// dictionaries were attached or encoding already started.
+    #[doc(alias = "BrotliEncoderAttachPreparedDictionary")]
+    pub fn attach_prepared_dictionary(
+        &mut self,
+        dictionary: Arc<PreparedDictionary>,
+    ) -> Result<(), SetParameterError> {
+        let r = unsafe { BrotliEncoderAttachPreparedDictionary(self.state, dictionary.dictionary) };
+
+        if r != 0 {
+            self.dictionaries.push(dictionary);
+            Ok(())
+        } else {
+            Err(SetParameterError::InvalidDictionary)
+        }
+    }
+
     /// Returns the version of the C brotli encoder library.
     #[doc(alias = "BrotliEncoderVersion")]
     pub fn version() -> u32 {
@@ -261,6 +464,217 @@ pub enum BrotliOperation {
     /// operation is initiated, to keep submitting flush operations till the encoder has no more
     /// output available. Additionally, the input stream should not be swapped, reduced or extended.
     Finish = BrotliEncoderOperation_BROTLI_OPERATION_FINISH as isize,
+
+    /// Instructs the encoder to emit the input as a metadata block. The whole input is considered
+    /// to be the content of the metadata block, which must not exceed [`MAX_METADATA_SIZE`] bytes.
+    /// Care must be taken once emitting metadata is initiated, to keep submitting the remaining
+    /// metadata till it is consumed and the encoder has no more output available. For a more
+    /// convenient alternative, see [`BrotliEncoder::emit_metadata`].
+    EmitMetadata = BrotliEncoderOperation_BROTLI_OPERATION_EMIT_METADATA as isize,
+}
+
+/// A dictionary prepared for use by brotli encoders.
+///
+/// Preparing a dictionary indexes its content, which is expensive for large dictionaries. A
+/// prepared dictionary is immutable, so it is meant to be prepared once and then shared across many
+/// encoders via [`Arc`], see [`BrotliEncoderOptions::prepared_dictionary`]. The dictionary data is
+/// owned by the prepared dictionary and every encoder holds onto the dictionary it uses, so the
+/// data is guaranteed to outlive all of them.
+///
+/// Data compressed with a dictionary can only be decompressed by a decoder that uses the same
+/// dictionary, see [`BrotliDecoderOptions::dictionary`].
+///
+/// [`BrotliDecoderOptions::dictionary`]: crate::decode::BrotliDecoderOptions::dictionary
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::Arc;
+/// use brotlic::encode::PreparedDictionary;
+/// use brotlic::{BrotliEncoderOptions, DictionaryType, Quality};
+///
+/// let data = b"{\"status\":\"ok\",\"data\":".as_slice();
+/// let dictionary = PreparedDictionary::new(DictionaryType::Raw, data, Quality::best())?;
+/// let dictionary = Arc::new(dictionary);
+///
+/// let encoder = BrotliEncoderOptions::new()
+///     .prepared_dictionary(dictionary.clone())
+///     .build()?;
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct PreparedDictionary {
+    dictionary: *mut BrotliEncoderPreparedDictionary,
+    dictionary_type: DictionaryType,
+
+    // this field is read across FFI boundaries
+    data: Arc<[u8]>,
+
+    // this field is read read across FFI boundaries
+    #[allow(dead_code)]
+    alloc: Option<Box<Box<dyn GlobalAlloc>>>,
+}
+
+unsafe impl Send for PreparedDictionary {}
+unsafe impl Sync for PreparedDictionary {}
+
+impl PreparedDictionary {
+    /// Prepares a dictionary of type `dictionary_type` from `data`.
+    ///
+    /// The dictionary is prepared for encoders of at most the given `quality`.
+    ///
+    /// # Errors
+    ///
+    /// An [`Err`] will be returned if the dictionary is invalid, its type is not supported or it
+    /// fails to be allocated.
+    #[doc(alias = "BrotliEncoderPrepareDictionary")]
+    pub fn new<D>(
+        dictionary_type: DictionaryType,
+        data: D,
+        quality: Quality,
+    ) -> Result<Self, PrepareDictionaryError>
+    where
+        D: Into<Arc<[u8]>>,
+    {
+        let data = data.into();
+        let dictionary = unsafe {
+            BrotliEncoderPrepareDictionary(
+                dictionary_type as BrotliSharedDictionaryType,
+                data.len(),
+                data.as_ptr(),
+                quality.0 as c_int,
+                None,
+                None,
+                ptr::null_mut(),
+            )
+        };
+
+        if !dictionary.is_null() {
+            Ok(PreparedDictionary {
+                dictionary,
+                dictionary_type,
+                data,
+                alloc: None,
+            })
+        } else {
+            Err(PrepareDictionaryError)
+        }
+    }
+
+    /// Prepares a dictionary of type `dictionary_type` from `data` using allocator `alloc`.
+    ///
+    /// The dictionary is prepared for encoders of at most the given `quality`.
+    ///
+    /// # Errors
+    ///
+    /// An [`Err`] will be returned if the dictionary is invalid, its type is not supported or it
+    /// fails to be allocated.
+    #[doc(alias = "BrotliEncoderPrepareDictionary")]
+    pub fn new_in<D, A>(
+        dictionary_type: DictionaryType,
+        data: D,
+        quality: Quality,
+        alloc: A,
+    ) -> Result<Self, PrepareDictionaryError>
+    where
+        D: Into<Arc<[u8]>>,
+        A: GlobalAlloc + 'static,
+    {
+        let data = data.into();
+        let alloc: Box<Box<dyn GlobalAlloc>> = Box::new(Box::new(alloc));
+        let alloc_ptr: *const Box<dyn GlobalAlloc> = alloc.as_ref();
+        let dictionary = unsafe {
+            BrotliEncoderPrepareDictionary(
+                dictionary_type as BrotliSharedDictionaryType,
+                data.len(),
+                data.as_ptr(),
+                quality.0 as c_int,
+                Some(crate::malloc),
+                Some(crate::free),
+                alloc_ptr as _,
+            )
+        };
+
+        if !dictionary.is_null() {
+            Ok(PreparedDictionary {
+                dictionary,
+                dictionary_type,
+                data,
+                alloc: Some(alloc),
+            })
+        } else {
+            Err(PrepareDictionaryError)
+        }
+    }
+
+    /// Returns the type of the dictionary.
+    pub fn dictionary_type(&self) -> DictionaryType {
+        self.dictionary_type
+    }
+
+    /// Returns the data the dictionary was prepared from.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Returns the number of bytes of memory used by the prepared dictionary.
+    #[doc(alias = "BrotliEncoderGetPreparedDictionarySize")]
+    pub fn size(&self) -> usize {
+        unsafe { BrotliEncoderGetPreparedDictionarySize(self.dictionary) }
+    }
+}
+
+impl fmt::Debug for PreparedDictionary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PreparedDictionary")
+            .field("dictionary", &self.dictionary)
+            .field("dictionary_type", &self.dictionary_type)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for PreparedDictionary {
+    #[doc(alias = "BrotliEncoderDestroyPreparedDictionary")]
+    fn drop(&mut self) {
+        unsafe {
+            BrotliEncoderDestroyPreparedDictionary(self.dictionary);
+        }
+    }
+}
+
+/// Wraps `input` into an uncompressed brotli stream with the minimal window size, returning how
+/// many bytes were written. `output` must be at least `BrotliEncoderMaxCompressedSize` in size.
+fn make_uncompressed_stream(input: &[u8], output: &mut [u8]) -> usize {
+    if input.is_empty() {
+        output[0] = 6;
+        return 1;
+    }
+
+    // window bits = 10, followed by an empty metadata block as padding
+    output[..2].copy_from_slice(&[0x21, 0x03]);
+    let mut bytes_written = 2;
+
+    for chunk in input.chunks(MAX_METADATA_SIZE) {
+        let nibbles: u32 = match chunk.len() {
+            0..=0x10000 => 0,
+            0x10001..=0x100000 => 1,
+            _ => 2,
+        };
+        let bits = (nibbles << 1) | ((chunk.len() as u32 - 1) << 3) | (1 << (19 + 4 * nibbles));
+        let header_len = if nibbles == 2 { 4 } else { 3 };
+
+        output[bytes_written..bytes_written + header_len]
+            .copy_from_slice(&bits.to_le_bytes()[..header_len]);
+        bytes_written += header_len;
+
+        output[bytes_written..bytes_written + chunk.len()].copy_from_slice(chunk);
+        bytes_written += chunk.len();
+    }
+
+    // last empty meta-block
+    output[bytes_written] = 3;
+
+    bytes_written + 1
 }
 
 /// Compression options to be used for a [`BrotliEncoder`].
@@ -289,6 +703,9 @@ pub struct BrotliEncoderOptions {
     postfix_bits: Option<u32>,
     direct_distance_codes: Option<u32>,
     stream_offset: Option<u32>,
+    dictionaries: Vec<Arc<PreparedDictionary>>,
+    #[cfg(feature = "checksum")]
+    checksum: Option<(Checksum, ChecksumMode)>,
 }
 
 impl BrotliEncoderOptions {
@@ -307,6 +724,9 @@ impl BrotliEncoderOptions {
             postfix_bits: None,
             direct_distance_codes: None,
             stream_offset: None,
+            dictionaries: Vec::new(),
+            #[cfg(feature = "checksum")]
+            checksum: None,
         }
     }
 
@@ -409,6 +829,120 @@ impl BrotliEncoderOptions {
         self
     }
 
+    /// Attaches a prepared dictionary to the encoder. For more information see
+    /// [`PreparedDictionary`].
+    ///
+    /// This can be called multiple times to attach several dictionaries. Multiple raw dictionaries
+    /// and at most one serialized dictionary can be attached. The dictionary is shared, not copied,
+    /// by every encoder built from these options.
+    pub fn prepared_dictionary(&mut self, dictionary: Arc<PreparedDictionary>) -> &mut Self {
+        self.dictionaries.push(dictionary);
+        self
+    }
+
+    /// Writes checksums of the uncompressed data into metadata blocks of the stream.
+    ///
+    /// Checksums are computed by [`CompressorReader`] and [`CompressorWriter`], they are not
+    /// computed when using the [`BrotliEncoder`] directly. `mode` determines whether a checksum
+    /// is written only once the stream is finished or on every flush as well. For more
+    /// information see [`crate::checksum`].
+    ///
+    /// This method requires the `checksum` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::{Read, Write};
+    /// use brotlic::checksum::{Checksum, ChecksumMode};
+    /// use brotlic::{BrotliDecoderOptions, BrotliEncoderOptions};
+    /// use brotlic::{CompressorWriter, DecompressorReader};
+    ///
+    /// let encoder = BrotliEncoderOptions::new()
+    ///     .checksum(Checksum::Crc32c, ChecksumMode::Trailer)
+    ///     .build()?;
+    /// let mut compressor = CompressorWriter::with_encoder(encoder, Vec::new());
+    /// compressor.write_all(b"hello world")?;
+    /// let compressed = compressor.into_inner()?;
+    ///
+    /// let decoder = BrotliDecoderOptions::new().verify_checksums(true).build()?;
+    /// let mut decompressor = DecompressorReader::with_decoder(decoder, compressed.as_slice());
+    /// let mut decompressed = String::new();
+    /// decompressor.read_to_string(&mut decompressed)?;
+    ///
+    /// assert_eq!(decompressed, "hello world");
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    #[cfg(feature = "checksum")]
+    pub fn checksum(&mut self, checksum: Checksum, mode: ChecksumMode) -> &mut Self {
+        self.checksum = Some((checksum, mode));
+        self
+    }
+
+    /// Estimates the peak memory usage in bytes of an encoder built from these options that
+    /// compresses `input_size` bytes.
+    ///
+    /// The estimate accounts for the quality, window size, block size and attached prepared
+    /// dictionaries. Prepared dictionaries are shared between encoders, but are counted in full
+    /// here. This is only an estimate, the actual memory usage may differ.
+    ///
+    /// brotli itself only estimates for its default block size. When a [block size] is set, the
+    /// estimate is adjusted from the buffer sizes brotli 1.1.0 derives from it, and is therefore
+    /// more approximate.
+    ///
+    /// [block size]: BrotliEncoderOptions::block_size
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use brotlic::{BrotliEncoderOptions, Quality, WindowSize};
+    ///
+    /// let fast = BrotliEncoderOptions::new()
+    ///     .quality(Quality::new(2)?)
+    ///     .window_size(WindowSize::new(18)?)
+    ///     .estimate_peak_memory(1 << 20);
+    /// let best = BrotliEncoderOptions::new()
+    ///     .quality(Quality::best())
+    ///     .window_size(WindowSize::best())
+    ///     .estimate_peak_memory(1 << 20);
+    ///
+    /// assert!(fast < best);
+    /// # Ok::<(), brotlic::SetParameterError>(())
+    /// ```
+    #[doc(alias = "BrotliEncoderEstimatePeakMemoryUsage")]
+    pub fn estimate_peak_memory(&self, input_size: usize) -> usize {
+        let quality = self.quality.unwrap_or_default().0;
+        let lgwin = self.window_size.unwrap_or_default().0;
+
+        let mut estimate = unsafe {
+            BrotliEncoderEstimatePeakMemoryUsage(quality as c_int, lgwin as c_int, input_size)
+        };
+
+        // brotli always estimates using the default block size, which is only configurable from
+        // quality 4 onwards. Adjust the input and ring buffers for the configured one instead.
+        // The default block size and buffer sizes mirror ComputeLgBlock and
+        // BrotliEncoderEstimatePeakMemoryUsage of brotli 1.1.0, other releases may differ.
+        if let (Some(block_bits), 4..) = (self.block_bits, quality) {
+            let default_lgblock = if quality >= 9 { lgwin.clamp(16, 18) } else { 16 };
+            let block_buffers = |lgblock: u8| {
+                let block_size = 1usize << lgblock;
+                let ring_buffer_size = if input_size < block_size {
+                    input_size
+                } else {
+                    (1 << (1 + lgwin.max(lgblock))) + block_size
+                };
+
+                ring_buffer_size + 6 * input_size.min(block_size)
+            };
+
+            estimate = (estimate + block_buffers(block_bits.0))
+                .saturating_sub(block_buffers(default_lgblock));
+        }
+
+        self.dictionaries
+            .iter()
+            .fold(estimate, |estimate, dictionary| estimate + dictionary.size())
+    }
+
     /// Creates a brotli encoder with the specified settings using allocator `alloc`.
     ///
     /// # Errors
@@ -416,11 +950,7 @@ impl BrotliEncoderOptions {
     /// If any of the preconditions of the parameters are violated, an error is returned.
     #[doc(alias = "BrotliEncoderSetParameter")]
     pub fn build(&self) -> Result<BrotliEncoder, SetParameterError> {
-        let mut encoder = BrotliEncoder::new();
-
-        self.configure(&mut encoder)?;
-
-        Ok(encoder)
+        unwrap_alloc(self.try_build())
     }
 
     /// Creates a brotli encoder using the specified settings.
@@ -433,33 +963,211 @@ impl BrotliEncoderOptions {
     where
         A: GlobalAlloc + 'static,
     {
-        let mut encoder = BrotliEncoder::new_in(alloc);
+        unwrap_alloc(self.try_build_in(alloc))
+    }
+
+    /// Attempts to create a brotli encoder using the specified settings.
+    ///
+    /// Unlike [`Self::build`], this does not panic if the encoder fails to be allocated.
+    ///
+    /// # Errors
+    ///
+    /// An [`Err`] will be returned if the encoder fails to be allocated or initialized, or if any
+    /// of the preconditions of the parameters are violated.
+    #[doc(alias = "BrotliEncoderSetParameter")]
+    pub fn try_build(&self) -> Result<BrotliEncoder, BuildError> {
+        let mut encoder = BrotliEncoder::try_new()?;
 
         self.configure(&mut encoder)?;
 
         Ok(encoder)
     }
 
-    fn configure(&self, encoder: &mut BrotliEncoder) -> Result<(), SetParameterError> {
-        if let Some(mode) = self.mode {
-            let key = BrotliEncoderParameter_BROTLI_PARAM_MODE;
-            let value = mode as u32;
-
-            encoder.set_param(key, value)?;
-        }
-
-        if let Some(quality) = self.quality {
-            let key = BrotliEncoderParameter_BROTLI_PARAM_QUALITY;
-            let value = quality.0 as u32;
-
-            encoder.set_param(key, value)?;
-        }
+    /// Attempts to create a brotli encoder with the specified settings using allocator `alloc`.
+    ///
+    /// Unlike [`Self::build_in`], this does not panic if the encoder fails to be allocated.
+    ///
+    /// # Errors
+    ///
+    /// An [`Err`] will be returned if the encoder fails to be allocated or initialized, or if any
+    /// of the preconditions of the parameters are violated.
+    #[doc(alias = "BrotliEncoderSetParameter")]
+    pub fn try_build_in<A>(&self, alloc: A) -> Result<BrotliEncoder, BuildError>
+    where
+        A: GlobalAlloc + 'static,
+    {
+        let mut encoder = BrotliEncoder::try_new_in(alloc)?;
 
-        if let Some(window_size) = self.window_size {
-            let key = BrotliEncoderParameter_BROTLI_PARAM_LGWIN;
-            let value = window_size.0 as u32;
+        self.configure(&mut encoder)?;
 
-            encoder.set_param(key, value)?;
+        Ok(encoder)
+    }
+
+    /// Read all bytes from `input` and compress them into `output` using these options, returning
+    /// how many bytes were written.
+    ///
+    /// Unlike [`crate::compress`], this honors every option, including block size, large window
+    /// sizes and prepared dictionaries. The compressed `input` must fit into `output`, otherwise an
+    /// error is returned. If `output` is at least as large as the bound returned by
+    /// [`compress_bound`], compression is guaranteed to succeed, even for qualities lower than `2`,
+    /// as the input is stored uncompressed if it does not compress well enough. This guarantee
+    /// does not hold if a [`stream_offset`] is set.
+    ///
+    /// # Errors
+    ///
+    /// An [`Err`] will be returned if:
+    ///
+    /// * `output` is not large enough to contain the compressed data
+    /// * the options are invalid
+    /// * A generic compression error occurs
+    /// * memory allocation failed
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use brotlic::{compress_bound, BlockSize, BrotliEncoderOptions, LargeWindowSize, Quality};
+    ///
+    /// let input = vec![0; 1024];
+    /// let mut output = vec![0; compress_bound(input.len(), Quality::best()).unwrap()];
+    ///
+    /// let bytes_written = BrotliEncoderOptions::new()
+    ///     .large_window_size(LargeWindowSize::new(26)?)
+    ///     .block_size(BlockSize::new(20)?)
+    ///     .compress(input.as_slice(), output.as_mut_slice())?;
+    ///
+    /// assert!(bytes_written < input.len());
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    ///
+    /// [`compress_bound`]: crate::compress_bound
+    /// [`stream_offset`]: Self::stream_offset
+    pub fn compress(&self, input: &[u8], output: &mut [u8]) -> Result<usize, CompressError> {
+        let mut encoder = self.build_one_shot(input.len())?;
+        let mut remaining = input;
+        let mut bytes_written = 0;
+
+        while !encoder.is_finished() {
+            let op = BrotliOperation::Finish;
+            let res = encoder
+                .compress(remaining, &mut output[bytes_written..], op)
+                .map_err(|_| CompressError)?;
+            remaining = &remaining[res.bytes_read..];
+            bytes_written += res.bytes_written;
+
+            if res.bytes_read == 0 && res.bytes_written == 0 {
+                return self.compress_uncompressed(input, output);
+            }
+        }
+
+        Ok(bytes_written)
+    }
+
+    /// Read all bytes from `input` and compress them into a newly allocated [`Vec`] using these
+    /// options.
+    ///
+    /// This is the allocating counterpart of [`Self::compress`] and offers the same guarantees:
+    /// the returned [`Vec`] is never larger than the bound returned by [`compress_bound`], unless a
+    /// [`stream_offset`] is set.
+    ///
+    /// # Errors
+    ///
+    /// An [`Err`] will be returned if the options are invalid, a generic compression error occurs
+    /// or memory allocation failed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use brotlic::{BrotliEncoderOptions, CompressionMode, Quality};
+    ///
+    /// let input = b"hello hello hello hello";
+    /// let compressed = BrotliEncoderOptions::new()
+    ///     .quality(Quality::new(5)?)
+    ///     .mode(CompressionMode::Text)
+    ///     .compress_to_vec(input)?;
+    ///
+    /// let mut decompressed = vec![0; input.len()];
+    /// let bytes_written = brotlic::decompress(&compressed, &mut decompressed)?;
+    ///
+    /// assert_eq!(&decompressed[..bytes_written], input);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    ///
+    /// [`compress_bound`]: crate::compress_bound
+    /// [`stream_offset`]: Self::stream_offset
+    pub fn compress_to_vec(&self, input: &[u8]) -> Result<Vec<u8>, CompressError> {
+        let mut encoder = self.build_one_shot(input.len())?;
+        let mut output = Vec::new();
+        let mut remaining = input;
+
+        while !encoder.is_finished() {
+            let bytes_read = encoder
+                .give_input(remaining, BrotliOperation::Finish)
+                .map_err(|_| CompressError)?;
+            remaining = &remaining[bytes_read..];
+
+            while let Some(chunk) = encoder.next_output() {
+                output.extend_from_slice(chunk);
+            }
+        }
+
+        let bound = unsafe { BrotliEncoderMaxCompressedSize(input.len()) };
+
+        if output.len() > bound && self.stream_offset.unwrap_or(0) == 0 {
+            output.resize(bound, 0);
+            let bytes_written = make_uncompressed_stream(input, &mut output);
+            output.truncate(bytes_written);
+        }
+
+        Ok(output)
+    }
+
+    fn build_one_shot(&self, input_size: usize) -> Result<BrotliEncoder, CompressError> {
+        let mut encoder = self.try_build().map_err(|_| CompressError)?;
+
+        if self.size_hint.is_none() {
+            let key = BrotliEncoderParameter_BROTLI_PARAM_SIZE_HINT;
+            let value = input_size.min(1 << 30) as u32;
+
+            encoder.set_param(key, value).map_err(|_| CompressError)?;
+        }
+
+        Ok(encoder)
+    }
+
+    fn compress_uncompressed(
+        &self,
+        input: &[u8],
+        output: &mut [u8],
+    ) -> Result<usize, CompressError> {
+        let bound = unsafe { BrotliEncoderMaxCompressedSize(input.len()) };
+
+        if bound != 0 && output.len() >= bound && self.stream_offset.unwrap_or(0) == 0 {
+            Ok(make_uncompressed_stream(input, output))
+        } else {
+            Err(CompressError)
+        }
+    }
+
+    fn configure(&self, encoder: &mut BrotliEncoder) -> Result<(), SetParameterError> {
+        if let Some(mode) = self.mode {
+            let key = BrotliEncoderParameter_BROTLI_PARAM_MODE;
+            let value = mode as u32;
+
+            encoder.set_param(key, value)?;
+        }
+
+        if let Some(quality) = self.quality {
+            let key = BrotliEncoderParameter_BROTLI_PARAM_QUALITY;
+            let value = quality.0 as u32;
+
+            encoder.set_param(key, value)?;
+        }
+
+        if let Some(window_size) = self.window_size {
+            let key = BrotliEncoderParameter_BROTLI_PARAM_LGWIN;
+            let value = window_size.0 as u32;
+
+            encoder.set_param(key, value)?;
 
             let large_window = WindowSize::try_from(window_size).is_err();
 
@@ -527,10 +1235,30 @@ impl BrotliEncoderOptions {
             encoder.set_param(key, value)?;
         }
 
+        for dictionary in &self.dictionaries {
+            encoder.attach_prepared_dictionary(dictionary.clone())?;
+        }
+
+        #[cfg(feature = "checksum")]
+        {
+            encoder.checksum = self.checksum;
+        }
+
         Ok(())
     }
 }
 
+/// Unwraps the result of a fallible build, panicking if the encoder failed to be allocated.
+fn unwrap_alloc(
+    res: Result<BrotliEncoder, BuildError>,
+) -> Result<BrotliEncoder, SetParameterError> {
+    match res {
+        Ok(encoder) => Ok(encoder),
+        Err(BuildError::SetParameter(err)) => Err(err),
+        Err(BuildError::Alloc(err)) => panic!("BrotliEncoderCreateInstance returned NULL: {}", err),
+    }
+}
+
 impl Default for BrotliEncoderOptions {
     fn default() -> Self {
         BrotliEncoderOptions::new()
@@ -560,7 +1288,25 @@ impl fmt::Display for EncodeError {
 
 impl From<EncodeError> for io::Error {
     fn from(err: EncodeError) -> Self {
-        io::Error::new(io::ErrorKind::Other, err)
+        io::Error::other(err)
+    }
+}
+
+/// An error returned by [`PreparedDictionary::new`] and [`PreparedDictionary::new_in`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct PrepareDictionaryError;
+
+impl Error for PrepareDictionaryError {}
+
+impl fmt::Display for PrepareDictionaryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("dictionary is invalid or failed to be prepared")
+    }
+}
+
+impl From<PrepareDictionaryError> for io::Error {
+    fn from(err: PrepareDictionaryError) -> Self {
+        io::Error::other(err)
     }
 }
 
@@ -594,8 +1340,7 @@ impl From<EncodeError> for io::Error {
 #[derive(Debug)]
 pub struct CompressorReader<R: BufRead> {
     inner: R,
-    encoder: BrotliEncoder,
-    op: BrotliOperation,
+    state: ReaderState,
 }
 
 impl<R: BufRead> CompressorReader<R> {
@@ -603,29 +1348,45 @@ impl<R: BufRead> CompressorReader<R> {
     ///
     /// # Panics
     ///
-    /// Panics if the encoder fails to be allocated or initialized
+    /// Panics if the encoder fails to be allocated or initialized, see [`Self::try_new`] for a
+    /// fallible alternative.
     pub fn new(inner: R) -> Self {
-        CompressorReader {
-            inner,
-            encoder: BrotliEncoder::new(),
-            op: BrotliOperation::Process,
-        }
+        CompressorReader::with_encoder(BrotliEncoder::new(), inner)
     }
 
     /// Creates a new `CompressorReader<R>` with a newly created encoder using allocator `alloc`.
     ///
     /// # Panics
     ///
-    /// Panics if the encoder fails to be allocated or initialized
+    /// Panics if the encoder fails to be allocated or initialized, see [`Self::try_new_in`] for
+    /// a fallible alternative.
     pub fn new_in<A>(inner: R, alloc: A) -> Self
     where
         A: GlobalAlloc + 'static,
     {
-        CompressorReader {
-            inner,
-            encoder: BrotliEncoder::new_in(alloc),
-            op: BrotliOperation::Process,
-        }
+        CompressorReader::with_encoder(BrotliEncoder::new_in(alloc), inner)
+    }
+
+    /// Attempts to create a new `CompressorReader<R>` with a newly created encoder.
+    ///
+    /// # Errors
+    ///
+    /// An [`AllocError`] will be returned if the encoder fails to be allocated or initialized.
+    pub fn try_new(inner: R) -> Result<Self, AllocError> {
+        Ok(CompressorReader::with_encoder(BrotliEncoder::try_new()?, inner))
+    }
+
+    /// Attempts to create a new `CompressorReader<R>` with a newly created encoder using
+    /// allocator `alloc`.
+    ///
+    /// # Errors
+    ///
+    /// An [`AllocError`] will be returned if the encoder fails to be allocated or initialized.
+    pub fn try_new_in<A>(inner: R, alloc: A) -> Result<Self, AllocError>
+    where
+        A: GlobalAlloc + 'static,
+    {
+        Ok(CompressorReader::with_encoder(BrotliEncoder::try_new_in(alloc)?, inner))
     }
 
     /// Creates a new `CompressorReader<R>` with a specified encoder.
@@ -647,8 +1408,7 @@ impl<R: BufRead> CompressorReader<R> {
     pub fn with_encoder(encoder: BrotliEncoder, inner: R) -> Self {
         CompressorReader {
             inner,
-            encoder,
-            op: BrotliOperation::Process,
+            state: ReaderState::new(encoder),
         }
     }
 
@@ -670,7 +1430,7 @@ impl<R: BufRead> CompressorReader<R> {
     ///
     /// An [`Err`] will be returned if the compression stream has not been finished.
     pub fn into_inner(self) -> Result<R, IntoInnerError<CompressorReader<R>>> {
-        if self.encoder.is_finished() {
+        if self.state.encoder().is_finished() {
             Ok(self.inner)
         } else {
             Err(IntoInnerError::new(
@@ -685,36 +1445,559 @@ impl<R: BufRead> CompressorReader<R> {
     /// `into_parts` makes no attempt to validate that the compression stream finished and cannot
     /// fail.
     pub fn into_parts(self) -> (R, BrotliEncoder) {
-        (self.inner, self.encoder)
+        (self.inner, self.state.into_encoder())
     }
 }
 
 impl<R: BufRead> Read for CompressorReader<R> {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let buffer = self.state.buffer();
+
+        if !buffer.is_empty() {
+            let len = buffer.len().min(buf.len());
+            buf[..len].copy_from_slice(&buffer[..len]);
+            self.state.consume(len);
+
+            return Ok(len);
+        }
+
         loop {
-            let input = self.inner.fill_buf()?;
-            let eof = input.is_empty();
-            let EncodeResult {
-                bytes_read,
-                bytes_written,
-            } = self.encoder.compress(input, buf, self.op)?;
+            let input = match self.state.needs_input() {
+                true => self.inner.fill_buf()?,
+                false => &[],
+            };
+
+            let (bytes_read, bytes_written) = self.state.read(input, buf)?;
             self.inner.consume(bytes_read);
 
-            match self.op {
-                _ if bytes_written > 0 => return Ok(bytes_written),
-                _ if buf.is_empty() => return Ok(0),
-                _ if !eof => continue,
-                BrotliOperation::Process => {
-                    self.op = BrotliOperation::Finish;
-                    continue;
-                }
-                BrotliOperation::Finish => return Ok(0),
-                _ => unreachable!(),
+            if let Some(bytes_written) = bytes_written {
+                return Ok(bytes_written);
             }
         }
     }
 }
 
+/// Reads compressed data directly from the internal output buffer of the encoder, without
+/// copying it.
+///
+/// # Examples
+///
+/// ```
+/// use std::io::{BufRead, Read};
+/// use brotlic::{CompressorReader, DecompressorReader};
+///
+/// let input = b"first line\nsecond line\n";
+/// let mut compressor = CompressorReader::new(input.as_slice());
+/// let mut compressed = Vec::new();
+///
+/// loop {
+///     let buf = compressor.fill_buf()?;
+///
+///     if buf.is_empty() {
+///         break;
+///     }
+///
+///     let len = buf.len();
+///     compressed.extend_from_slice(buf);
+///     compressor.consume(len);
+/// }
+///
+/// // a compressor can be used as the input of a decompressor directly
+/// let decompressor = DecompressorReader::new(CompressorReader::new(input.as_slice()));
+/// let lines = decompressor.lines().collect::<Result<Vec<_>, _>>()?;
+///
+/// assert_eq!(lines, ["first line", "second line"]);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+impl<R: BufRead> BufRead for CompressorReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        loop {
+            let input = match self.state.needs_input() {
+                true => self.inner.fill_buf()?,
+                false => &[],
+            };
+
+            let (bytes_read, filled) = self.state.fill(input)?;
+            self.inner.consume(bytes_read);
+
+            if filled {
+                return Ok(self.state.buffer());
+            }
+        }
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.state.consume(amt);
+    }
+}
+
+/// The checksum of the data compressed by a compressor wrapper, if enabled by
+/// [`BrotliEncoderOptions::checksum`], and the metadata block recording it that is yet to be
+/// emitted.
+#[derive(Debug, Default)]
+pub(crate) struct ChecksumState {
+    #[cfg(feature = "checksum")]
+    writer: Option<Box<ChecksumWriter>>,
+    record: Option<Vec<u8>>,
+}
+
+#[cfg(feature = "checksum")]
+impl ChecksumState {
+    pub(crate) fn new(encoder: &BrotliEncoder) -> Self {
+        ChecksumState::with_options(encoder.checksum)
+    }
+
+    /// Removes the checksum option from `options`, for wrappers that compute checksums across
+    /// several encoders.
+    pub(crate) fn take(options: &mut BrotliEncoderOptions) -> Self {
+        ChecksumState::with_options(options.checksum.take())
+    }
+
+    /// Queues the record of no data, which declares the algorithm at the start of the stream.
+    fn with_options(checksum: Option<(Checksum, ChecksumMode)>) -> Self {
+        let mut writer =
+            checksum.map(|(checksum, mode)| Box::new(ChecksumWriter::new(checksum, mode)));
+        let record = writer.as_mut().and_then(|writer| writer.record());
+
+        ChecksumState { writer, record }
+    }
+
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        if let Some(writer) = &mut self.writer {
+            writer.update(data);
+        }
+    }
+
+    /// Queues the record of the checksum if completing the stream with `op` requires one, which
+    /// is the case when finishing and, in [`ChecksumMode::Flush`], when flushing.
+    pub(crate) fn finish_op(&mut self, op: BrotliOperation) {
+        let writer = match &mut self.writer {
+            Some(writer) if self.record.is_none() => writer,
+            _ => return,
+        };
+
+        self.record = match op {
+            BrotliOperation::Finish => writer.record(),
+            BrotliOperation::Flush if writer.mode() == ChecksumMode::Flush => writer.record(),
+            _ => None,
+        };
+    }
+}
+
+#[cfg(not(feature = "checksum"))]
+impl ChecksumState {
+    pub(crate) fn new(_encoder: &BrotliEncoder) -> Self {
+        ChecksumState::default()
+    }
+
+    pub(crate) fn take(_options: &mut BrotliEncoderOptions) -> Self {
+        ChecksumState::default()
+    }
+
+    pub(crate) fn update(&mut self, _data: &[u8]) {}
+
+    pub(crate) fn finish_op(&mut self, _op: BrotliOperation) {}
+}
+
+impl ChecksumState {
+    /// Takes the queued record, for wrappers that emit it themselves.
+    pub(crate) fn take_record(&mut self) -> Option<Vec<u8>> {
+        self.record.take()
+    }
+
+    /// Returns the record of the checksum if completing the stream with `op` requires one, for
+    /// wrappers that emit it themselves.
+    pub(crate) fn record_for(&mut self, op: BrotliOperation) -> Option<Vec<u8>> {
+        self.finish_op(op);
+        self.take_record()
+    }
+
+    /// Returns if a record is queued, which must be emitted before any more data is compressed
+    /// or the stream is completed.
+    pub(crate) fn is_pending(&self) -> bool {
+        self.record.is_some()
+    }
+
+    /// Advances emitting the queued record as a metadata block, compressing it into `output` if
+    /// given or into the internal output buffer of `encoder` otherwise. Returns the number of
+    /// bytes written to `output`.
+    pub(crate) fn emit(
+        &mut self,
+        encoder: &mut BrotliEncoder,
+        output: Option<&mut [u8]>,
+    ) -> io::Result<usize> {
+        let record = match &mut self.record {
+            Some(record) => record,
+            None => return Ok(0),
+        };
+
+        let (bytes_read, bytes_written) = match output {
+            Some(output) => {
+                let res = encoder.compress(record, output, BrotliOperation::EmitMetadata)?;
+                (res.bytes_read, res.bytes_written)
+            }
+            None => {
+                let bytes_read = encoder.give_input(record, BrotliOperation::EmitMetadata)?;
+                (bytes_read, 0)
+            }
+        };
+        record.drain(..bytes_read);
+
+        // emitting metadata is complete once the encoder has no more output
+        if record.is_empty() && !encoder.has_output() {
+            self.record = None;
+        }
+
+        Ok(bytes_written)
+    }
+}
+
+/// The state machine of a compressing reader, shared by [`CompressorReader`] and the async
+/// readers.
+#[derive(Debug)]
+pub(crate) struct ReaderState {
+    encoder: BrotliEncoder,
+    op: BrotliOperation,
+    checksum: ChecksumState,
+    buffer: OutputBuffer,
+}
+
+impl ReaderState {
+    pub(crate) fn new(encoder: BrotliEncoder) -> Self {
+        ReaderState {
+            checksum: ChecksumState::new(&encoder),
+            encoder,
+            op: BrotliOperation::Process,
+            buffer: OutputBuffer::default(),
+        }
+    }
+
+    pub(crate) fn encoder(&self) -> &BrotliEncoder {
+        &self.encoder
+    }
+
+    pub(crate) fn into_encoder(self) -> BrotliEncoder {
+        self.encoder
+    }
+
+    /// Returns the output taken from the encoder that has not been consumed yet.
+    pub(crate) fn buffer(&self) -> &[u8] {
+        self.buffer.get()
+    }
+
+    pub(crate) fn consume(&mut self, amt: usize) {
+        self.buffer.consume(amt);
+    }
+
+    /// Returns if the next call to [`Self::read`] or [`Self::fill`] reads from its input. If not,
+    /// the input need not be filled.
+    pub(crate) fn needs_input(&self) -> bool {
+        !self.checksum.is_pending()
+    }
+
+    /// Compresses `input` into `buf`, where an empty `input` signals the end of the input. Returns
+    /// the number of bytes read from `input` and, once the read is complete, the number of bytes
+    /// written to `buf`. If the read is incomplete, this must be called again with refilled input.
+    pub(crate) fn read(
+        &mut self,
+        input: &[u8],
+        buf: &mut [u8],
+    ) -> io::Result<(usize, Option<usize>)> {
+        if self.checksum.is_pending() {
+            if buf.is_empty() {
+                return Ok((0, Some(0)));
+            }
+
+            return match self.checksum.emit(&mut self.encoder, Some(buf))? {
+                0 => Ok((0, None)),
+                bytes_written => Ok((0, Some(bytes_written))),
+            };
+        }
+
+        let eof = input.is_empty();
+        let EncodeResult {
+            bytes_read,
+            bytes_written,
+        } = self.encoder.compress(input, buf, self.op)?;
+        self.checksum.update(&input[..bytes_read]);
+
+        let bytes_written = match self.op {
+            _ if bytes_written > 0 => Some(bytes_written),
+            _ if buf.is_empty() => Some(0),
+            _ if !eof => None,
+            BrotliOperation::Process => {
+                self.op = BrotliOperation::Finish;
+                self.checksum.finish_op(self.op);
+                None
+            }
+            BrotliOperation::Finish => Some(0),
+            _ => unreachable!(),
+        };
+
+        Ok((bytes_read, bytes_written))
+    }
+
+    /// Compresses `input` into the internal output buffer of the encoder, where an empty `input`
+    /// signals the end of the input. Returns the number of bytes read from `input` and whether
+    /// [`Self::buffer`] is filled, which it is not only at the end of the stream. If the buffer is
+    /// not filled yet, this must be called again with refilled input.
+    pub(crate) fn fill(&mut self, input: &[u8]) -> io::Result<(usize, bool)> {
+        if !self.buffer().is_empty() {
+            return Ok((0, true));
+        }
+
+        let eof = input.is_empty();
+        let bytes_read = match self.op {
+            _ if self.encoder.has_output() => 0,
+            _ if self.checksum.is_pending() => {
+                self.checksum.emit(&mut self.encoder, None)?;
+                0
+            }
+            BrotliOperation::Process if eof => {
+                self.op = BrotliOperation::Finish;
+                self.checksum.finish_op(self.op);
+
+                return Ok((0, false));
+            }
+            _ => {
+                let bytes_read = self.encoder.give_input(input, self.op)?;
+                self.checksum.update(&input[..bytes_read]);
+
+                bytes_read
+            }
+        };
+
+        // SAFETY: the output remains valid until the encoder is used again, which only happens
+        // once the buffer is empty
+        if let Some(output) = unsafe { self.encoder.take_output() } {
+            unsafe { self.buffer.set(output) };
+
+            return Ok((bytes_read, true));
+        }
+
+        Ok((bytes_read, self.encoder.is_finished()))
+    }
+}
+
+/// The state machine of an async compressing writer.
+///
+/// Unlike [`CompressorWriter`], an async writer may only write part of the encoder output at a
+/// time, so output is compressed into a buffer from which it is written as the writer allows.
+#[cfg(any(feature = "tokio", feature = "futures-io"))]
+#[derive(Debug)]
+pub(crate) struct AsyncWriterState {
+    encoder: BrotliEncoder,
+    op: BrotliOperation,
+    done: bool,
+    checksum: ChecksumState,
+    output: Box<[u8]>,
+    pos: usize,
+    len: usize,
+}
+
+#[cfg(any(feature = "tokio", feature = "futures-io"))]
+impl AsyncWriterState {
+    const OUTPUT_SIZE: usize = 32 * 1024;
+
+    pub(crate) fn new(encoder: BrotliEncoder) -> Self {
+        AsyncWriterState {
+            checksum: ChecksumState::new(&encoder),
+            encoder,
+            op: BrotliOperation::Process,
+            done: false,
+            output: vec![0; Self::OUTPUT_SIZE].into_boxed_slice(),
+            pos: 0,
+            len: 0,
+        }
+    }
+
+    pub(crate) fn encoder(&self) -> &BrotliEncoder {
+        &self.encoder
+    }
+
+    pub(crate) fn into_encoder(self) -> BrotliEncoder {
+        self.encoder
+    }
+
+    /// Returns the output that is yet to be written.
+    pub(crate) fn pending(&self) -> &[u8] {
+        &self.output[self.pos..self.len]
+    }
+
+    /// Marks `amt` bytes of the pending output as written.
+    pub(crate) fn consume(&mut self, amt: usize) {
+        self.pos += amt;
+    }
+
+    /// Compresses `input`, returning the number of bytes read from it. Nothing is read while the
+    /// checksum declared at the start of the stream is pending output. Must only be called once
+    /// all pending output was written.
+    pub(crate) fn write(&mut self, input: &[u8]) -> io::Result<usize> {
+        self.pos = 0;
+
+        while self.checksum.is_pending() {
+            self.len = self.checksum.emit(&mut self.encoder, Some(&mut self.output))?;
+
+            if self.len > 0 {
+                return Ok(0);
+            }
+        }
+
+        let EncodeResult {
+            bytes_read,
+            bytes_written,
+        } = self
+            .encoder
+            .compress(input, &mut self.output, BrotliOperation::Process)?;
+        self.checksum.update(&input[..bytes_read]);
+
+        self.op = BrotliOperation::Process;
+        self.done = false;
+        self.pos = 0;
+        self.len = bytes_written;
+
+        Ok(bytes_read)
+    }
+
+    /// Advances flushing the compression stream. Returns `true` once the flush is complete, which
+    /// is only the case when all pending output was written. Must only be called once all pending
+    /// output was written.
+    pub(crate) fn flush(&mut self) -> io::Result<bool> {
+        self.complete(BrotliOperation::Flush)
+    }
+
+    /// Advances finishing the compression stream. Returns `true` once the stream is finished,
+    /// which is only the case when all pending output was written. Must only be called once all
+    /// pending output was written.
+    pub(crate) fn finish(&mut self) -> io::Result<bool> {
+        self.complete(BrotliOperation::Finish)
+    }
+
+    fn complete(&mut self, op: BrotliOperation) -> io::Result<bool> {
+        if self.op == op && self.done {
+            return Ok(true);
+        }
+
+        if self.op != op {
+            self.op = op;
+            self.done = false;
+            self.checksum.finish_op(op);
+        }
+
+        self.pos = 0;
+
+        if self.checksum.is_pending() {
+            self.len = self.checksum.emit(&mut self.encoder, Some(&mut self.output))?;
+            return Ok(false);
+        }
+
+        self.len = self.encoder.compress(&[], &mut self.output, op)?.bytes_written;
+        self.done = match op {
+            BrotliOperation::Finish => self.encoder.is_finished(),
+            _ => !self.encoder.has_output(),
+        };
+
+        Ok(false)
+    }
+}
+
+/// The state machine of a compressing stream.
+///
+/// Unlike the state of an async writer, output is not compressed into a buffer but taken from the
+/// encoder chunk by chunk, so every chunk is copied exactly once.
+#[cfg(feature = "stream")]
+#[derive(Debug)]
+pub(crate) struct ChunkState {
+    encoder: BrotliEncoder,
+    op: BrotliOperation,
+    done: bool,
+    checksum: ChecksumState,
+}
+
+#[cfg(feature = "stream")]
+impl ChunkState {
+    pub(crate) fn new(encoder: BrotliEncoder) -> Self {
+        ChunkState {
+            checksum: ChecksumState::new(&encoder),
+            encoder,
+            op: BrotliOperation::Process,
+            done: false,
+        }
+    }
+
+    pub(crate) fn encoder(&self) -> &BrotliEncoder {
+        &self.encoder
+    }
+
+    pub(crate) fn into_encoder(self) -> BrotliEncoder {
+        self.encoder
+    }
+
+    /// Takes the next chunk of output from the encoder, or returns `None` if it has no output.
+    pub(crate) fn take_output(&mut self) -> Option<Bytes> {
+        self.encoder.next_output().map(Bytes::copy_from_slice)
+    }
+
+    /// Compresses `input`, returning the number of bytes read from it. Nothing is read while the
+    /// checksum declared at the start of the stream is output to be taken. Must only be called
+    /// once all output was taken.
+    pub(crate) fn write(&mut self, input: &[u8]) -> io::Result<usize> {
+        while self.checksum.is_pending() {
+            self.checksum.emit(&mut self.encoder, None)?;
+
+            if self.encoder.has_output() {
+                return Ok(0);
+            }
+        }
+
+        let bytes_read = self.encoder.give_input(input, BrotliOperation::Process)?;
+        self.checksum.update(&input[..bytes_read]);
+
+        self.op = BrotliOperation::Process;
+        self.done = false;
+
+        Ok(bytes_read)
+    }
+
+    /// Advances flushing the compression stream. Returns `true` once the flush is complete, which
+    /// is only the case when all output was taken. Must only be called once all output was taken.
+    pub(crate) fn flush(&mut self) -> io::Result<bool> {
+        self.complete(BrotliOperation::Flush)
+    }
+
+    /// Advances finishing the compression stream. Returns `true` once the stream is finished,
+    /// which is only the case when all output was taken. Must only be called once all output was
+    /// taken.
+    pub(crate) fn finish(&mut self) -> io::Result<bool> {
+        self.complete(BrotliOperation::Finish)
+    }
+
+    fn complete(&mut self, op: BrotliOperation) -> io::Result<bool> {
+        if self.op != op {
+            self.op = op;
+            self.done = false;
+            self.checksum.finish_op(op);
+        }
+
+        if self.done {
+            return Ok(true);
+        }
+
+        if self.checksum.is_pending() {
+            self.checksum.emit(&mut self.encoder, None)?;
+            return Ok(false);
+        }
+
+        self.encoder.give_input(&[], op)?;
+        self.done = match op {
+            BrotliOperation::Finish => self.encoder.is_finished(),
+            _ => !self.encoder.has_output(),
+        };
+
+        Ok(self.done)
+    }
+}
+
 /// Wraps a writer and compresses its output.
 ///
 /// `CompressorWriter<W>` wraps a writer and adds brotli compression to the output. It is critical
@@ -754,6 +2037,7 @@ pub struct CompressorWriter<W: Write> {
     inner: W,
     encoder: BrotliEncoder,
     panicked: bool,
+    checksum: ChecksumState,
 }
 
 impl<W: Write> CompressorWriter<W> {
@@ -761,12 +2045,14 @@ impl<W: Write> CompressorWriter<W> {
     ///
     /// # Panics
     ///
-    /// Panics if the encoder fails to be allocated or initialized
+    /// Panics if the encoder fails to be allocated or initialized, see [`Self::try_new`] for a
+    /// fallible alternative.
     pub fn new(inner: W) -> Self {
         CompressorWriter {
             inner,
             encoder: BrotliEncoder::new(),
             panicked: false,
+            checksum: ChecksumState::default(),
         }
     }
 
@@ -774,7 +2060,8 @@ impl<W: Write> CompressorWriter<W> {
     ///
     /// # Panics
     ///
-    /// Panics if the encoder fails to be allocated or initialized
+    /// Panics if the encoder fails to be allocated or initialized, see [`Self::try_new_in`] for
+    /// a fallible alternative.
     pub fn new_in<A>(inner: W, alloc: A) -> Self
     where
         A: GlobalAlloc + 'static,
@@ -783,9 +2070,32 @@ impl<W: Write> CompressorWriter<W> {
             inner,
             encoder: BrotliEncoder::new_in(alloc),
             panicked: false,
+            checksum: ChecksumState::default(),
         }
     }
 
+    /// Attempts to create a new `CompressorWriter<W>` with a newly created encoder.
+    ///
+    /// # Errors
+    ///
+    /// An [`AllocError`] will be returned if the encoder fails to be allocated or initialized.
+    pub fn try_new(inner: W) -> Result<Self, AllocError> {
+        Ok(CompressorWriter::with_encoder(BrotliEncoder::try_new()?, inner))
+    }
+
+    /// Attempts to create a new `CompressorWriter<W>` with a newly created encoder using
+    /// allocator `alloc`.
+    ///
+    /// # Errors
+    ///
+    /// An [`AllocError`] will be returned if the encoder fails to be allocated or initialized.
+    pub fn try_new_in<A>(inner: W, alloc: A) -> Result<Self, AllocError>
+    where
+        A: GlobalAlloc + 'static,
+    {
+        Ok(CompressorWriter::with_encoder(BrotliEncoder::try_new_in(alloc)?, inner))
+    }
+
     /// Creates a new `CompressorWriter<W>` with a specified encoder.
     ///
     /// # Examples
@@ -805,6 +2115,7 @@ impl<W: Write> CompressorWriter<W> {
     pub fn with_encoder(encoder: BrotliEncoder, inner: W) -> Self {
         CompressorWriter {
             inner,
+            checksum: ChecksumState::new(&encoder),
             encoder,
             panicked: false,
         }
@@ -850,6 +2161,7 @@ impl<W: Write> CompressorWriter<W> {
         let inner = unsafe { ptr::read(&self.inner) };
         let encoder = unsafe { ptr::read(&self.encoder) };
         let panicked = self.panicked;
+        drop(unsafe { ptr::read(&self.checksum) });
         mem::forget(self);
 
         let encoder = if !panicked {
@@ -861,32 +2173,93 @@ impl<W: Write> CompressorWriter<W> {
         (inner, encoder)
     }
 
+    /// Writes `metadata` as a metadata block into the compression stream.
+    ///
+    /// All data written so far is flushed to the underlying writer before the metadata block is
+    /// emitted. Metadata does not affect the decompressed output and is skipped by decoders,
+    /// so the stream can still be decompressed by any brotli decoder. For more information see
+    /// [`BrotliEncoder::emit_metadata`].
+    ///
+    /// # Errors
+    ///
+    /// An [`Err`] of kind [`io::ErrorKind::InvalidInput`] will be returned if `metadata` exceeds
+    /// [`MAX_METADATA_SIZE`]. Any error from the encoder or the underlying writer is returned as
+    /// well.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::{Read, Write};
+    /// use brotlic::{CompressorWriter, DecompressorReader};
+    ///
+    /// let mut compressor = CompressorWriter::new(Vec::new());
+    /// compressor.write_metadata(b"application header")?;
+    /// compressor.write_all(b"hello world")?;
+    /// let compressed = compressor.into_inner()?;
+    ///
+    /// let mut decompressor = DecompressorReader::new(compressed.as_slice());
+    /// let mut decompressed = String::new();
+    /// decompressor.read_to_string(&mut decompressed)?;
+    ///
+    /// assert_eq!(decompressed, "hello world");
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn write_metadata(&mut self, metadata: &[u8]) -> io::Result<()> {
+        let inner = &mut self.inner;
+        let panicked = &mut self.panicked;
+
+        self.encoder.write_metadata(metadata, |output| {
+            *panicked = true;
+            let r = inner.write_all(output);
+            *panicked = false;
+            r
+        })
+    }
+
     fn finish(&mut self) -> io::Result<()> {
+        if !self.encoder.is_finished() {
+            self.checksum.finish_op(BrotliOperation::Finish);
+            self.emit_checksum()?;
+        }
+
         self.encoder.finish()?;
         self.flush_encoder_output()
     }
 
+    fn emit_checksum(&mut self) -> io::Result<()> {
+        while self.checksum.is_pending() {
+            self.checksum.emit(&mut self.encoder, None)?;
+            self.flush_encoder_output()?;
+        }
+
+        Ok(())
+    }
+
     fn flush_encoder_output(&mut self) -> io::Result<()> {
-        while let Some(output) = unsafe { self.encoder.take_output() } {
+        self.encoder.drain_output(|output| {
             self.panicked = true;
             let r = self.inner.write_all(output);
             self.panicked = false;
-            r?;
-        }
-
-        Ok(())
+            r
+        })
     }
 }
 
 impl<W: Write> Write for CompressorWriter<W> {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.emit_checksum()?;
+
         let bytes_read = self.encoder.give_input(buf, BrotliOperation::Process)?;
+        self.checksum.update(&buf[..bytes_read]);
         self.flush_encoder_output()?;
 
         Ok(bytes_read)
     }
 
     fn flush(&mut self) -> io::Result<()> {
+        self.checksum.finish_op(BrotliOperation::Flush);
+        self.emit_checksum()?;
+
         self.encoder.flush()?;
         self.flush_encoder_output()?;
 
@@ -960,6 +2333,43 @@ mod tests {
         assert_eq!(invalid.unwrap_err(), SetParameterError::InvalidBlockSize);
     }
 
+    #[test]
+    fn estimate_peak_memory() {
+        let estimate = |quality, window_bits, block_bits: Option<u8>| {
+            let mut options = BrotliEncoderOptions::new();
+            options
+                .quality(Quality::new(quality).unwrap())
+                .window_size(WindowSize::new(window_bits).unwrap());
+
+            if let Some(block_bits) = block_bits {
+                options.block_size(BlockSize::new(block_bits).unwrap());
+            }
+
+            options.estimate_peak_memory(1 << 24)
+        };
+
+        assert!(estimate(5, 22, None) < estimate(11, 22, None));
+        assert!(estimate(11, 16, None) < estimate(11, 22, None));
+        assert!(estimate(5, 22, Some(16)) < estimate(5, 22, Some(24)));
+        assert_eq!(estimate(5, 22, None), estimate(5, 22, Some(16)));
+        assert_eq!(estimate(1, 22, None), estimate(1, 22, Some(24)));
+    }
+
+    #[test]
+    fn estimate_peak_memory_with_dictionary() {
+        let dictionary =
+            PreparedDictionary::new(DictionaryType::Raw, vec![7; 4096], Quality::best());
+        let dictionary = Arc::new(dictionary.unwrap());
+
+        let without = BrotliEncoderOptions::new().estimate_peak_memory(4096);
+        let with = BrotliEncoderOptions::new()
+            .prepared_dictionary(dictionary.clone())
+            .estimate_peak_memory(4096);
+
+        assert!(dictionary.size() > 0);
+        assert_eq!(with, without + dictionary.size());
+    }
+
     #[test]
     fn valid_stream_offset() {
         let res = BrotliEncoderOptions::new().stream_offset(1 << 30).build();
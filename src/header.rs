@@ -0,0 +1,319 @@
+//! Module that contains a self-describing header for brotli streams
+//!
+//! Unlike gzip, brotli has no header to store the original name, modification time or size of
+//! the compressed data. This module provides a [`Header`] that is carried within the first
+//! metadata block of a brotli stream. Since decoders skip metadata blocks, streams with a header
+//! can still be decompressed by any brotli decoder.
+//!
+//! A header is written with a [`HeaderBuilder`] and read back with
+//! [`DecompressorReader::header`].
+//!
+//! [`DecompressorReader::header`]: crate::decode::DecompressorReader::header
+
+use std::error::Error;
+use std::io::{self, Write};
+use std::{fmt, str};
+
+use crate::encode::{BrotliEncoder, CompressorWriter};
+
+const MAGIC: &[u8; 4] = b"BRHD";
+const VERSION: u8 = 1;
+
+const TAG_FILENAME: u8 = 1;
+const TAG_MTIME: u8 = 2;
+const TAG_MODE: u8 = 3;
+const TAG_UNCOMPRESSED_LEN: u8 = 4;
+const TAG_ENTRY: u8 = 5;
+
+/// A self-describing header of a brotli stream.
+///
+/// The header is stored in the first metadata block of the stream. It is written by
+/// [`HeaderBuilder`] and read by [`DecompressorReader::header`].
+///
+/// [`DecompressorReader::header`]: crate::decode::DecompressorReader::header
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Header {
+    filename: Option<Vec<u8>>,
+    mtime: Option<u64>,
+    mode: Option<u32>,
+    uncompressed_len: Option<u64>,
+    entries: Vec<(String, Vec<u8>)>,
+}
+
+impl Header {
+    /// Returns the filename of the compressed data, if present.
+    pub fn filename(&self) -> Option<&[u8]> {
+        self.filename.as_deref()
+    }
+
+    /// Returns the modification time of the compressed data in seconds since the Unix epoch, if
+    /// present.
+    pub fn mtime(&self) -> Option<u64> {
+        self.mtime
+    }
+
+    /// Returns the file mode of the compressed data, if present.
+    pub fn mode(&self) -> Option<u32> {
+        self.mode
+    }
+
+    /// Returns the length of the data when decompressed, if present.
+    pub fn uncompressed_len(&self) -> Option<u64> {
+        self.uncompressed_len
+    }
+
+    /// Returns the value of the first entry with the given `key`, if present.
+    pub fn get(&self, key: &str) -> Option<&[u8]> {
+        self.entries
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_slice())
+    }
+
+    /// Returns an iterator over all key/value entries in the order they were added.
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &[u8])> {
+        self.entries.iter().map(|(k, v)| (k.as_str(), v.as_slice()))
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+
+        if let Some(filename) = &self.filename {
+            write_field(&mut bytes, TAG_FILENAME, filename);
+        }
+
+        if let Some(mtime) = self.mtime {
+            write_field(&mut bytes, TAG_MTIME, &mtime.to_le_bytes());
+        }
+
+        if let Some(mode) = self.mode {
+            write_field(&mut bytes, TAG_MODE, &mode.to_le_bytes());
+        }
+
+        if let Some(uncompressed_len) = self.uncompressed_len {
+            write_field(
+                &mut bytes,
+                TAG_UNCOMPRESSED_LEN,
+                &uncompressed_len.to_le_bytes(),
+            );
+        }
+
+        for (key, value) in &self.entries {
+            let mut entry = Vec::with_capacity(4 + key.len() + value.len());
+            entry.extend_from_slice(&(key.len() as u32).to_le_bytes());
+            entry.extend_from_slice(key.as_bytes());
+            entry.extend_from_slice(value);
+
+            write_field(&mut bytes, TAG_ENTRY, &entry);
+        }
+
+        bytes
+    }
+
+    /// Parses a header from the contents of a metadata block. Returns `Ok(None)` if the metadata
+    /// block does not contain a header.
+    pub(crate) fn parse(bytes: &[u8]) -> Result<Option<Header>, HeaderError> {
+        let mut bytes = match bytes.strip_prefix(MAGIC) {
+            Some(bytes) => bytes,
+            None => return Ok(None),
+        };
+
+        match take(&mut bytes, 1)? {
+            [VERSION] => {}
+            _ => return Err(HeaderError),
+        }
+
+        let mut header = Header::default();
+
+        while !bytes.is_empty() {
+            let tag = take(&mut bytes, 1)?[0];
+            let len = u32::from_le_bytes(take_array(&mut bytes)?) as usize;
+            let mut value = take(&mut bytes, len)?;
+
+            match tag {
+                TAG_FILENAME => header.filename = Some(value.to_vec()),
+                TAG_MTIME => header.mtime = Some(u64::from_le_bytes(exact_array(value)?)),
+                TAG_MODE => header.mode = Some(u32::from_le_bytes(exact_array(value)?)),
+                TAG_UNCOMPRESSED_LEN => {
+                    header.uncompressed_len = Some(u64::from_le_bytes(exact_array(value)?))
+                }
+                TAG_ENTRY => {
+                    let key_len = u32::from_le_bytes(take_array(&mut value)?) as usize;
+                    let key = take(&mut value, key_len)?;
+                    let key = str::from_utf8(key).map_err(|_| HeaderError)?;
+
+                    header.entries.push((key.to_owned(), value.to_vec()));
+                }
+                // fields unknown to this version are skipped for forward compatibility
+                _ => {}
+            }
+        }
+
+        Ok(Some(header))
+    }
+}
+
+fn write_field(bytes: &mut Vec<u8>, tag: u8, value: &[u8]) {
+    bytes.push(tag);
+    bytes.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(value);
+}
+
+fn take<'a>(bytes: &mut &'a [u8], len: usize) -> Result<&'a [u8], HeaderError> {
+    if bytes.len() < len {
+        return Err(HeaderError);
+    }
+
+    let (head, tail) = bytes.split_at(len);
+    *bytes = tail;
+
+    Ok(head)
+}
+
+fn take_array<const N: usize>(bytes: &mut &[u8]) -> Result<[u8; N], HeaderError> {
+    exact_array(take(bytes, N)?)
+}
+
+fn exact_array<const N: usize>(bytes: &[u8]) -> Result<[u8; N], HeaderError> {
+    bytes.try_into().map_err(|_| HeaderError)
+}
+
+/// A builder for a [`Header`] that is written as the first metadata block of a
+/// [`CompressorWriter`].
+///
+/// # Examples
+///
+/// ```
+/// use std::io::{Read, Write};
+/// use brotlic::{DecompressorReader, HeaderBuilder};
+///
+/// let mut compressor = HeaderBuilder::new()
+///     .filename("hello.txt")
+///     .mtime(1_650_000_000)
+///     .uncompressed_len(11)
+///     .entry("content-type", "text/plain")
+///     .write(Vec::new())?;
+/// compressor.write_all(b"hello world")?;
+/// let compressed = compressor.into_inner()?;
+///
+/// let mut decompressor = DecompressorReader::new(compressed.as_slice());
+/// let header = decompressor.header()?.unwrap();
+///
+/// assert_eq!(header.filename(), Some(b"hello.txt".as_slice()));
+/// assert_eq!(header.mtime(), Some(1_650_000_000));
+/// assert_eq!(header.get("content-type"), Some(b"text/plain".as_slice()));
+///
+/// let mut decompressed = String::new();
+/// decompressor.read_to_string(&mut decompressed)?;
+///
+/// assert_eq!(decompressed, "hello world");
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct HeaderBuilder {
+    header: Header,
+}
+
+impl HeaderBuilder {
+    /// Creates a new blank header builder.
+    ///
+    /// Initially, the header does not contain any fields.
+    pub fn new() -> Self {
+        HeaderBuilder {
+            header: Header::default(),
+        }
+    }
+
+    /// Sets the filename of the compressed data.
+    pub fn filename<T: Into<Vec<u8>>>(&mut self, filename: T) -> &mut Self {
+        self.header.filename = Some(filename.into());
+        self
+    }
+
+    /// Sets the modification time of the compressed data in seconds since the Unix epoch.
+    pub fn mtime(&mut self, mtime: u64) -> &mut Self {
+        self.header.mtime = Some(mtime);
+        self
+    }
+
+    /// Sets the file mode of the compressed data.
+    pub fn mode(&mut self, mode: u32) -> &mut Self {
+        self.header.mode = Some(mode);
+        self
+    }
+
+    /// Sets the length of the data when decompressed.
+    ///
+    /// Since the header is written before any data, the length is not verified.
+    pub fn uncompressed_len(&mut self, uncompressed_len: u64) -> &mut Self {
+        self.header.uncompressed_len = Some(uncompressed_len);
+        self
+    }
+
+    /// Adds a free-form key/value entry to the header. Entries are kept in the order they were
+    /// added and keys need not be unique.
+    pub fn entry<K, V>(&mut self, key: K, value: V) -> &mut Self
+    where
+        K: Into<String>,
+        V: Into<Vec<u8>>,
+    {
+        self.header.entries.push((key.into(), value.into()));
+        self
+    }
+
+    /// Returns the header that would be written by this builder.
+    pub fn build(&self) -> Header {
+        self.header.clone()
+    }
+
+    /// Creates a new `CompressorWriter<W>` with a newly created encoder and writes the header to
+    /// it.
+    ///
+    /// # Errors
+    ///
+    /// An [`Err`] will be returned if the encoder fails to be allocated or initialized, the header
+    /// does not fit in a metadata block or writing to `inner` failed.
+    pub fn write<W: Write>(&self, inner: W) -> io::Result<CompressorWriter<W>> {
+        self.write_with_encoder(BrotliEncoder::try_new()?, inner)
+    }
+
+    /// Creates a new `CompressorWriter<W>` with a specified encoder and writes the header to it.
+    ///
+    /// # Errors
+    ///
+    /// An [`Err`] will be returned if the header does not fit in a metadata block or writing to
+    /// `inner` failed.
+    pub fn write_with_encoder<W: Write>(
+        &self,
+        encoder: BrotliEncoder,
+        inner: W,
+    ) -> io::Result<CompressorWriter<W>> {
+        let mut compressor = CompressorWriter::with_encoder(encoder, inner);
+        compressor.write_metadata(&self.header.to_bytes())?;
+
+        Ok(compressor)
+    }
+}
+
+/// An error returned by [`DecompressorReader::header`] if the header is malformed.
+///
+/// [`DecompressorReader::header`]: crate::decode::DecompressorReader::header
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct HeaderError;
+
+impl Error for HeaderError {}
+
+impl fmt::Display for HeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("brotli stream header is malformed")
+    }
+}
+
+impl From<HeaderError> for io::Error {
+    fn from(err: HeaderError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
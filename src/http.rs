@@ -0,0 +1,235 @@
+//! Module that contains content negotiation helpers for HTTP
+//!
+//! [`AcceptEncoding`] parses the `Accept-Encoding` request header, including quality values,
+//! wildcards and the exclusion of the `identity` coding, and decides whether a response should be
+//! brotli compressed. [`Preset`] provides encoder options suited to the kind of content that is
+//! served, e.g. a fast quality for responses that are compressed on the fly and the best quality
+//! for static assets that are compressed once.
+//!
+//! This module requires the `http` feature.
+//!
+//! # Examples
+//!
+//! ```
+//! use brotlic::http::{AcceptEncoding, Encoding, Preset};
+//!
+//! let accept_encoding = AcceptEncoding::parse("gzip;q=0.8, br, identity;q=0");
+//! assert_eq!(accept_encoding.negotiate(), Encoding::Brotli);
+//!
+//! let options = accept_encoding.brotli_options(Preset::Dynamic);
+//! assert!(options.is_some());
+//!
+//! let accept_encoding = AcceptEncoding::parse("br;q=0, *");
+//! assert_eq!(accept_encoding.negotiate(), Encoding::Identity);
+//! ```
+
+use ::http::header::{HeaderMap, ACCEPT_ENCODING};
+
+use crate::{BrotliEncoderOptions, Quality, WindowSize};
+
+/// A quality value of an `Accept-Encoding` entry, in thousandths.
+type Weight = u16;
+
+const MAX_WEIGHT: Weight = 1000;
+
+/// The parsed value of an `Accept-Encoding` request header.
+///
+/// Parsing is lenient: entries that are malformed, such as entries with an invalid quality value,
+/// are ignored instead of failing the whole header. Content coding names are matched
+/// case-insensitively.
+///
+/// If a request has no `Accept-Encoding` header, clients are in theory willing to accept any
+/// content coding. In practice however, this is the case for clients that do not support
+/// compression at all, so a missing header is treated like an empty one, which only accepts the
+/// `identity` coding.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct AcceptEncoding {
+    codings: Vec<(String, Weight)>,
+    wildcard: Option<Weight>,
+}
+
+/// The content coding of a response as negotiated by [`AcceptEncoding::negotiate`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Encoding {
+    /// The response should be brotli compressed.
+    Brotli,
+
+    /// The response should not be compressed.
+    Identity,
+
+    /// The client accepts neither brotli nor uncompressed responses, which may be answered with
+    /// `406 Not Acceptable`.
+    NotAcceptable,
+}
+
+impl AcceptEncoding {
+    /// Parses the value of an `Accept-Encoding` header.
+    pub fn parse(value: &str) -> Self {
+        let mut accept_encoding = AcceptEncoding::default();
+        accept_encoding.extend(value);
+        accept_encoding
+    }
+
+    /// Parses all `Accept-Encoding` headers of `headers`.
+    ///
+    /// Multiple headers are combined as if their values were a single comma separated list.
+    /// Values that are not valid UTF-8 are ignored.
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let mut accept_encoding = AcceptEncoding::default();
+
+        for value in headers.get_all(ACCEPT_ENCODING) {
+            if let Ok(value) = value.to_str() {
+                accept_encoding.extend(value);
+            }
+        }
+
+        accept_encoding
+    }
+
+    /// Returns the quality value of `coding` between `0.0` and `1.0`, where `0.0` means that it
+    /// is not acceptable.
+    ///
+    /// A coding that is not listed explicitly takes the quality value of the `*` wildcard, if
+    /// present. The `identity` coding is acceptable unless it is excluded explicitly or by the
+    /// wildcard, all other codings are not acceptable unless listed.
+    pub fn quality(&self, coding: &str) -> f32 {
+        self.weight(coding) as f32 / MAX_WEIGHT as f32
+    }
+
+    /// Returns `true` if `coding` is acceptable, see [`Self::quality`].
+    pub fn accepts(&self, coding: &str) -> bool {
+        self.weight(coding) > 0
+    }
+
+    /// Decides whether a response should be brotli compressed.
+    ///
+    /// Brotli is chosen if it is acceptable, unless an uncompressed response is preferred over it
+    /// explicitly, i.e. by a higher quality value of `identity` or the `*` wildcard. Otherwise, an
+    /// uncompressed response is chosen if acceptable.
+    pub fn negotiate(&self) -> Encoding {
+        let br = self.weight("br");
+        let identity = self.weight("identity");
+
+        // the implicit acceptance of `identity` carries no preference
+        let preferred = match self.listed("identity").or(self.wildcard) {
+            Some(weight) => br >= weight,
+            None => true,
+        };
+
+        if br > 0 && preferred {
+            Encoding::Brotli
+        } else if identity > 0 {
+            Encoding::Identity
+        } else if br > 0 {
+            Encoding::Brotli
+        } else {
+            Encoding::NotAcceptable
+        }
+    }
+
+    /// Returns the encoder options of `preset` if a response should be brotli compressed as
+    /// decided by [`Self::negotiate`], or [`None`] otherwise.
+    pub fn brotli_options(&self, preset: Preset) -> Option<BrotliEncoderOptions> {
+        match self.negotiate() {
+            Encoding::Brotli => Some(preset.options()),
+            _ => None,
+        }
+    }
+
+    fn extend(&mut self, value: &str) {
+        for entry in value.split(',') {
+            let mut params = entry.split(';');
+            let coding = params.next().unwrap_or_default().trim();
+
+            if coding.is_empty() {
+                continue;
+            }
+
+            let mut weight = Some(MAX_WEIGHT);
+
+            for param in params {
+                match param.split_once('=') {
+                    Some((name, value)) if name.trim().eq_ignore_ascii_case("q") => {
+                        weight = parse_weight(value.trim());
+                    }
+                    _ => {}
+                }
+            }
+
+            match weight {
+                Some(weight) if coding == "*" => self.wildcard = Some(weight),
+                Some(weight) => self.codings.push((coding.to_ascii_lowercase(), weight)),
+                None => {}
+            }
+        }
+    }
+
+    fn weight(&self, coding: &str) -> Weight {
+        match (self.listed(coding), self.wildcard) {
+            (Some(weight), _) => weight,
+            (None, Some(weight)) => weight,
+            (None, None) if coding.eq_ignore_ascii_case("identity") => MAX_WEIGHT,
+            (None, None) => 0,
+        }
+    }
+
+    fn listed(&self, coding: &str) -> Option<Weight> {
+        self.codings
+            .iter()
+            .rev()
+            .find(|(name, _)| name.eq_ignore_ascii_case(coding))
+            .map(|&(_, weight)| weight)
+    }
+}
+
+/// Parses a quality value, which has at most three decimal places and ranges from `0` to `1`.
+fn parse_weight(value: &str) -> Option<Weight> {
+    let (int, frac) = value.split_once('.').unwrap_or((value, ""));
+
+    if frac.len() > 3 || !frac.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    let frac = frac.bytes().chain(*b"000").take(3);
+    let frac = frac.fold(0, |acc, digit| acc * 10 + (digit - b'0') as Weight);
+
+    match int {
+        "0" => Some(frac),
+        "1" if frac == 0 => Some(MAX_WEIGHT),
+        _ => None,
+    }
+}
+
+/// The quality of [`Preset::Dynamic`], validated at compile time.
+const DYNAMIC_QUALITY: Quality = match Quality::new(4) {
+    Ok(quality) => quality,
+    Err(_) => panic!("4 is a valid quality"),
+};
+
+/// Encoder option presets for common kinds of HTTP content.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Preset {
+    /// Content that is compressed on the fly for every response, such as rendered pages or API
+    /// responses. Uses quality 4, which compresses better than gzip at a comparable speed.
+    Dynamic,
+
+    /// Content that is compressed once and served many times, such as precompressed static
+    /// assets. Uses the best quality and the largest window size that all browsers support.
+    Static,
+}
+
+impl Preset {
+    /// Returns the encoder options of this preset.
+    pub fn options(self) -> BrotliEncoderOptions {
+        let mut options = BrotliEncoderOptions::new();
+
+        match self {
+            Preset::Dynamic => options.quality(DYNAMIC_QUALITY),
+            Preset::Static => options
+                .quality(Quality::best())
+                .window_size(WindowSize::best()),
+        };
+
+        options
+    }
+}
@@ -28,7 +28,12 @@
 //! | [`CompressorWriter<W>`]   | Uncompressed | Compressed   | [`Write`]   |
 //! | [`DecompressorWriter<W>`] | Compressed   | Uncompressed | [`Write`]   |
 //!
+//! To read from a plain [`Read`], such as a `File`, without wrapping it in a `BufReader` first,
+//! use the readers in the [`read`] module. The [`BufRead`] readers are available from the
+//! [`bufread`] module as well.
+//!
 //! [`BufRead`]: https://doc.rust-lang.org/std/io/trait.BufRead.html
+//! [`Read`]: https://doc.rust-lang.org/std/io/trait.Read.html
 //! [`Write`]: https://doc.rust-lang.org/std/io/trait.Write.html
 //!
 //! To compress a file with brotli:
@@ -113,8 +118,29 @@
 #![deny(warnings)]
 #![deny(missing_docs)]
 
+#[cfg(any(feature = "tokio", feature = "futures-io"))]
+mod async_io;
+pub mod bufread;
+#[cfg(feature = "checksum")]
+pub mod checksum;
 pub mod decode;
 pub mod encode;
+#[cfg(feature = "futures-io")]
+pub mod futures_io;
+pub mod header;
+#[cfg(feature = "http")]
+pub mod http;
+pub mod parallel;
+#[cfg(feature = "precompress")]
+pub mod precompress;
+pub mod read;
+pub mod seekable;
+#[cfg(feature = "stream")]
+pub mod stream;
+#[cfg(feature = "tokio")]
+pub mod tokio;
+#[cfg(feature = "tower")]
+pub mod tower;
 
 pub use encode::BrotliEncoder;
 pub use encode::BrotliEncoderOptions;
@@ -125,13 +151,19 @@ pub use decode::BrotliDecoder;
 pub use decode::BrotliDecoderOptions;
 pub use decode::DecompressorReader;
 pub use decode::DecompressorWriter;
+pub use decode::MultiDecompressorReader;
+
+pub use header::Header;
+pub use header::HeaderBuilder;
 
 use brotlic_sys::*;
 use std::os::raw::{c_int, c_void};
-use std::{fmt, io, ptr};
+use std::{fmt, io, ptr, slice};
 use std::alloc::{GlobalAlloc, Layout};
 use std::error::Error;
 
+use decode::DecoderInfo;
+
 /// Quality level of the brotli compression
 ///
 /// [`Quality::best()`] represents the best available quality that maximizes the compression ratio
@@ -731,6 +763,25 @@ impl Default for CompressionMode {
     }
 }
 
+/// The format of a custom dictionary.
+///
+/// Custom dictionaries improve compression ratio when the input shares a lot of content with data
+/// known in advance by both the encoder and the decoder, e.g. common boilerplate.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum DictionaryType {
+    /// Raw LZ77 prefix dictionary.
+    ///
+    /// Any sequence of bytes can be used, the compressor will reference it as if it preceded the
+    /// input.
+    Raw = BrotliSharedDictionaryType_BROTLI_SHARED_DICTIONARY_RAW as isize,
+
+    /// Serialized shared dictionary, which can also contain custom words and transforms.
+    ///
+    /// Support for serialized dictionaries is experimental in the brotli library. Unless it was
+    /// built with `BROTLI_EXPERIMENTAL`, dictionaries of this type are rejected.
+    Serialized = BrotliSharedDictionaryType_BROTLI_SHARED_DICTIONARY_SERIALIZED as isize,
+}
+
 /// An error returned by [`compress`].
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub struct CompressError;
@@ -745,7 +796,7 @@ impl Error for CompressError {}
 
 impl From<CompressError> for io::Error {
     fn from(err: CompressError) -> Self {
-        io::Error::new(io::ErrorKind::Other, err)
+        io::Error::other(err)
     }
 }
 
@@ -763,7 +814,39 @@ impl Error for DecompressError {}
 
 impl From<DecompressError> for io::Error {
     fn from(err: DecompressError) -> Self {
-        io::Error::new(io::ErrorKind::Other, err)
+        io::Error::other(err)
+    }
+}
+
+/// An error returned by [`decompress_to_vec_with_limit`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum DecompressToVecError {
+    /// The input was corrupted or truncated, or memory allocation failed.
+    Decompress,
+
+    /// The decompressed output would exceed the output limit.
+    OutputLimitExceeded,
+}
+
+impl fmt::Display for DecompressToVecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecompressToVecError::Decompress => f.write_str("decompression error occurred"),
+            DecompressToVecError::OutputLimitExceeded => f.write_str("output limit exceeded"),
+        }
+    }
+}
+
+impl Error for DecompressToVecError {}
+
+impl From<DecompressToVecError> for io::Error {
+    fn from(err: DecompressToVecError) -> Self {
+        match err {
+            DecompressToVecError::Decompress => io::Error::other(err),
+            DecompressToVecError::OutputLimitExceeded => {
+                io::Error::new(io::ErrorKind::InvalidData, err)
+            }
+        }
     }
 }
 
@@ -797,6 +880,12 @@ pub enum SetParameterError {
 
     /// Block size bits were out of range.
     InvalidBlockSize,
+
+    /// The dictionary was rejected by the encoder or decoder.
+    ///
+    /// This happens when the dictionary is corrupted, too many dictionaries were attached or the
+    /// instance already started processing data.
+    InvalidDictionary,
 }
 
 impl fmt::Display for SetParameterError {
@@ -811,12 +900,87 @@ impl fmt::Display for SetParameterError {
             SetParameterError::InvalidQuality => f.write_str("quality out of range"),
             SetParameterError::InvalidWindowSize => f.write_str("window size out of range"),
             SetParameterError::InvalidBlockSize => f.write_str("block size out of range"),
+            SetParameterError::InvalidDictionary => f.write_str("dictionary was rejected"),
         }
     }
 }
 
 impl Error for SetParameterError {}
 
+/// An error returned when an encoder or decoder fails to be allocated or initialized.
+///
+/// This is returned by the fallible constructors, such as [`BrotliEncoder::try_new`] and
+/// [`BrotliDecoder::try_new`], instead of panicking.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct AllocError;
+
+impl fmt::Display for AllocError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("failed to allocate or initialize the brotli instance")
+    }
+}
+
+impl Error for AllocError {}
+
+impl From<AllocError> for io::Error {
+    fn from(err: AllocError) -> Self {
+        io::Error::new(io::ErrorKind::OutOfMemory, err)
+    }
+}
+
+/// An error returned by [`BrotliEncoderOptions::try_build`] and
+/// [`BrotliDecoderOptions::try_build`]
+///
+/// [`BrotliEncoderOptions::try_build`]: encode::BrotliEncoderOptions::try_build
+/// [`BrotliDecoderOptions::try_build`]: decode::BrotliDecoderOptions::try_build
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum BuildError {
+    /// The encoder or decoder failed to be allocated or initialized.
+    Alloc(AllocError),
+
+    /// A parameter was rejected by the encoder or decoder.
+    SetParameter(SetParameterError),
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::Alloc(err) => err.fmt(f),
+            BuildError::SetParameter(err) => err.fmt(f),
+        }
+    }
+}
+
+impl Error for BuildError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            BuildError::Alloc(err) => Some(err),
+            BuildError::SetParameter(err) => Some(err),
+        }
+    }
+}
+
+impl From<AllocError> for BuildError {
+    fn from(err: AllocError) -> Self {
+        BuildError::Alloc(err)
+    }
+}
+
+impl From<SetParameterError> for BuildError {
+    fn from(err: SetParameterError) -> Self {
+        BuildError::SetParameter(err)
+    }
+}
+
+impl From<BuildError> for io::Error {
+    fn from(err: BuildError) -> Self {
+        match err {
+            BuildError::Alloc(err) => err.into(),
+            BuildError::SetParameter(err) => io::Error::new(io::ErrorKind::InvalidInput, err),
+        }
+    }
+}
+
 /// Read all bytes from `input` and compress them into `output`, returning how many bytes were
 /// written.
 ///
@@ -882,6 +1046,45 @@ pub fn compress(
     }
 }
 
+/// Read all bytes from `input` and compress them into a newly allocated [`Vec`].
+///
+/// This is the allocating counterpart of [`compress`] using the same `quality`, `window_size` and
+/// `mode`. The output grows as needed, so no upper bound has to be determined up front. For more
+/// compression options, see [`BrotliEncoderOptions::compress_to_vec`].
+///
+/// # Errors
+///
+/// An [`Err`] will be returned if a generic compression error occurs or memory allocation failed.
+///
+/// # Examples
+///
+/// ```
+/// use brotlic::{compress_to_vec, CompressionMode, Quality, WindowSize};
+///
+/// let input = vec![0; 1024];
+/// let compressed = compress_to_vec(
+///      input.as_slice(),
+///      Quality::default(),
+///      WindowSize::default(),
+///      CompressionMode::Generic
+/// )?;
+///
+/// assert!(compressed.len() < input.len());
+/// # Ok::<(), brotlic::CompressError>(())
+/// ```
+pub fn compress_to_vec(
+    input: &[u8],
+    quality: Quality,
+    window_size: WindowSize,
+    mode: CompressionMode,
+) -> Result<Vec<u8>, CompressError> {
+    BrotliEncoderOptions::new()
+        .quality(quality)
+        .window_size(window_size)
+        .mode(mode)
+        .compress_to_vec(input)
+}
+
 /// Returns an upper bound for compression.
 ///
 /// Given an input of `input_size` bytes in size and a `quality`, determine an upper bound for
@@ -955,6 +1158,113 @@ pub fn decompress(input: &[u8], output: &mut [u8]) -> Result<usize, DecompressEr
     }
 }
 
+/// Read all bytes from `input` and decompress them into a newly allocated [`Vec`].
+///
+/// The output grows as needed, so the size of the uncompressed data need not be known up front.
+/// Since brotli can reach very high compression ratios, use [`decompress_to_vec_with_limit`] when
+/// `input` is untrusted.
+///
+/// # Errors
+///
+/// An [`Err`] will be returned if:
+///
+/// * `input` is corrupted or truncated
+/// * memory allocation failed
+///
+/// # Examples
+///
+/// ```
+/// use brotlic::{compress_to_vec, decompress_to_vec, CompressionMode, Quality, WindowSize};
+///
+/// let input = vec![0; 1024];
+/// let compressed = compress_to_vec(
+///      input.as_slice(),
+///      Quality::default(),
+///      WindowSize::default(),
+///      CompressionMode::Generic
+/// )?;
+///
+/// assert_eq!(decompress_to_vec(compressed.as_slice())?, input);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn decompress_to_vec(input: &[u8]) -> Result<Vec<u8>, DecompressError> {
+    decompress_to_vec_with_limit(input, usize::MAX).map_err(|_| DecompressError)
+}
+
+/// Read all bytes from `input` and decompress them into a newly allocated [`Vec`] of at most
+/// `limit` bytes.
+///
+/// Like [`decompress_to_vec`], but decompression is aborted as soon as the output would exceed
+/// `limit` bytes. The output is never grown beyond `limit` bytes, which protects against
+/// decompression bombs.
+///
+/// # Errors
+///
+/// An [`Err`] will be returned if:
+///
+/// * `input` is corrupted or truncated
+/// * memory allocation failed
+/// * the uncompressed `input` is larger than `limit` bytes, in which case
+///   [`DecompressToVecError::OutputLimitExceeded`] is returned
+///
+/// # Examples
+///
+/// ```
+/// use brotlic::{compress_to_vec, decompress_to_vec_with_limit, DecompressToVecError};
+/// use brotlic::{CompressionMode, Quality, WindowSize};
+///
+/// let input = vec![0; 1024];
+/// let compressed = compress_to_vec(
+///      input.as_slice(),
+///      Quality::default(),
+///      WindowSize::default(),
+///      CompressionMode::Generic
+/// )?;
+///
+/// assert_eq!(decompress_to_vec_with_limit(compressed.as_slice(), 1024)?, input);
+/// assert_eq!(
+///     decompress_to_vec_with_limit(compressed.as_slice(), 1023),
+///     Err(DecompressToVecError::OutputLimitExceeded)
+/// );
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn decompress_to_vec_with_limit(
+    input: &[u8],
+    limit: usize,
+) -> Result<Vec<u8>, DecompressToVecError> {
+    let mut decoder = BrotliDecoder::try_new().map_err(|_| DecompressToVecError::Decompress)?;
+    let mut output = Vec::new();
+    let mut input = input;
+
+    loop {
+        if output.len() == output.capacity() && output.len() < limit {
+            let additional = output.capacity().max(input.len()).max(4096);
+            output.reserve_exact(additional.min(limit - output.len()));
+        }
+
+        let len = output.len();
+        let spare_len = (output.capacity() - len).min(limit - len);
+        let spare = &mut output.spare_capacity_mut()[..spare_len];
+
+        let res = decoder
+            .decompress_uninit(input, spare)
+            .map_err(|_| DecompressToVecError::Decompress)?;
+
+        // SAFETY: the decoder initialized the first `bytes_written` bytes of the spare capacity
+        unsafe { output.set_len(len + res.bytes_written) };
+        input = &input[res.bytes_read..];
+
+        match res.info {
+            DecoderInfo::Finished => return Ok(output),
+            DecoderInfo::NeedsMoreInput => return Err(DecompressToVecError::Decompress),
+            DecoderInfo::NeedsMoreOutput if output.len() == limit => {
+                return Err(DecompressToVecError::OutputLimitExceeded)
+            }
+            DecoderInfo::NeedsMoreOutput => {}
+        }
+    }
+}
+
 /// An error returned by `into_inner`.
 ///
 /// This error combines an error that happened while processing data, and the instance
@@ -1004,18 +1314,72 @@ impl<I> fmt::Display for IntoInnerError<I> {
     }
 }
 
+/// Output taken from an encoder or decoder, which remains valid until it is used again.
+#[derive(Debug)]
+pub(crate) struct OutputBuffer {
+    ptr: *const u8,
+    len: usize,
+}
+
+// the buffer is only a view into the output of an encoder or decoder, which are Send and Sync
+unsafe impl Send for OutputBuffer {}
+unsafe impl Sync for OutputBuffer {}
+
+impl Default for OutputBuffer {
+    fn default() -> Self {
+        OutputBuffer {
+            ptr: ptr::null(),
+            len: 0,
+        }
+    }
+}
+
+impl OutputBuffer {
+    /// Replaces the contents of the buffer with `output`.
+    ///
+    /// # Safety
+    ///
+    /// `output` must remain valid for as long as the buffer is not empty.
+    pub(crate) unsafe fn set(&mut self, output: &[u8]) {
+        self.ptr = output.as_ptr();
+        self.len = output.len();
+    }
+
+    pub(crate) fn get(&self) -> &[u8] {
+        match self.len {
+            0 => &[],
+            // SAFETY: the output is kept valid while the buffer is not empty
+            len => unsafe { slice::from_raw_parts(self.ptr, len) },
+        }
+    }
+
+    pub(crate) fn consume(&mut self, amt: usize) {
+        let amt = amt.min(self.len);
+
+        // SAFETY: the pointer stays within the bounds of the output
+        self.ptr = unsafe { self.ptr.add(amt) };
+        self.len -= amt;
+    }
+}
+
 const MIN_ALIGN: usize = 16;
 
 extern "C" fn malloc(opaque: *mut c_void, size: usize) -> *mut c_void {
     let global_alloc = opaque as *const Box<dyn GlobalAlloc>;
 
-    if size > usize::MAX - 2 * MIN_ALIGN + 1 {
-        return ptr::null_mut();
-    }
+    // the size is stored in front of the allocation, refuse sizes for which that overflows
+    let layout = match size.checked_add(MIN_ALIGN).map(|n| Layout::from_size_align(n, MIN_ALIGN)) {
+        Some(Ok(layout)) => layout,
+        _ => return ptr::null_mut(),
+    };
 
     unsafe {
-        let layout = Layout::from_size_align_unchecked(size + MIN_ALIGN, MIN_ALIGN);
         let alloc = (*global_alloc).alloc(layout);
+
+        if alloc.is_null() {
+            return ptr::null_mut();
+        }
+
         (alloc as *mut usize).write(size);
 
         (alloc.add(MIN_ALIGN)) as _
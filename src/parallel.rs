@@ -0,0 +1,723 @@
+//! Module that contains multi-threaded compression and decompression of large inputs
+//!
+//! A single brotli encoder processes its input sequentially, which is slow for large inputs at
+//! high qualities. [`ParallelCompressorWriter`] splits its input into blocks and compresses them
+//! independently on a pool of threads. Every block is compressed by its own encoder that is told
+//! how much data precedes it via [`BrotliEncoderOptions::stream_offset`], and every block but the
+//! last one is flushed. The compressed blocks are then concatenated in order, which results in a
+//! single, regular brotli stream that can be decompressed by any brotli decoder.
+//!
+//! Since blocks do not reference each other, the compression ratio is slightly worse than that of
+//! a single encoder. Using a block size that is larger than the window size keeps this loss low.
+//!
+//! Decompressing such a stream is still sequential, as nothing tells a decoder where the blocks
+//! start. Streams in the [seekable format] carry an index of their frames, which
+//! [`ParallelDecompressorOptions`] uses to decompress the frames on a pool of threads instead.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::io::{Read, Write};
+//! use brotlic::parallel::ParallelCompressorOptions;
+//! use brotlic::{BrotliEncoderOptions, DecompressorReader, Quality};
+//!
+//! let input = vec![b'a'; 1 << 20];
+//! let mut compressor = ParallelCompressorOptions::new()
+//!     .encoder_options(BrotliEncoderOptions::new().quality(Quality::new(5)?))
+//!     .block_size(1 << 18)
+//!     .threads(4)
+//!     .build(Vec::new())?;
+//!
+//! compressor.write_all(&input)?;
+//! let compressed = compressor.into_inner()?;
+//!
+//! let mut decompressor = DecompressorReader::new(compressed.as_slice());
+//! let mut decompressed = Vec::new();
+//! decompressor.read_to_end(&mut decompressed)?;
+//!
+//! assert_eq!(decompressed, input);
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! [seekable format]: crate::seekable
+
+use std::collections::VecDeque;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::{mem, ptr};
+
+use crate::encode::{BrotliEncoderOptions, BrotliOperation, ChecksumState, CompressorWriter};
+use crate::seekable::{Frame, Index};
+use crate::{BuildError, IntoInnerError, SetParameterError};
+
+/// The default size of the blocks that are compressed independently (4 MiB).
+pub const DEFAULT_BLOCK_SIZE: usize = 1 << 22;
+
+/// The largest stream offset accepted by the encoder, larger offsets have the same effect.
+const MAX_STREAM_OFFSET: u64 = 1 << 30;
+
+/// Options to build a [`ParallelCompressorWriter`].
+#[derive(Debug, Clone)]
+pub struct ParallelCompressorOptions {
+    encoder: BrotliEncoderOptions,
+    block_size: usize,
+    threads: usize,
+}
+
+impl ParallelCompressorOptions {
+    /// Creates a new set of options, using default encoder options, a block size of
+    /// [`DEFAULT_BLOCK_SIZE`] and as many threads as there are available CPUs.
+    pub fn new() -> Self {
+        ParallelCompressorOptions {
+            encoder: BrotliEncoderOptions::new(),
+            block_size: DEFAULT_BLOCK_SIZE,
+            threads: 0,
+        }
+    }
+
+    /// Sets the options of the encoders that compress the blocks.
+    ///
+    /// The stream offset and size hint are set for each block individually, so these options are
+    /// overridden. Checksums are computed over all of the input and behave like they do for
+    /// [`CompressorWriter`].
+    pub fn encoder_options(&mut self, encoder: &BrotliEncoderOptions) -> &mut Self {
+        self.encoder = encoder.clone();
+        self
+    }
+
+    /// Sets the number of uncompressed bytes that are compressed independently by a thread.
+    ///
+    /// Larger blocks improve the compression ratio, while smaller blocks allow to compress small
+    /// inputs in parallel. A block size of zero is treated as one. This is not to be confused with
+    /// [`BrotliEncoderOptions::block_size`], which affects each encoder.
+    pub fn block_size(&mut self, block_size: usize) -> &mut Self {
+        self.block_size = block_size.max(1);
+        self
+    }
+
+    /// Sets the number of threads that compress blocks. Zero, which is the default, uses as many
+    /// threads as there are available CPUs.
+    pub fn threads(&mut self, threads: usize) -> &mut Self {
+        self.threads = threads;
+        self
+    }
+
+    /// Creates a new [`ParallelCompressorWriter`] writing to `inner` from these options.
+    ///
+    /// # Errors
+    ///
+    /// An [`Err`] will be returned if the encoder options are invalid.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a thread fails to be spawned.
+    pub fn build<W: Write>(
+        &self,
+        inner: W,
+    ) -> Result<ParallelCompressorWriter<W>, SetParameterError> {
+        let mut encoder = self.encoder.clone();
+        let mut checksum = ChecksumState::take(&mut encoder);
+
+        // validate the options as used for all but the first block, failing to allocate the encoder
+        // is reported once a block is compressed
+        if let Err(BuildError::SetParameter(err)) = encoder.clone().stream_offset(1).try_build() {
+            return Err(err);
+        }
+
+        let threads = match self.threads {
+            0 => thread::available_parallelism().map_or(1, |threads| threads.get()),
+            threads => threads,
+        };
+
+        Ok(ParallelCompressorWriter {
+            inner,
+            state: Box::new(State {
+                pool: Pool::new(threads, encoder),
+                block_size: self.block_size,
+                max_pending: 2 * threads,
+                block: Vec::new(),
+                offset: 0,
+                pending: VecDeque::new(),
+                declaration: checksum.take_record(),
+                checksum,
+                finished: false,
+            }),
+            panicked: false,
+        })
+    }
+}
+
+impl Default for ParallelCompressorOptions {
+    fn default() -> Self {
+        ParallelCompressorOptions::new()
+    }
+}
+
+/// Wraps a writer and compresses its output using multiple threads.
+///
+/// `ParallelCompressorWriter<W>` behaves like [`CompressorWriter`], except that its input is
+/// compressed in blocks by a pool of threads, see the [module level documentation] for details.
+/// At most two blocks per thread are buffered or compressed at a time, after which writing waits
+/// for the oldest block to be compressed and written to the underlying writer.
+///
+/// It is critical to finish the compression stream, otherwise decompression will not be
+/// successful. Dropping will attempt to finish the compression stream, any errors that might arise
+/// however will be ignored. Calling [`into_inner`] ensures that the compression stream is
+/// finished.
+///
+/// Calling [`flush`] compresses all buffered input as a block of its own and waits until all
+/// blocks are written to the underlying writer. This leads to a worse compression ratio if done
+/// frequently.
+///
+/// [module level documentation]: crate::parallel
+/// [`into_inner`]: ParallelCompressorWriter::into_inner
+/// [`flush`]: ParallelCompressorWriter::flush
+#[derive(Debug)]
+pub struct ParallelCompressorWriter<W: Write> {
+    inner: W,
+    state: Box<State>,
+    panicked: bool,
+}
+
+#[derive(Debug)]
+struct State {
+    pool: Pool,
+    block_size: usize,
+    max_pending: usize,
+    block: Vec<u8>,
+    offset: u64,
+    pending: VecDeque<Receiver<io::Result<Vec<u8>>>>,
+    // the checksum declaring the algorithm, which precedes the first block
+    declaration: Option<Vec<u8>>,
+    checksum: ChecksumState,
+    finished: bool,
+}
+
+impl<W: Write> ParallelCompressorWriter<W> {
+    /// Creates a new `ParallelCompressorWriter<W>` using default options, see
+    /// [`ParallelCompressorOptions::new`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if a thread fails to be spawned.
+    pub fn new(inner: W) -> Self {
+        ParallelCompressorOptions::new()
+            .build(inner)
+            .expect("default options are valid")
+    }
+
+    /// Gets a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Gets a mutable reference to the underlying writer.
+    ///
+    /// It is inadvisable to directly write to the underlying writer.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Unwraps this `ParallelCompressorWriter<W>`, returning the underlying writer.
+    ///
+    /// The compression stream is finished before returning the writer and all threads are
+    /// stopped.
+    ///
+    /// # Errors
+    ///
+    /// An [`Err`] will be returned if an error occurs while finishing the compression stream.
+    pub fn into_inner(mut self) -> Result<W, IntoInnerError<ParallelCompressorWriter<W>>> {
+        if let Err(e) = self.finish() {
+            return Err(IntoInnerError::new(self, e));
+        }
+
+        let inner = unsafe { ptr::read(&self.inner) };
+        drop(unsafe { ptr::read(&self.state) });
+        mem::forget(self);
+
+        Ok(inner)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if !self.state.finished {
+            let metadata = self.state.checksum.record_for(BrotliOperation::Finish);
+
+            self.submit(metadata, true)?;
+            self.state.finished = true;
+        }
+
+        while !self.state.pending.is_empty() {
+            self.write_block()?;
+        }
+
+        Ok(())
+    }
+
+    fn submit(&mut self, metadata: Option<Vec<u8>>, last: bool) -> io::Result<()> {
+        while self.state.pending.len() >= self.state.max_pending {
+            self.write_block()?;
+        }
+
+        let input = mem::take(&mut self.state.block);
+        let offset = self.state.offset;
+        let (result, receiver) = mpsc::sync_channel(1);
+
+        self.state.offset += input.len() as u64;
+        self.state.pool.submit(Job {
+            declaration: self.state.declaration.take(),
+            input,
+            offset,
+            metadata,
+            last,
+            result,
+        })?;
+        self.state.pending.push_back(receiver);
+
+        Ok(())
+    }
+
+    fn write_block(&mut self) -> io::Result<()> {
+        let receiver = match self.state.pending.pop_front() {
+            Some(receiver) => receiver,
+            None => return Ok(()),
+        };
+
+        let output = receiver.recv().map_err(|_| thread_panicked())??;
+
+        self.panicked = true;
+        let r = self.inner.write_all(&output);
+        self.panicked = false;
+        r
+    }
+}
+
+impl<W: Write> Write for ParallelCompressorWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.state.finished {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the compression stream is already finished",
+            ));
+        }
+
+        let block_size = self.state.block_size;
+        let block = &mut self.state.block;
+
+        if block.capacity() == 0 {
+            block.reserve_exact(block_size);
+        }
+
+        let bytes_read = buf.len().min(block_size - block.len());
+        block.extend_from_slice(&buf[..bytes_read]);
+        self.state.checksum.update(&buf[..bytes_read]);
+
+        if block.len() == block_size {
+            self.submit(None, false)?;
+        }
+
+        Ok(bytes_read)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.state.finished {
+            let metadata = self.state.checksum.record_for(BrotliOperation::Flush);
+
+            if !self.state.block.is_empty() || metadata.is_some() {
+                self.submit(metadata, false)?;
+            }
+        }
+
+        while !self.state.pending.is_empty() {
+            self.write_block()?;
+        }
+
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Drop for ParallelCompressorWriter<W> {
+    fn drop(&mut self) {
+        if !self.panicked {
+            let _r = self.finish();
+        }
+    }
+}
+
+/// Options to decompress seekable brotli streams using multiple threads.
+///
+/// Regular brotli streams can only be decompressed sequentially, but the frames of a stream in
+/// the [seekable format] are independent of each other. The frame index is read first, after
+/// which the frames are decompressed by a pool of threads. Each thread decompresses one frame at
+/// a time using its own decoder, while the calling thread reads the compressed frames and writes
+/// the decompressed frames in order.
+///
+/// The checksum records of a stream are not verified, as they cover all of the decompressed data.
+/// Use a [`DecompressorReader`] that verifies checksums for that instead.
+///
+/// # Examples
+///
+/// ```
+/// use std::io::{Cursor, Write};
+/// use brotlic::parallel::ParallelDecompressorOptions;
+/// use brotlic::seekable::SeekableCompressorOptions;
+///
+/// let input = vec![b'a'; 1 << 20];
+/// let mut compressor = SeekableCompressorOptions::new()
+///     .frame_size(1 << 16)
+///     .build(Vec::new())?;
+///
+/// compressor.write_all(&input)?;
+/// let compressed = compressor.into_inner()?;
+///
+/// let mut decompressed = Vec::new();
+/// ParallelDecompressorOptions::new()
+///     .threads(4)
+///     .decompress(Cursor::new(compressed), &mut decompressed)?;
+///
+/// assert_eq!(decompressed, input);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+///
+/// [seekable format]: crate::seekable
+/// [`DecompressorReader`]: crate::decode::DecompressorReader
+#[derive(Debug, Clone)]
+pub struct ParallelDecompressorOptions {
+    threads: usize,
+}
+
+impl ParallelDecompressorOptions {
+    /// Creates a new set of options, using as many threads as there are available CPUs.
+    pub fn new() -> Self {
+        ParallelDecompressorOptions { threads: 0 }
+    }
+
+    /// Sets the number of threads that decompress frames. Zero, which is the default, uses as many
+    /// threads as there are available CPUs.
+    pub fn threads(&mut self, threads: usize) -> &mut Self {
+        self.threads = threads;
+        self
+    }
+
+    /// Decompresses the seekable brotli stream `reader` and writes the decompressed data to
+    /// `writer`, returning the number of bytes written.
+    ///
+    /// At most two frames per thread are buffered at a time.
+    ///
+    /// # Errors
+    ///
+    /// An [`Err`] will be returned if the index of the stream cannot be read, see [`Index::read`],
+    /// if the stream is corrupt or if reading or writing fails.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a thread fails to be spawned.
+    pub fn decompress<R, W>(&self, mut reader: R, mut writer: W) -> io::Result<u64>
+    where
+        R: Read + Seek,
+        W: Write,
+    {
+        let index = Index::read(&mut reader)?;
+
+        self.run(
+            &mut reader,
+            &index,
+            |_| None,
+            |output| writer.write_all(&output),
+        )?;
+
+        Ok(index.uncompressed_len())
+    }
+
+    /// Decompresses the seekable brotli stream `reader` into `output`, returning the number of
+    /// bytes written.
+    ///
+    /// Each thread decompresses its frame directly into `output`, so no frames are buffered.
+    ///
+    /// # Errors
+    ///
+    /// An [`Err`] will be returned if the index of the stream cannot be read, see [`Index::read`],
+    /// if `output` is too small, if the stream is corrupt or if reading fails.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a thread fails to be spawned.
+    pub fn decompress_into<R>(&self, mut reader: R, output: &mut [u8]) -> io::Result<usize>
+    where
+        R: Read + Seek,
+    {
+        let index = Index::read(&mut reader)?;
+        let len = usize::try_from(index.uncompressed_len())
+            .ok()
+            .filter(|&len| len <= output.len())
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "the output buffer is too small",
+                )
+            })?;
+
+        let mut remaining = &mut output[..len];
+
+        self.run(
+            &mut reader,
+            &index,
+            |frame| {
+                // the frames fit into `output`, so their lengths fit into usize
+                let len = frame.uncompressed_len() as usize;
+                let (output, rest) = mem::take(&mut remaining).split_at_mut(len);
+                remaining = rest;
+                Some(output)
+            },
+            |_| Ok(()),
+        )?;
+
+        Ok(len)
+    }
+
+    fn run<'a, R: Read + Seek>(
+        &self,
+        reader: &mut R,
+        index: &Index,
+        output: impl FnMut(&Frame) -> Option<&'a mut [u8]>,
+        sink: impl FnMut(Vec<u8>) -> io::Result<()>,
+    ) -> io::Result<()> {
+        let threads = match self.threads {
+            0 => thread::available_parallelism().map_or(1, |threads| threads.get()),
+            threads => threads,
+        };
+        let threads = threads.min(index.frames().len()).max(1);
+        let (jobs, receiver) = mpsc::channel::<FrameJob>();
+        let receiver = Mutex::new(receiver);
+
+        thread::scope(|scope| {
+            let workers: Vec<_> = (0..threads)
+                .map(|i| {
+                    let receiver = &receiver;
+
+                    thread::Builder::new()
+                        .name(format!("brotlic-decompressor-{}", i))
+                        .spawn_scoped(scope, move || FrameJob::work(receiver, index))
+                        .expect("failed to spawn decompression thread")
+                })
+                .collect();
+
+            // the workers stop once the sender is dropped at the end of dispatching
+            let mut res = dispatch(reader, index, jobs, 2 * threads, output, sink);
+
+            for worker in workers {
+                if worker.join().is_err() && res.is_ok() {
+                    res = Err(thread_panicked());
+                }
+            }
+
+            res
+        })
+    }
+}
+
+impl Default for ParallelDecompressorOptions {
+    fn default() -> Self {
+        ParallelDecompressorOptions::new()
+    }
+}
+
+/// A block of input to be compressed by a worker thread.
+struct Job {
+    declaration: Option<Vec<u8>>,
+    input: Vec<u8>,
+    offset: u64,
+    metadata: Option<Vec<u8>>,
+    last: bool,
+    result: SyncSender<io::Result<Vec<u8>>>,
+}
+
+impl Job {
+    fn compress(&self, options: &BrotliEncoderOptions) -> io::Result<Vec<u8>> {
+        let mut options = options.clone();
+        options.stream_offset(self.offset.min(MAX_STREAM_OFFSET) as u32);
+
+        if let Ok(size_hint) = u32::try_from(self.input.len()) {
+            options.size_hint(size_hint);
+        }
+
+        let encoder = options.try_build()?;
+        let mut writer = CompressorWriter::with_encoder(encoder, Vec::new());
+
+        if let Some(declaration) = &self.declaration {
+            writer.write_metadata(declaration)?;
+        }
+
+        writer.write_all(&self.input)?;
+
+        if let Some(metadata) = &self.metadata {
+            writer.write_metadata(metadata)?;
+        }
+
+        if self.last {
+            Ok(writer.into_inner()?)
+        } else {
+            // the next block must start at a byte boundary
+            writer.flush()?;
+            Ok(writer.into_parts().0)
+        }
+    }
+}
+
+/// A pool of threads compressing jobs.
+#[derive(Debug)]
+struct Pool {
+    jobs: Option<Sender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl Pool {
+    fn new(threads: usize, options: BrotliEncoderOptions) -> Self {
+        let (jobs, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let options = Arc::new(options);
+
+        let workers = (0..threads)
+            .map(|index| {
+                let receiver = Arc::clone(&receiver);
+                let options = Arc::clone(&options);
+
+                thread::Builder::new()
+                    .name(format!("brotlic-compressor-{}", index))
+                    .spawn(move || Pool::work(&receiver, &options))
+                    .expect("failed to spawn compression thread")
+            })
+            .collect();
+
+        Pool {
+            jobs: Some(jobs),
+            workers,
+        }
+    }
+
+    fn submit(&self, job: Job) -> io::Result<()> {
+        match &self.jobs {
+            Some(jobs) => jobs.send(job).map_err(|_| thread_panicked()),
+            None => Err(thread_panicked()),
+        }
+    }
+
+    fn work(receiver: &Mutex<Receiver<Job>>, options: &BrotliEncoderOptions) {
+        loop {
+            let job = match receiver
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .recv()
+            {
+                Ok(job) => job,
+                Err(_) => return,
+            };
+
+            // the writer may have been dropped without waiting for the result
+            let _ = job.result.send(job.compress(options));
+        }
+    }
+}
+
+impl Drop for Pool {
+    fn drop(&mut self) {
+        drop(self.jobs.take());
+
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// A compressed frame to be decompressed by a worker thread.
+struct FrameJob<'a> {
+    frame: Frame,
+    input: Vec<u8>,
+    output: Option<&'a mut [u8]>,
+    result: SyncSender<io::Result<Vec<u8>>>,
+}
+
+impl FrameJob<'_> {
+    fn decompress(&mut self, index: &Index) -> io::Result<Vec<u8>> {
+        match &mut self.output {
+            Some(output) => {
+                index.decompress_frame(&self.frame, &self.input, output)?;
+                Ok(Vec::new())
+            }
+            None => index.decompress_frame_to_vec(&self.frame, &self.input),
+        }
+    }
+
+    fn work(receiver: &Mutex<Receiver<FrameJob<'_>>>, index: &Index) {
+        loop {
+            let mut job = match receiver
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .recv()
+            {
+                Ok(job) => job,
+                Err(_) => return,
+            };
+
+            // the caller may have stopped waiting for the result after an error
+            let res = job.decompress(index);
+            let _ = job.result.send(res);
+        }
+    }
+}
+
+/// Reads the compressed frames of `index` from `reader` and sends them to the workers, passing
+/// the decompressed frames to `sink` in order.
+fn dispatch<'a, R: Read + Seek>(
+    reader: &mut R,
+    index: &Index,
+    jobs: Sender<FrameJob<'a>>,
+    max_pending: usize,
+    mut output: impl FnMut(&Frame) -> Option<&'a mut [u8]>,
+    mut sink: impl FnMut(Vec<u8>) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut pending = VecDeque::new();
+    reader.seek(SeekFrom::Start(0))?;
+
+    for frame in index.frames() {
+        if pending.len() >= max_pending {
+            sink(recv_frame(&mut pending)?)?;
+        }
+
+        let mut input = vec![0; buffer_len(frame.compressed_len())?];
+        reader.read_exact(&mut input)?;
+
+        let (result, receiver) = mpsc::sync_channel(1);
+        let job = FrameJob {
+            frame: *frame,
+            input,
+            output: output(frame),
+            result,
+        };
+
+        jobs.send(job).map_err(|_| thread_panicked())?;
+        pending.push_back(receiver);
+    }
+
+    while !pending.is_empty() {
+        sink(recv_frame(&mut pending)?)?;
+    }
+
+    Ok(())
+}
+
+fn recv_frame(pending: &mut VecDeque<Receiver<io::Result<Vec<u8>>>>) -> io::Result<Vec<u8>> {
+    match pending.pop_front() {
+        Some(receiver) => receiver.recv().map_err(|_| thread_panicked())?,
+        None => Ok(Vec::new()),
+    }
+}
+
+fn buffer_len(len: u64) -> io::Result<usize> {
+    usize::try_from(len).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "frame too large"))
+}
+
+fn thread_panicked() -> io::Error {
+    io::Error::other("a worker thread panicked")
+}
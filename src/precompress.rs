@@ -0,0 +1,602 @@
+//! Module that generates precompressed static assets
+//!
+//! Web servers can serve a brotli compressed `.br` sibling of a static asset to clients that
+//! accept brotli, instead of compressing the asset on every request. [`PrecompressOptions`] walks
+//! a directory and writes these siblings using [`CompressorWriter`], picking the
+//! [`CompressionMode`] by file type.
+//!
+//! Precompression is incremental. A [`Manifest`] of the sizes and hashes of all assets is kept
+//! in the directory, and an asset is only compressed again if its modification time and its hash
+//! changed since the last run, or if it was compressed with a different quality, window size or
+//! mode. Outputs that are not smaller than their asset are not kept, as
+//! serving them would only waste time decompressing.
+//!
+//! This module requires the `precompress` feature.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use brotlic::precompress::PrecompressOptions;
+//! use brotlic::Quality;
+//!
+//! let report = PrecompressOptions::new()
+//!     .quality(Quality::best())
+//!     .precompress_dir("public")?;
+//!
+//! println!(
+//!     "{} compressed, {} unchanged",
+//!     report.compressed(),
+//!     report.unchanged()
+//! );
+//!
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::ffi::OsString;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use xxhash_rust::xxh64::Xxh64;
+
+use crate::encode::{BrotliEncoderOptions, CompressorWriter};
+use crate::{CompressionMode, Quality, WindowSize};
+
+/// The default file name of the manifest, located in the precompressed directory.
+pub const MANIFEST_NAME: &str = "brotli-manifest.txt";
+
+const MANIFEST_HEADER: &str = "# brotlic precompress manifest v2";
+
+/// Options to precompress the static assets of a directory.
+#[derive(Debug, Clone)]
+pub struct PrecompressOptions {
+    quality: Quality,
+    window_size: WindowSize,
+    manifest_name: OsString,
+}
+
+impl PrecompressOptions {
+    /// Creates a new set of options, using the best quality and window size.
+    pub fn new() -> Self {
+        PrecompressOptions {
+            quality: Quality::best(),
+            window_size: WindowSize::best(),
+            manifest_name: OsString::from(MANIFEST_NAME),
+        }
+    }
+
+    /// Sets the compression quality, see [`Quality`].
+    pub fn quality(&mut self, quality: Quality) -> &mut Self {
+        self.quality = quality;
+        self
+    }
+
+    /// Sets the sliding window size, see [`WindowSize`].
+    pub fn window_size(&mut self, window_size: WindowSize) -> &mut Self {
+        self.window_size = window_size;
+        self
+    }
+
+    /// Sets the file name of the manifest, which defaults to [`MANIFEST_NAME`].
+    pub fn manifest_name<N: Into<OsString>>(&mut self, manifest_name: N) -> &mut Self {
+        self.manifest_name = manifest_name.into();
+        self
+    }
+
+    /// Precompresses all assets in `dir` and its subdirectories.
+    ///
+    /// Every file for which [`compression_mode`] returns a mode is compressed into a sibling with
+    /// an additional `.br` extension, e.g. `index.html.br`. An existing sibling is overwritten if
+    /// its asset or the settings it was compressed with changed. If an output is not smaller than
+    /// its asset, it is discarded and any existing sibling of the asset is removed. Siblings of
+    /// assets that were removed since the last run are removed as well.
+    ///
+    /// Symbolic links are not followed, and files whose path relative to `dir` is not valid UTF-8
+    /// or contains a line break are skipped, since they cannot be recorded in the manifest.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading an asset or writing an output fails, or if an existing manifest
+    /// is malformed.
+    pub fn precompress_dir<P: AsRef<Path>>(&self, dir: P) -> io::Result<Report> {
+        let dir = dir.as_ref();
+        let manifest_path = dir.join(&self.manifest_name);
+
+        let previous = match File::open(&manifest_path) {
+            Ok(file) => Manifest::read(BufReader::new(file))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Manifest::default(),
+            Err(e) => return Err(e),
+        };
+
+        let mut assets = Vec::new();
+        self.find_assets(dir, "", &mut assets)?;
+        assets.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        let mut report = Report::default();
+
+        for (name, mode) in assets {
+            let path = dir.join(&name);
+            let entry = self.precompress_file(&path, name, mode, &previous, &mut report)?;
+            report.manifest.entries.push(entry);
+        }
+
+        for entry in &previous.entries {
+            if entry.compressed_size.is_some() && report.manifest.get(&entry.path).is_none() {
+                remove_if_exists(&output_path(&dir.join(&entry.path)))?;
+                report.removed += 1;
+            }
+        }
+
+        let temp_path = temp_path(&manifest_path);
+        let mut writer = BufWriter::new(File::create(&temp_path)?);
+        report.manifest.write(&mut writer)?;
+        writer.into_inner().map_err(|e| e.into_error())?;
+        fs::rename(temp_path, manifest_path)?;
+
+        Ok(report)
+    }
+
+    fn find_assets(
+        &self,
+        dir: &Path,
+        prefix: &str,
+        assets: &mut Vec<(String, CompressionMode)>,
+    ) -> io::Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+
+            let name = match entry.file_name().into_string() {
+                Ok(name) if !name.contains(['\n', '\r']) => [prefix, &name].concat(),
+                _ => continue,
+            };
+
+            if file_type.is_dir() {
+                self.find_assets(&entry.path(), &[&name, "/"].concat(), assets)?;
+            } else if file_type.is_file() && !(prefix.is_empty() && self.manifest_name == *name) {
+                if let Some(mode) = compression_mode(&entry.path()) {
+                    assets.push((name, mode));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn precompress_file(
+        &self,
+        path: &Path,
+        name: String,
+        mode: CompressionMode,
+        previous: &Manifest,
+        report: &mut Report,
+    ) -> io::Result<ManifestEntry> {
+        let metadata = fs::metadata(path)?;
+        let size = metadata.len();
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok());
+        let output = output_path(path);
+
+        let previous = match previous.get(&name) {
+            Some(entry)
+                if entry.size == size
+                    && entry.quality == self.quality
+                    && entry.window_size == self.window_size
+                    && entry.mode == mode
+                    && is_output_current(&output, entry)? =>
+            {
+                Some(entry)
+            }
+            _ => None,
+        };
+
+        if let Some(entry) = previous {
+            if modified.is_some() && entry.modified == modified {
+                report.unchanged += 1;
+                return Ok(entry.clone());
+            }
+        }
+
+        let hash = hash_file(path)?;
+
+        if let Some(entry) = previous {
+            if entry.hash == hash {
+                report.unchanged += 1;
+                return Ok(ManifestEntry {
+                    modified,
+                    ..entry.clone()
+                });
+            }
+        }
+
+        let compressed_size = self.compress_file(path, &output, mode, size)?;
+
+        match compressed_size {
+            Some(_) => report.compressed += 1,
+            None => report.skipped += 1,
+        }
+
+        Ok(ManifestEntry {
+            path: name,
+            size,
+            compressed_size,
+            hash,
+            modified,
+            quality: self.quality,
+            window_size: self.window_size,
+            mode,
+        })
+    }
+
+    fn compress_file(
+        &self,
+        path: &Path,
+        output: &Path,
+        mode: CompressionMode,
+        size: u64,
+    ) -> io::Result<Option<u64>> {
+        let mut options = BrotliEncoderOptions::new();
+        options
+            .quality(self.quality)
+            .window_size(self.window_size)
+            .mode(mode);
+
+        if let Ok(size_hint) = u32::try_from(size) {
+            options.size_hint(size_hint);
+        }
+
+        let encoder = options.try_build()?;
+
+        let temp_path = temp_path(output);
+        let compressed_size = File::create(&temp_path).and_then(|file| {
+            let mut input = File::open(path)?;
+            let mut writer = CompressorWriter::with_encoder(encoder, BufWriter::new(file));
+
+            io::copy(&mut input, &mut writer)?;
+
+            let file = writer
+                .into_inner()?
+                .into_inner()
+                .map_err(|e| e.into_error())?;
+            Ok(file.metadata()?.len())
+        });
+
+        match compressed_size {
+            Ok(compressed_size) if compressed_size < size => {
+                fs::rename(&temp_path, output)?;
+                Ok(Some(compressed_size))
+            }
+            Ok(_) => {
+                fs::remove_file(&temp_path)?;
+                remove_if_exists(output)?;
+                Ok(None)
+            }
+            Err(e) => {
+                let _ = fs::remove_file(&temp_path);
+                Err(e)
+            }
+        }
+    }
+}
+
+impl Default for PrecompressOptions {
+    /// Creates a new set of options, see [`PrecompressOptions::new`].
+    fn default() -> Self {
+        PrecompressOptions::new()
+    }
+}
+
+/// Precompresses all assets in `dir` using the default options.
+///
+/// See [`PrecompressOptions::precompress_dir`] for more information.
+pub fn precompress_dir<P: AsRef<Path>>(dir: P) -> io::Result<Report> {
+    PrecompressOptions::new().precompress_dir(dir)
+}
+
+/// Returns the compression mode for the asset at `path` based on its extension, or [`None`] if it
+/// should not be precompressed.
+///
+/// Text based formats such as HTML, CSS and JavaScript use [`CompressionMode::Text`] and fonts use
+/// [`CompressionMode::Font`]. Formats that are compressed already, such as images or WOFF 2.0
+/// fonts, are not precompressed.
+pub fn compression_mode(path: &Path) -> Option<CompressionMode> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+
+    match extension.as_str() {
+        "html" | "htm" | "css" | "js" | "mjs" | "cjs" | "json" | "map" | "svg" | "xml" | "txt"
+        | "md" | "csv" | "webmanifest" => Some(CompressionMode::Text),
+        "woff" | "ttf" | "otf" | "eot" => Some(CompressionMode::Font),
+        "wasm" | "ico" => Some(CompressionMode::Generic),
+        _ => None,
+    }
+}
+
+/// The outcome of precompressing a directory.
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    manifest: Manifest,
+    compressed: usize,
+    skipped: usize,
+    unchanged: usize,
+    removed: usize,
+}
+
+impl Report {
+    /// Returns the manifest that was written to the directory.
+    pub fn manifest(&self) -> &Manifest {
+        &self.manifest
+    }
+
+    /// Unwraps this `Report`, returning the manifest that was written to the directory.
+    pub fn into_manifest(self) -> Manifest {
+        self.manifest
+    }
+
+    /// Returns the number of assets that were compressed.
+    pub fn compressed(&self) -> usize {
+        self.compressed
+    }
+
+    /// Returns the number of assets that were compressed, but whose output was discarded since it
+    /// was not smaller than the asset.
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+
+    /// Returns the number of assets that did not change since the last run.
+    pub fn unchanged(&self) -> usize {
+        self.unchanged
+    }
+
+    /// Returns the number of outputs that were removed since their asset no longer exists.
+    pub fn removed(&self) -> usize {
+        self.removed
+    }
+}
+
+/// The sizes and hashes of precompressed assets.
+///
+/// A manifest is a text file with a line for each asset, sorted by path. Every line consists of
+/// the xxHash64 of the asset in hexadecimal, the size of the asset, the size of its compressed
+/// output or `-` if it was discarded, the modification time of the asset in seconds since the
+/// Unix epoch or `-` if unknown, the quality, window size in bits and mode (`generic`, `text` or
+/// `font`) it was compressed with, and the path of the asset relative to the directory, separated
+/// by spaces. Lines starting with `#` are comments.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Manifest {
+    entries: Vec<ManifestEntry>,
+}
+
+impl Manifest {
+    /// Reads a manifest from `reader`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind [`io::ErrorKind::InvalidData`] if the manifest is malformed.
+    pub fn read<R: BufRead>(reader: R) -> io::Result<Self> {
+        let mut entries = Vec::new();
+
+        for line in reader.lines() {
+            let line = line?;
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let entry = ManifestEntry::parse(&line).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "malformed manifest entry")
+            })?;
+
+            entries.push(entry);
+        }
+
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+
+        Ok(Manifest { entries })
+    }
+
+    /// Writes this manifest to `writer`.
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(writer, "{}", MANIFEST_HEADER)?;
+        writeln!(writer, "# hash size compressed-size modified quality window mode path")?;
+
+        for entry in &self.entries {
+            writeln!(writer, "{}", entry)?;
+        }
+
+        writer.flush()
+    }
+
+    /// Returns the entries of all assets, sorted by path.
+    pub fn entries(&self) -> &[ManifestEntry] {
+        &self.entries
+    }
+
+    /// Returns the entry of the asset at `path`, relative to the directory and separated by `/`.
+    pub fn get(&self, path: &str) -> Option<&ManifestEntry> {
+        self.entries
+            .binary_search_by(|entry| entry.path.as_str().cmp(path))
+            .ok()
+            .map(|index| &self.entries[index])
+    }
+}
+
+/// An entry of a [`Manifest`], describing a single asset.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ManifestEntry {
+    path: String,
+    size: u64,
+    compressed_size: Option<u64>,
+    hash: u64,
+    modified: Option<Duration>,
+    quality: Quality,
+    window_size: WindowSize,
+    mode: CompressionMode,
+}
+
+impl ManifestEntry {
+    /// Returns the path of the asset, relative to the directory and separated by `/`.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Returns the size of the asset in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Returns the size of the compressed output in bytes, or [`None`] if it was discarded since
+    /// it was not smaller than the asset.
+    pub fn compressed_size(&self) -> Option<u64> {
+        self.compressed_size
+    }
+
+    /// Returns the xxHash64 of the asset.
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    /// Returns the modification time of the asset, if known.
+    pub fn modified(&self) -> Option<SystemTime> {
+        self.modified.map(|modified| UNIX_EPOCH + modified)
+    }
+
+    /// Returns the quality the asset was compressed with.
+    pub fn quality(&self) -> Quality {
+        self.quality
+    }
+
+    /// Returns the window size the asset was compressed with.
+    pub fn window_size(&self) -> WindowSize {
+        self.window_size
+    }
+
+    /// Returns the mode the asset was compressed with.
+    pub fn mode(&self) -> CompressionMode {
+        self.mode
+    }
+
+    fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.splitn(8, ' ');
+
+        let hash = u64::from_str_radix(fields.next()?, 16).ok()?;
+        let size = fields.next()?.parse().ok()?;
+        let compressed_size = match fields.next()? {
+            "-" => None,
+            compressed_size => Some(compressed_size.parse().ok()?),
+        };
+        let modified = match fields.next()? {
+            "-" => None,
+            modified => {
+                let (secs, nanos) = modified.split_once('.')?;
+                let nanos = nanos.parse().ok().filter(|&nanos| nanos < 1_000_000_000)?;
+
+                Some(Duration::new(secs.parse().ok()?, nanos))
+            }
+        };
+        let quality = Quality::new(fields.next()?.parse().ok()?).ok()?;
+        let window_size = WindowSize::new(fields.next()?.parse().ok()?).ok()?;
+        let mode = match fields.next()? {
+            "generic" => CompressionMode::Generic,
+            "text" => CompressionMode::Text,
+            "font" => CompressionMode::Font,
+            _ => return None,
+        };
+        let path = fields.next().filter(|path| is_relative_path(path))?;
+
+        Some(ManifestEntry {
+            path: path.to_string(),
+            size,
+            compressed_size,
+            hash,
+            modified,
+            quality,
+            window_size,
+            mode,
+        })
+    }
+}
+
+impl fmt::Display for ManifestEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x} {} ", self.hash, self.size)?;
+
+        match self.compressed_size {
+            Some(compressed_size) => write!(f, "{} ", compressed_size)?,
+            None => f.write_str("- ")?,
+        }
+
+        match self.modified {
+            Some(modified) => write!(f, "{}.{:09} ", modified.as_secs(), modified.subsec_nanos())?,
+            None => f.write_str("- ")?,
+        }
+
+        let mode = match self.mode {
+            CompressionMode::Generic => "generic",
+            CompressionMode::Text => "text",
+            CompressionMode::Font => "font",
+        };
+
+        write!(f, "{} {} {} ", self.quality.level(), self.window_size.bits(), mode)?;
+
+        f.write_str(&self.path)
+    }
+}
+
+/// Returns whether the output of an asset is the one recorded in its manifest `entry`.
+fn is_output_current(output: &Path, entry: &ManifestEntry) -> io::Result<bool> {
+    let compressed_size = match fs::metadata(output) {
+        Ok(metadata) => Some(metadata.len()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e),
+    };
+
+    Ok(compressed_size == entry.compressed_size)
+}
+
+/// Returns whether `path` is a non-empty path that stays within the directory it is relative to,
+/// as outputs are removed based on the paths of a manifest.
+fn is_relative_path(path: &str) -> bool {
+    !path.is_empty()
+        && Path::new(path)
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+}
+
+fn hash_file(path: &Path) -> io::Result<u64> {
+    let mut file = File::open(path)?;
+    let mut hasher = Xxh64::new(0);
+    let mut buf = vec![0; 64 * 1024];
+
+    loop {
+        match file.read(&mut buf) {
+            Ok(0) => return Ok(hasher.digest()),
+            Ok(len) => hasher.update(&buf[..len]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+fn output_path(path: &Path) -> PathBuf {
+    let mut output = path.as_os_str().to_owned();
+    output.push(".br");
+    PathBuf::from(output)
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    PathBuf::from(temp)
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
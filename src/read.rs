@@ -0,0 +1,343 @@
+//! Module that contains compression and decompression readers over plain [`Read`]
+//!
+//! The readers in the [`bufread`] module wrap a [`BufRead`], so a plain [`Read`] such as a
+//! [`File`] or a [`TcpStream`] must be wrapped in a [`BufReader`] first. The readers in this
+//! module wrap any [`Read`] and buffer its input internally instead. The capacity of the internal
+//! buffer defaults to that of a [`BufReader`] and can be configured using `with_capacity`.
+//!
+//! Input that is already buffered, such as a byte slice, is better read using the readers in
+//! the [`bufread`] module, which do not buffer it once more.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::fs::File;
+//! use std::io::{self, Read};
+//! use brotlic::read::DecompressorReader;
+//!
+//! let input = File::open("test.brotli")?; // no BufReader required
+//! let mut decompressor = DecompressorReader::new(input);
+//! let mut decompressed = String::new();
+//!
+//! decompressor.read_to_string(&mut decompressed)?;
+//! # Ok::<(), io::Error>(())
+//! ```
+//!
+//! [`bufread`]: crate::bufread
+//! [`Read`]: https://doc.rust-lang.org/stable/std/io/trait.Read.html
+//! [`BufRead`]: https://doc.rust-lang.org/stable/std/io/trait.BufRead.html
+//! [`BufReader`]: https://doc.rust-lang.org/stable/std/io/struct.BufReader.html
+//! [`File`]: https://doc.rust-lang.org/stable/std/fs/struct.File.html
+//! [`TcpStream`]: https://doc.rust-lang.org/stable/std/net/struct.TcpStream.html
+
+use crate::decode::Metadata;
+use crate::header::Header;
+use crate::{AllocError, BrotliDecoder, BrotliEncoder, IntoInnerError, bufread};
+use std::alloc::GlobalAlloc;
+use std::io::{self, BufReader, Read};
+
+/// Wraps a reader and compresses its output.
+///
+/// Like [`bufread::CompressorReader`], but wraps any reader by buffering its input internally.
+/// The compression stream is finished when the underlying reader reaches EOF. When [`read`]
+/// returns zero on a non-zero buffer, the compression is considered finished.
+///
+/// # Examples
+///
+/// ```
+/// use std::io::Read;
+/// use brotlic::read::CompressorReader;
+/// use brotlic::BrotliEncoder;
+///
+/// let input = b"hello world".as_slice();
+/// let mut compressor = CompressorReader::with_capacity(16, BrotliEncoder::new(), input);
+/// let mut compressed = Vec::new();
+///
+/// compressor.read_to_end(&mut compressed)?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+///
+/// [`read`]: CompressorReader::read
+#[derive(Debug)]
+pub struct CompressorReader<R: Read> {
+    // boxed, so that errors returning the reader stay small
+    inner: Box<bufread::CompressorReader<BufReader<R>>>,
+}
+
+impl<R: Read> CompressorReader<R> {
+    /// Creates a new `CompressorReader<R>` with a newly created encoder.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the encoder fails to be allocated or initialized
+    pub fn new(inner: R) -> Self {
+        CompressorReader::with_encoder(BrotliEncoder::new(), inner)
+    }
+
+    /// Creates a new `CompressorReader<R>` with a newly created encoder using allocator `alloc`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the encoder fails to be allocated or initialized
+    pub fn new_in<A>(inner: R, alloc: A) -> Self
+    where
+        A: GlobalAlloc + 'static,
+    {
+        CompressorReader::with_encoder(BrotliEncoder::new_in(alloc), inner)
+    }
+
+    /// Attempts to create a new `CompressorReader<R>` with a newly created encoder.
+    ///
+    /// # Errors
+    ///
+    /// An [`AllocError`] will be returned if the encoder fails to be allocated or initialized.
+    pub fn try_new(inner: R) -> Result<Self, AllocError> {
+        Ok(CompressorReader::with_encoder(BrotliEncoder::try_new()?, inner))
+    }
+
+    /// Attempts to create a new `CompressorReader<R>` with a newly created encoder using allocator
+    /// `alloc`.
+    ///
+    /// # Errors
+    ///
+    /// An [`AllocError`] will be returned if the encoder fails to be allocated or initialized.
+    pub fn try_new_in<A>(inner: R, alloc: A) -> Result<Self, AllocError>
+    where
+        A: GlobalAlloc + 'static,
+    {
+        Ok(CompressorReader::with_encoder(BrotliEncoder::try_new_in(alloc)?, inner))
+    }
+
+    /// Creates a new `CompressorReader<R>` with a specified encoder.
+    pub fn with_encoder(encoder: BrotliEncoder, inner: R) -> Self {
+        CompressorReader {
+            inner: Box::new(bufread::CompressorReader::with_encoder(
+                encoder,
+                BufReader::new(inner),
+            )),
+        }
+    }
+
+    /// Creates a new `CompressorReader<R>` with a specified encoder, which buffers up to
+    /// `capacity` bytes of input at a time.
+    pub fn with_capacity(capacity: usize, encoder: BrotliEncoder, inner: R) -> Self {
+        let inner = BufReader::with_capacity(capacity, inner);
+
+        CompressorReader {
+            inner: Box::new(bufread::CompressorReader::with_encoder(encoder, inner)),
+        }
+    }
+
+    /// Gets a reference to the underlying reader
+    pub fn get_ref(&self) -> &R {
+        self.inner.get_ref().get_ref()
+    }
+
+    /// Gets a mutable reference to the underlying reader.
+    ///
+    /// It is inadvisable to directly read from the underlying reader.
+    pub fn get_mut(&mut self) -> &mut R {
+        self.inner.get_mut().get_mut()
+    }
+
+    /// Unwraps this `CompressorReader<R>`, returning the underlying reader.
+    ///
+    /// # Errors
+    ///
+    /// An [`Err`] will be returned if the compression stream has not been finished.
+    pub fn into_inner(self) -> Result<R, IntoInnerError<CompressorReader<R>>> {
+        match (*self.inner).into_inner() {
+            Ok(inner) => Ok(inner.into_inner()),
+            Err(err) => {
+                let (error, inner) = err.into_parts();
+                let inner = Box::new(inner);
+
+                Err(IntoInnerError::new(CompressorReader { inner }, error))
+            }
+        }
+    }
+
+    /// Disassembles this `CompressorReader<R>`, returning the underlying reader and encoder.
+    ///
+    /// `into_parts` makes no attempt to validate that the compression stream finished and cannot
+    /// fail. Any input that was buffered but not compressed yet is lost.
+    pub fn into_parts(self) -> (R, BrotliEncoder) {
+        let (inner, encoder) = (*self.inner).into_parts();
+
+        (inner.into_inner(), encoder)
+    }
+}
+
+impl<R: Read> Read for CompressorReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+/// Wraps a reader and decompresses its output.
+///
+/// Like [`bufread::DecompressorReader`], but wraps any reader by buffering its input internally.
+/// As input is read ahead into the buffer, the underlying reader is generally not positioned
+/// right after the decompression stream once it is finished. Use [`bufread::DecompressorReader`]
+/// instead to read any trailing data after the stream.
+///
+/// # Examples
+///
+/// ```
+/// use std::io::{Read, Write};
+/// use brotlic::CompressorWriter;
+/// use brotlic::read::DecompressorReader;
+///
+/// let mut compressor = CompressorWriter::new(Vec::new());
+/// compressor.write_all(b"hello world")?;
+/// let compressed = compressor.into_inner()?;
+///
+/// let mut decompressor = DecompressorReader::new(compressed.as_slice());
+/// let mut decompressed = String::new();
+/// decompressor.read_to_string(&mut decompressed)?;
+///
+/// assert_eq!(decompressed, "hello world");
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug)]
+pub struct DecompressorReader<R: Read> {
+    // boxed, so that errors returning the reader stay small
+    inner: Box<bufread::DecompressorReader<BufReader<R>>>,
+}
+
+impl<R: Read> DecompressorReader<R> {
+    /// Creates a new `DecompressorReader<R>` with a newly created decoder.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the decoder fails to be allocated or initialized
+    pub fn new(inner: R) -> Self {
+        DecompressorReader::with_decoder(BrotliDecoder::new(), inner)
+    }
+
+    /// Creates a new `DecompressorReader<R>` with a newly created decoder using allocator `alloc`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the decoder fails to be allocated or initialized
+    pub fn new_in<A>(inner: R, alloc: A) -> Self
+    where
+        A: GlobalAlloc + 'static,
+    {
+        DecompressorReader::with_decoder(BrotliDecoder::new_in(alloc), inner)
+    }
+
+    /// Attempts to create a new `DecompressorReader<R>` with a newly created decoder.
+    ///
+    /// # Errors
+    ///
+    /// An [`AllocError`] will be returned if the decoder fails to be allocated or initialized.
+    pub fn try_new(inner: R) -> Result<Self, AllocError> {
+        Ok(DecompressorReader::with_decoder(BrotliDecoder::try_new()?, inner))
+    }
+
+    /// Attempts to create a new `DecompressorReader<R>` with a newly created decoder using
+    /// allocator `alloc`.
+    ///
+    /// # Errors
+    ///
+    /// An [`AllocError`] will be returned if the decoder fails to be allocated or initialized.
+    pub fn try_new_in<A>(inner: R, alloc: A) -> Result<Self, AllocError>
+    where
+        A: GlobalAlloc + 'static,
+    {
+        Ok(DecompressorReader::with_decoder(BrotliDecoder::try_new_in(alloc)?, inner))
+    }
+
+    /// Creates a new `DecompressorReader<R>` with a specified decoder.
+    pub fn with_decoder(decoder: BrotliDecoder, inner: R) -> Self {
+        DecompressorReader {
+            inner: Box::new(bufread::DecompressorReader::with_decoder(
+                decoder,
+                BufReader::new(inner),
+            )),
+        }
+    }
+
+    /// Creates a new `DecompressorReader<R>` with a specified decoder, which buffers up to
+    /// `capacity` bytes of input at a time.
+    pub fn with_capacity(capacity: usize, decoder: BrotliDecoder, inner: R) -> Self {
+        let inner = BufReader::with_capacity(capacity, inner);
+
+        DecompressorReader {
+            inner: Box::new(bufread::DecompressorReader::with_decoder(decoder, inner)),
+        }
+    }
+
+    /// Gets a reference to the underlying reader
+    pub fn get_ref(&self) -> &R {
+        self.inner.get_ref().get_ref()
+    }
+
+    /// Gets a mutable reference to the underlying reader.
+    ///
+    /// It is inadvisable to directly read from the underlying reader.
+    pub fn get_mut(&mut self) -> &mut R {
+        self.inner.get_mut().get_mut()
+    }
+
+    /// Unwraps this `DecompressorReader<R>`, returning the underlying reader.
+    ///
+    /// Any input that was buffered past the end of the decompression stream is lost.
+    ///
+    /// # Errors
+    ///
+    /// An [`Err`] will be returned if the decompression stream has not been finished.
+    pub fn into_inner(self) -> Result<R, IntoInnerError<DecompressorReader<R>>> {
+        match (*self.inner).into_inner() {
+            Ok(inner) => Ok(inner.into_inner()),
+            Err(err) => {
+                let (error, inner) = err.into_parts();
+                let inner = Box::new(inner);
+
+                Err(IntoInnerError::new(DecompressorReader { inner }, error))
+            }
+        }
+    }
+
+    /// Removes the oldest captured metadata block from the queue and returns it. For more
+    /// information see [`BrotliDecoder::pop_metadata`].
+    pub fn pop_metadata(&mut self) -> Option<Metadata> {
+        self.inner.pop_metadata()
+    }
+
+    /// Returns the number of bytes of the compression stream consumed so far.
+    ///
+    /// Unlike the number of bytes read from the underlying reader, this excludes any input that
+    /// is buffered but not decompressed yet.
+    pub fn bytes_consumed(&self) -> u64 {
+        self.inner.bytes_consumed()
+    }
+
+    /// Reads and parses the [`Header`] of the stream. For more information see
+    /// [`bufread::DecompressorReader::header`].
+    ///
+    /// # Errors
+    ///
+    /// An [`Err`] of kind [`io::ErrorKind::InvalidData`] will be returned if the header is
+    /// malformed. Any error from the decoder or the underlying reader is returned as well.
+    pub fn header(&mut self) -> io::Result<Option<&Header>> {
+        self.inner.header()
+    }
+
+    /// Disassembles this `DecompressorReader<R>`, returning the underlying reader and decoder.
+    ///
+    /// `into_parts` makes no attempt to validate that the decompression stream finished and cannot
+    /// fail. Any input that was buffered but not decompressed yet is lost.
+    pub fn into_parts(self) -> (R, BrotliDecoder) {
+        let (inner, decoder) = (*self.inner).into_parts();
+
+        (inner.into_inner(), decoder)
+    }
+}
+
+impl<R: Read> Read for DecompressorReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
@@ -0,0 +1,1008 @@
+//! Module that contains a seekable brotli format for random access
+//!
+//! Brotli streams can only be decompressed from the start. The seekable format splits the
+//! uncompressed data into frames that are compressed independently, and records an index mapping
+//! uncompressed offsets to compressed offsets at the end of the stream. This allows
+//! [`SeekableDecompressorReader`] to start decompressing at the frame containing any offset,
+//! similar to zstd's seekable format.
+//!
+//! A seekable stream is a regular brotli stream that can be decompressed as a whole by any brotli
+//! decoder. Frames are stitched together like [`ParallelCompressorWriter`] does, and the index is
+//! stored in metadata blocks right before the end of the stream, which decoders skip. The last
+//! metadata block holds a fixed size footer, so the index can be found from the end of the
+//! stream.
+//!
+//! Every frame but the first one is compressed as if all preceding data was known, so that
+//! references into brotli's built-in dictionary are encoded the same way as in a single stream.
+//! To decompress a frame on its own, the decoder is first fed uncompressed meta-blocks of
+//! arbitrary bytes that fill the sliding window, and their output is discarded. Seeking hence
+//! costs copying up to a window of data in addition to decompressing the frame itself.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+//! use brotlic::seekable::{SeekableCompressorOptions, SeekableDecompressorReader};
+//!
+//! let input: Vec<u8> = (0..100_000u32).flat_map(|i| i.to_le_bytes()).collect();
+//!
+//! let mut compressor = SeekableCompressorOptions::new()
+//!     .frame_size(1 << 16)
+//!     .build(Vec::new())?;
+//! compressor.write_all(&input)?;
+//! let compressed = compressor.into_inner()?;
+//!
+//! let mut decompressor = SeekableDecompressorReader::new(Cursor::new(compressed))?;
+//! let mut buf = [0; 4];
+//! decompressor.seek(SeekFrom::Start(4 * 54_321))?;
+//! decompressor.read_exact(&mut buf)?;
+//!
+//! assert_eq!(u32::from_le_bytes(buf), 54_321);
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! [`ParallelCompressorWriter`]: crate::parallel::ParallelCompressorWriter
+
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::{cmp, mem, ptr};
+
+use crate::decode::{BrotliDecoder, BrotliDecoderOptions, DecoderInfo};
+use crate::encode::{
+    BrotliEncoder, BrotliEncoderOptions, BrotliOperation, ChecksumState, MAX_METADATA_SIZE,
+};
+use crate::{BuildError, IntoInnerError, SetParameterError};
+
+/// The default number of uncompressed bytes per frame (1 MiB).
+pub const DEFAULT_FRAME_SIZE: usize = 1 << 20;
+
+const INDEX_MAGIC: &[u8; 4] = b"BRSI";
+const FOOTER_MAGIC: &[u8; 4] = b"BRSK";
+const VERSION: u8 = 1;
+
+const ENTRY_LEN: usize = 16;
+const FOOTER_LEN: usize = 24;
+
+/// The maximum number of bytes following the footer, i.e. the last meta-block of the stream.
+const MAX_TRAILER_LEN: usize = 4;
+
+/// The largest stream offset accepted by the encoder, larger offsets have the same effect.
+const MAX_STREAM_OFFSET: u64 = 1 << 30;
+
+/// The largest number of bytes in a single meta-block.
+const MAX_META_BLOCK_LEN: u64 = 1 << 24;
+
+const BUFFER_SIZE: usize = 32 * 1024;
+
+/// Options to build a [`SeekableCompressorWriter`].
+#[derive(Debug, Clone)]
+pub struct SeekableCompressorOptions {
+    encoder: BrotliEncoderOptions,
+    frame_size: usize,
+}
+
+impl SeekableCompressorOptions {
+    /// Creates a new set of options, using default encoder options and a frame size of
+    /// [`DEFAULT_FRAME_SIZE`].
+    pub fn new() -> Self {
+        SeekableCompressorOptions {
+            encoder: BrotliEncoderOptions::new(),
+            frame_size: DEFAULT_FRAME_SIZE,
+        }
+    }
+
+    /// Sets the options of the encoders that compress the frames.
+    ///
+    /// The stream offset and size hint are set for each frame individually, so these options are
+    /// overridden. Checksums are computed over all of the input and behave like they do for
+    /// [`CompressorWriter`].
+    ///
+    /// [`CompressorWriter`]: crate::encode::CompressorWriter
+    pub fn encoder_options(&mut self, encoder: &BrotliEncoderOptions) -> &mut Self {
+        self.encoder = encoder.clone();
+        self
+    }
+
+    /// Sets the number of uncompressed bytes per frame.
+    ///
+    /// Smaller frames make seeking cheaper, while larger frames improve the compression ratio. A
+    /// frame size of zero is treated as one.
+    pub fn frame_size(&mut self, frame_size: usize) -> &mut Self {
+        self.frame_size = frame_size.max(1);
+        self
+    }
+
+    /// Creates a new [`SeekableCompressorWriter`] writing to `inner` from these options.
+    ///
+    /// # Errors
+    ///
+    /// An [`Err`] will be returned if the encoder options are invalid.
+    pub fn build<W: Write>(
+        &self,
+        inner: W,
+    ) -> Result<SeekableCompressorWriter<W>, SetParameterError> {
+        let mut encoder = self.encoder.clone();
+        let checksum = ChecksumState::take(&mut encoder);
+
+        // validate the options as used for all but the first frame, failing to allocate the encoder
+        // is reported once a frame is compressed
+        if let Err(BuildError::SetParameter(err)) = encoder.clone().stream_offset(1).try_build() {
+            return Err(err);
+        }
+
+        Ok(SeekableCompressorWriter {
+            inner,
+            state: Box::new(WriterState {
+                options: encoder,
+                encoder: None,
+                frame_size: self.frame_size as u64,
+                frame_in: 0,
+                frame_out: 0,
+                total_in: 0,
+                total_out: 0,
+                frames: Vec::new(),
+                checksum,
+                finished: false,
+            }),
+            panicked: false,
+        })
+    }
+}
+
+impl Default for SeekableCompressorOptions {
+    fn default() -> Self {
+        SeekableCompressorOptions::new()
+    }
+}
+
+/// Wraps a writer and compresses its output in the seekable format.
+///
+/// `SeekableCompressorWriter<W>` behaves like [`CompressorWriter`], except that its input is split
+/// into independently compressed frames and an index of the frames is written once the stream is
+/// finished, see the [module level documentation] for details.
+///
+/// It is critical to finish the compression stream, otherwise neither decompression nor seeking
+/// will be successful. Dropping will attempt to finish the compression stream, any errors that
+/// might arise however will be ignored. Calling [`into_inner`] ensures that the compression
+/// stream is finished.
+///
+/// Calling [`flush`] ends the current frame, so that all data written so far can be decompressed.
+/// Frames that are shorter than the frame size lead to a worse compression ratio.
+///
+/// [`CompressorWriter`]: crate::encode::CompressorWriter
+/// [module level documentation]: crate::seekable
+/// [`into_inner`]: SeekableCompressorWriter::into_inner
+/// [`flush`]: SeekableCompressorWriter::flush
+#[derive(Debug)]
+pub struct SeekableCompressorWriter<W: Write> {
+    inner: W,
+    state: Box<WriterState>,
+    panicked: bool,
+}
+
+#[derive(Debug)]
+struct WriterState {
+    options: BrotliEncoderOptions,
+    encoder: Option<BrotliEncoder>,
+    frame_size: u64,
+    frame_in: u64,
+    frame_out: u64,
+    total_in: u64,
+    total_out: u64,
+    frames: Vec<(u64, u64)>,
+    checksum: ChecksumState,
+    finished: bool,
+}
+
+impl<W: Write> SeekableCompressorWriter<W> {
+    /// Creates a new `SeekableCompressorWriter<W>` using default options, see
+    /// [`SeekableCompressorOptions::new`].
+    pub fn new(inner: W) -> Self {
+        SeekableCompressorOptions::new()
+            .build(inner)
+            .expect("default options are valid")
+    }
+
+    /// Gets a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Gets a mutable reference to the underlying writer.
+    ///
+    /// It is inadvisable to directly write to the underlying writer.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Unwraps this `SeekableCompressorWriter<W>`, returning the underlying writer.
+    ///
+    /// The compression stream is finished and the index is written before returning the writer.
+    ///
+    /// # Errors
+    ///
+    /// An [`Err`] will be returned if an error occurs while finishing the compression stream.
+    pub fn into_inner(mut self) -> Result<W, IntoInnerError<SeekableCompressorWriter<W>>> {
+        if let Err(e) = self.finish() {
+            return Err(IntoInnerError::new(self, e));
+        }
+
+        let inner = unsafe { ptr::read(&self.inner) };
+        drop(unsafe { ptr::read(&self.state) });
+        mem::forget(self);
+
+        Ok(inner)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if self.state.finished {
+            return Ok(());
+        }
+
+        if self.state.encoder.is_some() {
+            self.end_frame(None)?;
+        }
+
+        let index_offset = self.state.total_out;
+        self.start_frame()?;
+
+        if let Some(record) = self.state.checksum.record_for(BrotliOperation::Finish) {
+            self.emit_metadata(&record)?;
+        }
+
+        let frames = mem::take(&mut self.state.frames);
+
+        for chunk in frames.chunks((MAX_METADATA_SIZE - INDEX_MAGIC.len()) / ENTRY_LEN) {
+            let mut block = Vec::with_capacity(INDEX_MAGIC.len() + chunk.len() * ENTRY_LEN);
+            block.extend_from_slice(INDEX_MAGIC);
+
+            for &(compressed_len, uncompressed_len) in chunk {
+                block.extend_from_slice(&compressed_len.to_le_bytes());
+                block.extend_from_slice(&uncompressed_len.to_le_bytes());
+            }
+
+            self.emit_metadata(&block)?;
+        }
+
+        let mut footer = Vec::with_capacity(FOOTER_LEN);
+        footer.extend_from_slice(&(frames.len() as u64).to_le_bytes());
+        footer.extend_from_slice(&index_offset.to_le_bytes());
+        footer.extend_from_slice(&[VERSION, 0, 0, 0]);
+        footer.extend_from_slice(FOOTER_MAGIC);
+
+        self.emit_metadata(&footer)?;
+        self.complete(BrotliOperation::Finish)?;
+        self.state.encoder = None;
+        self.state.finished = true;
+
+        Ok(())
+    }
+
+    fn start_frame(&mut self) -> io::Result<()> {
+        let mut options = self.state.options.clone();
+        options.stream_offset(cmp::min(self.state.total_in, MAX_STREAM_OFFSET) as u32);
+
+        if let Ok(size_hint) = u32::try_from(self.state.frame_size) {
+            options.size_hint(size_hint);
+        }
+
+        let encoder = options.try_build()?;
+
+        self.state.encoder = Some(encoder);
+        Ok(())
+    }
+
+    fn end_frame(&mut self, metadata: Option<Vec<u8>>) -> io::Result<()> {
+        if let Some(metadata) = metadata {
+            self.emit_metadata(&metadata)?;
+        }
+
+        // the next frame must start at a byte boundary
+        self.complete(BrotliOperation::Flush)?;
+
+        let state = &mut *self.state;
+        state.frames.push((state.frame_out, state.frame_in));
+        state.encoder = None;
+        state.frame_in = 0;
+        state.frame_out = 0;
+
+        Ok(())
+    }
+
+    fn emit_metadata(&mut self, metadata: &[u8]) -> io::Result<()> {
+        let state = &mut *self.state;
+        let encoder = state.encoder.as_mut().expect("frame is started");
+        let inner = &mut self.inner;
+        let panicked = &mut self.panicked;
+
+        encoder.write_metadata(metadata, |output| {
+            *panicked = true;
+            let r = inner.write_all(output);
+            *panicked = false;
+            r?;
+
+            state.frame_out += output.len() as u64;
+            state.total_out += output.len() as u64;
+            Ok(())
+        })
+    }
+
+    fn complete(&mut self, op: BrotliOperation) -> io::Result<()> {
+        loop {
+            let encoder = self.state.encoder.as_mut().expect("frame is started");
+            encoder.give_input(&[], op)?;
+
+            let done = match op {
+                BrotliOperation::Finish => encoder.is_finished(),
+                _ => !encoder.has_output(),
+            };
+
+            self.flush_encoder_output()?;
+
+            if done {
+                return Ok(());
+            }
+        }
+    }
+
+    fn flush_encoder_output(&mut self) -> io::Result<()> {
+        let state = &mut *self.state;
+        let encoder = state.encoder.as_mut().expect("frame is started");
+
+        encoder.drain_output(|output| {
+            self.panicked = true;
+            let r = self.inner.write_all(output);
+            self.panicked = false;
+            r?;
+
+            state.frame_out += output.len() as u64;
+            state.total_out += output.len() as u64;
+            Ok(())
+        })
+    }
+}
+
+impl<W: Write> Write for SeekableCompressorWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.state.finished {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the compression stream is already finished",
+            ));
+        }
+
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        if self.state.encoder.is_none() {
+            self.start_frame()?;
+
+            // the checksum declaring the algorithm starts the first frame
+            if let Some(record) = self.state.checksum.take_record() {
+                self.emit_metadata(&record)?;
+            }
+        }
+
+        let state = &mut *self.state;
+        let len = cmp::min(buf.len() as u64, state.frame_size - state.frame_in) as usize;
+        let encoder = state.encoder.as_mut().expect("frame is started");
+        let bytes_read = encoder.give_input(&buf[..len], BrotliOperation::Process)?;
+        state.checksum.update(&buf[..bytes_read]);
+
+        state.frame_in += bytes_read as u64;
+        state.total_in += bytes_read as u64;
+        self.flush_encoder_output()?;
+
+        if self.state.frame_in == self.state.frame_size {
+            self.end_frame(None)?;
+        }
+
+        Ok(bytes_read)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.state.finished && self.state.total_in > 0 {
+            let metadata = self.state.checksum.record_for(BrotliOperation::Flush);
+
+            if self.state.encoder.is_none() && metadata.is_some() {
+                self.start_frame()?;
+            }
+
+            if self.state.encoder.is_some() {
+                self.end_frame(metadata)?;
+            }
+        }
+
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Drop for SeekableCompressorWriter<W> {
+    fn drop(&mut self) {
+        if !self.panicked {
+            let _r = self.finish();
+        }
+    }
+}
+
+/// The index of a seekable brotli stream, listing all of its frames.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Index {
+    frames: Vec<Frame>,
+    window: Option<Window>,
+}
+
+/// A frame of a seekable brotli stream, see [`Index`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Frame {
+    compressed_offset: u64,
+    compressed_len: u64,
+    uncompressed_offset: u64,
+    uncompressed_len: u64,
+}
+
+/// The sliding window size of a stream as declared in its stream header.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+struct Window {
+    bits: u8,
+    large: bool,
+}
+
+impl Index {
+    /// Reads the index of the seekable brotli stream `reader`, starting from its end.
+    ///
+    /// The position of `reader` is unspecified afterwards.
+    ///
+    /// # Errors
+    ///
+    /// An [`Err`] of kind [`io::ErrorKind::InvalidData`] will be returned if the stream does not
+    /// end with an index of the seekable format or the index is malformed.
+    pub fn read<R: Read + Seek>(reader: &mut R) -> io::Result<Index> {
+        let len = reader.seek(SeekFrom::End(0))?;
+        let mut tail = vec![0; cmp::min(len, (FOOTER_LEN + MAX_TRAILER_LEN) as u64) as usize];
+
+        reader.seek(SeekFrom::Start(len - tail.len() as u64))?;
+        reader.read_exact(&mut tail)?;
+
+        let footer = (1..=MAX_TRAILER_LEN)
+            .filter_map(|trailer_len| tail.len().checked_sub(trailer_len))
+            .filter_map(|end| tail.get(end.checked_sub(FOOTER_LEN)?..end))
+            .find(|footer| footer.ends_with(FOOTER_MAGIC))
+            .ok_or_else(|| invalid_data("the stream does not end with a seekable index"))?;
+
+        let frame_count = u64::from_le_bytes(footer[..8].try_into().unwrap());
+        let index_offset = u64::from_le_bytes(footer[8..16].try_into().unwrap());
+
+        if footer[16] != VERSION {
+            return Err(invalid_data("unsupported version of the seekable format"));
+        }
+
+        if index_offset >= len {
+            return Err(invalid_data("malformed seekable index"));
+        }
+
+        let window = if index_offset > 0 {
+            let mut header = [0; 2];
+            reader.seek(SeekFrom::Start(0))?;
+            reader.read_exact(&mut header)?;
+
+            Some(Window::parse(header).ok_or_else(|| invalid_data("invalid stream header"))?)
+        } else {
+            None
+        };
+
+        let mut decoder = BrotliDecoderOptions::new()
+            .large_window_size(window.is_some_and(|window| window.large))
+            .capture_metadata(true)
+            .try_build()?;
+
+        if let Some(window) = window {
+            window.prime(&mut decoder, 0)?;
+        }
+
+        let mut compressed = Vec::new();
+        reader.seek(SeekFrom::Start(index_offset))?;
+        reader
+            .take(len - index_offset)
+            .read_to_end(&mut compressed)?;
+        feed(&mut decoder, &compressed)?;
+
+        if !decoder.is_finished() {
+            return Err(invalid_data("malformed seekable index"));
+        }
+
+        let mut frames = Vec::new();
+        let mut compressed_offset = 0;
+        let mut uncompressed_offset = 0;
+
+        while let Some(metadata) = decoder.pop_metadata() {
+            let entries = match metadata.data().strip_prefix(INDEX_MAGIC) {
+                Some(entries) if entries.len() % ENTRY_LEN == 0 => entries,
+                _ => continue,
+            };
+
+            for entry in entries.chunks_exact(ENTRY_LEN) {
+                let compressed_len = u64::from_le_bytes(entry[..8].try_into().unwrap());
+                let uncompressed_len = u64::from_le_bytes(entry[8..].try_into().unwrap());
+
+                frames.push(Frame {
+                    compressed_offset,
+                    compressed_len,
+                    uncompressed_offset,
+                    uncompressed_len,
+                });
+
+                compressed_offset = compressed_offset
+                    .checked_add(compressed_len)
+                    .ok_or_else(|| invalid_data("malformed seekable index"))?;
+                uncompressed_offset = uncompressed_offset
+                    .checked_add(uncompressed_len)
+                    .ok_or_else(|| invalid_data("malformed seekable index"))?;
+            }
+        }
+
+        if frames.len() as u64 != frame_count || compressed_offset != index_offset {
+            return Err(invalid_data("malformed seekable index"));
+        }
+
+        Ok(Index { frames, window })
+    }
+
+    /// Returns all frames of the stream, ordered by their offsets.
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    /// Returns the length of the stream when decompressed.
+    pub fn uncompressed_len(&self) -> u64 {
+        self.frames.last().map_or(0, |frame| {
+            frame.uncompressed_offset + frame.uncompressed_len
+        })
+    }
+
+    /// Returns the length of the compressed frames, excluding the index.
+    pub fn compressed_len(&self) -> u64 {
+        self.frames
+            .last()
+            .map_or(0, |frame| frame.compressed_offset + frame.compressed_len)
+    }
+
+    /// Returns the position of the frame containing the uncompressed `offset` in [`Self::frames`],
+    /// or [`None`] if `offset` is past the end of the stream.
+    pub fn find(&self, offset: u64) -> Option<usize> {
+        let position = self
+            .frames
+            .partition_point(|frame| frame.uncompressed_offset + frame.uncompressed_len <= offset);
+
+        (position < self.frames.len()).then_some(position)
+    }
+
+    /// Creates a decoder that is ready to decompress the stream starting at `frame`.
+    pub(crate) fn frame_decoder(&self, frame: &Frame) -> io::Result<BrotliDecoder> {
+        let mut decoder = BrotliDecoderOptions::new()
+            .large_window_size(self.window.is_some_and(|window| window.large))
+            .try_build()?;
+
+        // the first frame starts with the stream header, all others are primed with a prefix
+        if let Some(window) = self.window.filter(|_| frame.compressed_offset > 0) {
+            let primed_len = cmp::min(frame.uncompressed_offset, window.max_distance());
+            window.prime(&mut decoder, primed_len)?;
+        }
+
+        Ok(decoder)
+    }
+
+    /// Decompresses `frame` from its compressed bytes `input` into `output`, which must be exactly
+    /// as long as the frame.
+    pub(crate) fn decompress_frame(
+        &self,
+        frame: &Frame,
+        mut input: &[u8],
+        mut output: &mut [u8],
+    ) -> io::Result<()> {
+        let mut decoder = self.frame_decoder(frame)?;
+
+        loop {
+            let res = decoder.decompress(input, output)?;
+            input = &input[res.bytes_read..];
+            output = &mut output[res.bytes_written..];
+
+            match res.info {
+                DecoderInfo::NeedsMoreOutput if output.is_empty() => {
+                    return Err(invalid_data("a frame is longer than indexed"));
+                }
+                DecoderInfo::NeedsMoreInput if input.is_empty() && !decoder.has_output() => {
+                    return match output.is_empty() {
+                        true => Ok(()),
+                        false => Err(invalid_data("a frame is shorter than indexed")),
+                    };
+                }
+                DecoderInfo::Finished => {
+                    return Err(invalid_data("the stream ended before the seekable index"));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Decompresses `frame` from its compressed bytes `input` into a new vector.
+    ///
+    /// The length of the frame is read from the untrusted index, so the vector grows as the frame
+    /// is decompressed instead of being allocated up front, but never beyond the indexed length.
+    pub(crate) fn decompress_frame_to_vec(
+        &self,
+        frame: &Frame,
+        mut input: &[u8],
+    ) -> io::Result<Vec<u8>> {
+        let mut decoder = self.frame_decoder(frame)?;
+        let mut output = Vec::new();
+
+        loop {
+            let remaining = frame.uncompressed_len - output.len() as u64;
+
+            if output.len() == output.capacity() && remaining > 0 {
+                let additional = cmp::max(output.len(), BUFFER_SIZE) as u64;
+                output.reserve_exact(cmp::min(additional, remaining) as usize);
+            }
+
+            let res = decoder.decompress_into_vec(input, &mut output)?;
+            input = &input[res.bytes_read..];
+
+            if output.len() as u64 > frame.uncompressed_len {
+                return Err(invalid_data("a frame is longer than indexed"));
+            }
+
+            match res.info {
+                DecoderInfo::NeedsMoreOutput if output.len() as u64 == frame.uncompressed_len => {
+                    return Err(invalid_data("a frame is longer than indexed"));
+                }
+                DecoderInfo::NeedsMoreInput if input.is_empty() && !decoder.has_output() => {
+                    return match output.len() as u64 == frame.uncompressed_len {
+                        true => Ok(output),
+                        false => Err(invalid_data("a frame is shorter than indexed")),
+                    };
+                }
+                DecoderInfo::Finished => {
+                    return Err(invalid_data("the stream ended before the seekable index"));
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Frame {
+    /// Returns the offset of the frame within the compressed stream.
+    pub fn compressed_offset(&self) -> u64 {
+        self.compressed_offset
+    }
+
+    /// Returns the length of the frame within the compressed stream.
+    pub fn compressed_len(&self) -> u64 {
+        self.compressed_len
+    }
+
+    /// Returns the offset of the frame within the decompressed stream.
+    pub fn uncompressed_offset(&self) -> u64 {
+        self.uncompressed_offset
+    }
+
+    /// Returns the length of the frame when decompressed.
+    pub fn uncompressed_len(&self) -> u64 {
+        self.uncompressed_len
+    }
+}
+
+impl Window {
+    /// Parses the window size from the first two bytes of a stream, see RFC 7932 section 9.1
+    /// and the large window extension of brotli.
+    fn parse(header: [u8; 2]) -> Option<Window> {
+        let bits = u16::from_le_bytes(header);
+
+        let window = |bits| Some(Window { bits, large: false });
+
+        if bits & 1 == 0 {
+            return window(16);
+        }
+
+        match (bits >> 1) & 7 {
+            0 => {}
+            n => return window(17 + n as u8),
+        }
+
+        match (bits >> 4) & 7 {
+            0 => window(17),
+            1 if (bits >> 7) & 1 == 0 => Some(Window {
+                bits: (bits >> 8) as u8 & 0x3f,
+                large: true,
+            }),
+            1 => None,
+            m => window(8 + m as u8),
+        }
+    }
+
+    /// Returns the maximum backward distance of the window.
+    fn max_distance(&self) -> u64 {
+        (1 << self.bits) - 16
+    }
+
+    /// Feeds `decoder` the start of a stream with this window, which decompresses to `len`
+    /// arbitrary bytes and ends at a byte boundary.
+    ///
+    /// The stream consists of uncompressed meta-blocks, which the decoder merely copies. If `len`
+    /// is zero, an empty metadata block aligns the stream instead.
+    fn prime(&self, decoder: &mut BrotliDecoder, mut len: u64) -> io::Result<()> {
+        let mut writer = BitWriter::default();
+
+        // WBITS, see RFC 7932 section 9.1
+        match self.bits {
+            bits if self.large => writer.write(14, (u64::from(bits) << 8) | 0x11),
+            16 => writer.write(1, 0),
+            17 => writer.write(7, 1),
+            bits if bits > 17 => writer.write(4, (u64::from(bits - 17) << 1) | 1),
+            bits => writer.write(7, (u64::from(bits - 8) << 4) | 1),
+        }
+
+        if len == 0 {
+            // ISLAST = 0, MNIBBLES = 0, reserved = 0, MSKIPBYTES = 0
+            writer.write(6, 0b110);
+            return feed(decoder, &writer.take_aligned());
+        }
+
+        let zeros = [0; BUFFER_SIZE];
+
+        while len > 0 {
+            let block_len = cmp::min(len, MAX_META_BLOCK_LEN);
+            let nibbles = match block_len - 1 {
+                n if n < 1 << 16 => 4,
+                n if n < 1 << 20 => 5,
+                _ => 6,
+            };
+
+            // ISLAST = 0, MNIBBLES, MLEN - 1, ISUNCOMPRESSED = 1
+            writer.write(1, 0);
+            writer.write(2, nibbles - 4);
+            writer.write(4 * nibbles as u32, block_len - 1);
+            writer.write(1, 1);
+            feed(decoder, &writer.take_aligned())?;
+
+            let mut remaining = block_len;
+
+            while remaining > 0 {
+                let chunk_len = cmp::min(remaining, BUFFER_SIZE as u64) as usize;
+                feed(decoder, &zeros[..chunk_len])?;
+                remaining -= chunk_len as u64;
+            }
+
+            len -= block_len;
+        }
+
+        Ok(())
+    }
+}
+
+/// Writes the bits of a brotli stream, starting with the least significant bit.
+#[derive(Debug, Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    bits: u64,
+    bit_count: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, bit_count: u32, value: u64) {
+        self.bits |= value << self.bit_count;
+        self.bit_count += bit_count;
+
+        while self.bit_count >= 8 {
+            self.bytes.push(self.bits as u8);
+            self.bits >>= 8;
+            self.bit_count -= 8;
+        }
+    }
+
+    /// Pads the written bits with zeros to a byte boundary and returns the bytes.
+    fn take_aligned(&mut self) -> Vec<u8> {
+        if self.bit_count > 0 {
+            self.bytes.push(self.bits as u8);
+            self.bits = 0;
+            self.bit_count = 0;
+        }
+
+        mem::take(&mut self.bytes)
+    }
+}
+
+/// Wraps a reader of a seekable brotli stream and decompresses it, allowing to seek within the
+/// decompressed data.
+///
+/// Reading sequentially decompresses the stream like [`DecompressorReader`] does. Seeking is
+/// cheap, the actual work is deferred until the next read. If the next read is not within the
+/// frame that is currently being decompressed, decompression restarts at the frame containing the
+/// new position. Seeking past the end is allowed, reads there return no data.
+///
+/// [`DecompressorReader`]: crate::decode::DecompressorReader
+#[derive(Debug)]
+pub struct SeekableDecompressorReader<R: Read + Seek> {
+    inner: R,
+    index: Index,
+    decoder: Option<BrotliDecoder>,
+    input: Box<[u8]>,
+    input_pos: usize,
+    input_len: usize,
+    remaining: u64,
+    pos: u64,
+    target: u64,
+}
+
+impl<R: Read + Seek> SeekableDecompressorReader<R> {
+    /// Creates a new `SeekableDecompressorReader<R>`, reading the index of the stream.
+    ///
+    /// # Errors
+    ///
+    /// An [`Err`] will be returned if reading the index fails, see [`Index::read`].
+    pub fn new(mut inner: R) -> io::Result<Self> {
+        let index = Index::read(&mut inner)?;
+
+        Ok(SeekableDecompressorReader {
+            inner,
+            index,
+            decoder: None,
+            input: vec![0; BUFFER_SIZE].into_boxed_slice(),
+            input_pos: 0,
+            input_len: 0,
+            remaining: 0,
+            pos: 0,
+            target: 0,
+        })
+    }
+
+    /// Returns the index of the stream.
+    pub fn index(&self) -> &Index {
+        &self.index
+    }
+
+    /// Gets a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Gets a mutable reference to the underlying reader.
+    ///
+    /// It is inadvisable to directly read from or seek the underlying reader.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Unwraps this `SeekableDecompressorReader<R>`, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn restart(&mut self, frame: usize) -> io::Result<()> {
+        let frame = self.index.frames[frame];
+
+        self.decoder = None;
+        self.inner.seek(SeekFrom::Start(frame.compressed_offset))?;
+        self.decoder = Some(self.index.frame_decoder(&frame)?);
+        self.input_pos = 0;
+        self.input_len = 0;
+        self.remaining = self.index.compressed_len() - frame.compressed_offset;
+        self.pos = frame.uncompressed_offset;
+
+        Ok(())
+    }
+
+    fn decompress(&mut self, output: &mut [u8]) -> io::Result<usize> {
+        let decoder = self.decoder.as_mut().expect("decompression is started");
+
+        loop {
+            if self.input_pos == self.input_len && self.remaining > 0 {
+                let len = cmp::min(self.remaining, self.input.len() as u64) as usize;
+                let bytes_read = match self.inner.read(&mut self.input[..len]) {
+                    Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                    Ok(bytes_read) => bytes_read,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(e),
+                };
+
+                self.input_pos = 0;
+                self.input_len = bytes_read;
+                self.remaining -= bytes_read as u64;
+            }
+
+            let input = &self.input[self.input_pos..self.input_len];
+            let res = decoder.decompress(input, output)?;
+            self.input_pos += res.bytes_read;
+
+            if res.bytes_written > 0 {
+                self.pos += res.bytes_written as u64;
+                return Ok(res.bytes_written);
+            }
+
+            match res.info {
+                DecoderInfo::NeedsMoreInput if self.remaining == 0 => {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+                DecoderInfo::Finished => {
+                    return Err(invalid_data("the stream ended before the seekable index"));
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+impl<R: Read + Seek> Read for SeekableDecompressorReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let frame = match self.index.find(self.target) {
+            Some(frame) if !buf.is_empty() => frame,
+            _ => return Ok(0),
+        };
+
+        let frame_offset = self.index.frames[frame].uncompressed_offset;
+
+        if self.decoder.is_none() || self.target < self.pos || frame_offset > self.pos {
+            self.restart(frame)?;
+        }
+
+        if self.pos < self.target {
+            let mut skipped =
+                vec![0; cmp::min(self.target - self.pos, BUFFER_SIZE as u64) as usize];
+
+            while self.pos < self.target {
+                let len = cmp::min(self.target - self.pos, skipped.len() as u64) as usize;
+                self.decompress(&mut skipped[..len])?;
+            }
+        }
+
+        let len = cmp::min(buf.len() as u64, self.index.uncompressed_len() - self.pos) as usize;
+        let bytes_read = self.decompress(&mut buf[..len])?;
+        self.target = self.pos;
+
+        Ok(bytes_read)
+    }
+}
+
+impl<R: Read + Seek> Seek for SeekableDecompressorReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.index.uncompressed_len().checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.target.checked_add_signed(offset),
+        };
+
+        match target {
+            Some(target) => {
+                self.target = target;
+                Ok(target)
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+}
+
+/// Feeds all of `input` to `decoder`, discarding its output.
+fn feed(decoder: &mut BrotliDecoder, mut input: &[u8]) -> io::Result<()> {
+    let mut output = vec![0; BUFFER_SIZE];
+
+    loop {
+        let res = decoder.decompress(input, &mut output)?;
+        input = &input[res.bytes_read..];
+
+        match res.info {
+            DecoderInfo::NeedsMoreOutput => {}
+            _ if decoder.has_output() => {}
+            _ if input.is_empty() => return Ok(()),
+            DecoderInfo::Finished => return Err(invalid_data("data after the end of the stream")),
+            DecoderInfo::NeedsMoreInput => {}
+        }
+    }
+}
+
+fn invalid_data(message: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
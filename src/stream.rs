@@ -0,0 +1,682 @@
+//! Module that contains compression and decompression adapters for streams and sinks of bytes
+//!
+//! Chunked pipelines such as HTTP bodies commonly deal in a [`Stream`] of [`Bytes`] rather than in
+//! readers and writers. [`CompressorStream`] and [`DecompressorStream`] turn such a stream into a
+//! stream of compressed or decompressed chunks, whereas [`CompressorSink`] and
+//! [`DecompressorSink`] compress or decompress the chunks sent to an underlying [`Sink`]. Output is
+//! taken from the encoder or decoder as is, so every chunk of output is copied only once. The
+//! adapters are built on a [`BrotliEncoder`] or [`BrotliDecoder`], so options like checksums and
+//! decompression limits apply as well.
+//!
+//! Errors of the encoder or decoder are converted from an [`io::Error`] into the error type of the
+//! underlying stream or sink.
+//!
+//! This module requires the `stream` feature.
+//!
+//! # Examples
+//!
+//! ```
+//! use brotlic::stream::{compress_stream, decompress_stream};
+//! use bytes::Bytes;
+//! use futures::{executor, stream, TryStreamExt};
+//!
+//! let chunks = stream::iter([
+//!     Ok::<_, std::io::Error>(Bytes::from_static(b"hello ")),
+//!     Ok(Bytes::from_static(b"world")),
+//! ]);
+//!
+//! let compressed = compress_stream(chunks).flush_each_chunk(true);
+//! let decompressed: Vec<Bytes> = executor::block_on(decompress_stream(compressed).try_collect())?;
+//!
+//! assert_eq!(decompressed.concat(), b"hello world");
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+
+use bytes::{Buf, Bytes};
+use futures_core::Stream;
+use futures_sink::Sink;
+use pin_project_lite::pin_project;
+
+use crate::AllocError;
+use crate::decode::{self, BrotliDecoder, Metadata};
+use crate::encode::{self, BrotliEncoder};
+
+/// Creates a [`CompressorStream`] that compresses the chunks of `inner` with a newly created
+/// encoder.
+///
+/// # Panics
+///
+/// Panics if the encoder fails to be allocated or initialized
+pub fn compress_stream<S>(inner: S) -> CompressorStream<S> {
+    CompressorStream::new(inner)
+}
+
+/// Creates a [`DecompressorStream`] that decompresses the chunks of `inner` with a newly created
+/// decoder.
+///
+/// # Panics
+///
+/// Panics if the decoder fails to be allocated or initialized
+pub fn decompress_stream<S>(inner: S) -> DecompressorStream<S> {
+    DecompressorStream::new(inner)
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Step {
+    Read,
+    Write,
+    Flush,
+    Finish,
+    Done,
+}
+
+pin_project! {
+    /// Wraps a stream of chunks and compresses them.
+    ///
+    /// Every item of the underlying stream is compressed, yielding chunks of compressed output as
+    /// the encoder produces them. Errors of the underlying stream are passed through. The
+    /// compression stream is finished when the underlying stream ends.
+    ///
+    /// By default, the encoder decides when to produce output, which gives the best compression.
+    /// For low-latency streaming, [`Self::flush_each_chunk`] flushes the compression stream after
+    /// every chunk, so that all data of a chunk can be decompressed once it was yielded.
+    #[derive(Debug)]
+    pub struct CompressorStream<S> {
+        #[pin]
+        inner: S,
+        state: encode::ChunkState,
+        input: Bytes,
+        step: Step,
+        flush_each_chunk: bool,
+    }
+}
+
+impl<S> CompressorStream<S> {
+    /// Creates a new `CompressorStream<S>` with a newly created encoder.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the encoder fails to be allocated or initialized
+    pub fn new(inner: S) -> Self {
+        CompressorStream::with_encoder(BrotliEncoder::new(), inner)
+    }
+
+    /// Attempts to create a new `CompressorStream<S>` with a newly created encoder.
+    ///
+    /// # Errors
+    ///
+    /// An [`AllocError`] will be returned if the encoder fails to be allocated or initialized.
+    pub fn try_new(inner: S) -> Result<Self, AllocError> {
+        Ok(CompressorStream::with_encoder(BrotliEncoder::try_new()?, inner))
+    }
+
+    /// Creates a new `CompressorStream<S>` with a specified encoder.
+    pub fn with_encoder(encoder: BrotliEncoder, inner: S) -> Self {
+        CompressorStream {
+            inner,
+            state: encode::ChunkState::new(encoder),
+            input: Bytes::new(),
+            step: Step::Read,
+            flush_each_chunk: false,
+        }
+    }
+
+    /// Sets whether the compression stream is flushed after every chunk of the underlying stream.
+    ///
+    /// Flushing lowers the latency of the compressed stream at the expense of compression ratio,
+    /// especially for small chunks.
+    pub fn flush_each_chunk(mut self, flush_each_chunk: bool) -> Self {
+        self.flush_each_chunk = flush_each_chunk;
+        self
+    }
+
+    /// Gets a reference to the underlying stream.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Gets a mutable reference to the underlying stream.
+    ///
+    /// It is inadvisable to directly poll the underlying stream.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Gets a pinned mutable reference to the underlying stream.
+    ///
+    /// It is inadvisable to directly poll the underlying stream.
+    pub fn get_pin_mut(self: Pin<&mut Self>) -> Pin<&mut S> {
+        self.project().inner
+    }
+
+    /// Returns `true` if the compression stream has been finished.
+    pub fn is_finished(&self) -> bool {
+        self.state.encoder().is_finished()
+    }
+
+    /// Unwraps this `CompressorStream<S>`, returning the underlying stream.
+    ///
+    /// This makes no attempt to validate that the compression stream finished.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    /// Disassembles this `CompressorStream<S>`, returning the underlying stream and encoder.
+    pub fn into_parts(self) -> (S, BrotliEncoder) {
+        (self.inner, self.state.into_encoder())
+    }
+}
+
+impl<S, E> Stream for CompressorStream<S>
+where
+    S: Stream<Item = Result<Bytes, E>>,
+    E: From<io::Error>,
+{
+    type Item = Result<Bytes, E>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        loop {
+            if let Some(output) = this.state.take_output() {
+                return Poll::Ready(Some(Ok(output)));
+            }
+
+            let res = match *this.step {
+                Step::Read => match ready!(this.inner.as_mut().poll_next(cx)) {
+                    Some(Ok(input)) => {
+                        *this.input = input;
+                        *this.step = Step::Write;
+                        Ok(())
+                    }
+                    Some(Err(err)) => return Poll::Ready(Some(Err(err))),
+                    None => {
+                        *this.step = Step::Finish;
+                        Ok(())
+                    }
+                },
+                Step::Write if this.input.is_empty() => {
+                    *this.step = match this.flush_each_chunk {
+                        true => Step::Flush,
+                        false => Step::Read,
+                    };
+                    Ok(())
+                }
+                Step::Write => this
+                    .state
+                    .write(this.input)
+                    .map(|bytes_read| this.input.advance(bytes_read)),
+                Step::Flush => this.state.flush().map(|flushed| {
+                    if flushed {
+                        *this.step = Step::Read;
+                    }
+                }),
+                Step::Finish => this.state.finish().map(|finished| {
+                    if finished {
+                        *this.step = Step::Done;
+                    }
+                }),
+                Step::Done => return Poll::Ready(None),
+            };
+
+            if let Err(err) = res {
+                *this.step = Step::Done;
+                return Poll::Ready(Some(Err(err.into())));
+            }
+        }
+    }
+}
+
+pin_project! {
+    /// Wraps a stream of compressed chunks and decompresses them.
+    ///
+    /// Every item of the underlying stream is decompressed, yielding chunks of decompressed output
+    /// as the decoder produces them. Errors of the underlying stream are passed through.
+    ///
+    /// An error of kind [`io::ErrorKind::UnexpectedEof`] is yielded if the underlying stream ends
+    /// before the decompression stream is finished, and an error of kind
+    /// [`io::ErrorKind::InvalidData`] if the underlying stream continues after it.
+    #[derive(Debug)]
+    pub struct DecompressorStream<S> {
+        #[pin]
+        inner: S,
+        state: decode::ChunkState,
+        input: Bytes,
+        step: Step,
+    }
+}
+
+impl<S> DecompressorStream<S> {
+    /// Creates a new `DecompressorStream<S>` with a newly created decoder.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the decoder fails to be allocated or initialized
+    pub fn new(inner: S) -> Self {
+        DecompressorStream::with_decoder(BrotliDecoder::new(), inner)
+    }
+
+    /// Attempts to create a new `DecompressorStream<S>` with a newly created decoder.
+    ///
+    /// # Errors
+    ///
+    /// An [`AllocError`] will be returned if the decoder fails to be allocated or initialized.
+    pub fn try_new(inner: S) -> Result<Self, AllocError> {
+        Ok(DecompressorStream::with_decoder(BrotliDecoder::try_new()?, inner))
+    }
+
+    /// Creates a new `DecompressorStream<S>` with a specified decoder.
+    pub fn with_decoder(decoder: BrotliDecoder, inner: S) -> Self {
+        DecompressorStream {
+            inner,
+            state: decode::ChunkState::new(decoder),
+            input: Bytes::new(),
+            step: Step::Read,
+        }
+    }
+
+    /// Gets a reference to the underlying stream.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Gets a mutable reference to the underlying stream.
+    ///
+    /// It is inadvisable to directly poll the underlying stream.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Gets a pinned mutable reference to the underlying stream.
+    ///
+    /// It is inadvisable to directly poll the underlying stream.
+    pub fn get_pin_mut(self: Pin<&mut Self>) -> Pin<&mut S> {
+        self.project().inner
+    }
+
+    /// Returns `true` if the decompression stream has been finished.
+    pub fn is_finished(&self) -> bool {
+        self.state.decoder().is_finished()
+    }
+
+    /// Removes the oldest captured metadata block from the queue and returns it. For more
+    /// information see [`BrotliDecoder::pop_metadata`].
+    pub fn pop_metadata(&mut self) -> Option<Metadata> {
+        self.state.pop_metadata()
+    }
+
+    /// Unwraps this `DecompressorStream<S>`, returning the underlying stream.
+    ///
+    /// This makes no attempt to validate that the decompression stream finished.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    /// Disassembles this `DecompressorStream<S>`, returning the underlying stream and decoder.
+    pub fn into_parts(self) -> (S, BrotliDecoder) {
+        (self.inner, self.state.into_decoder())
+    }
+}
+
+impl<S, E> Stream for DecompressorStream<S>
+where
+    S: Stream<Item = Result<Bytes, E>>,
+    E: From<io::Error>,
+{
+    type Item = Result<Bytes, E>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        loop {
+            if *this.step == Step::Done {
+                return Poll::Ready(None);
+            }
+
+            let res = match this.state.take_output() {
+                Ok(Some(output)) => return Poll::Ready(Some(Ok(output))),
+                Ok(None) if *this.step == Step::Read => {
+                    match ready!(this.inner.as_mut().poll_next(cx)) {
+                        Some(Ok(input)) => {
+                            *this.input = input;
+                            *this.step = Step::Write;
+                            Ok(())
+                        }
+                        Some(Err(err)) => return Poll::Ready(Some(Err(err))),
+                        None => {
+                            *this.step = Step::Done;
+                            this.state.finish()
+                        }
+                    }
+                }
+                Ok(None) if this.input.is_empty() => {
+                    *this.step = Step::Read;
+                    Ok(())
+                }
+                Ok(None) => this
+                    .state
+                    .write(this.input)
+                    .map(|bytes_read| this.input.advance(bytes_read)),
+                Err(err) => Err(err),
+            };
+
+            if let Err(err) = res {
+                *this.step = Step::Done;
+                return Poll::Ready(Some(Err(err.into())));
+            }
+        }
+    }
+}
+
+pin_project! {
+    /// Wraps a sink and compresses the chunks sent to it.
+    ///
+    /// Chunks of compressed output are sent to the underlying sink as the encoder produces them.
+    /// Flushing the sink flushes the compression stream as well. The sink must be closed to
+    /// finish the compression stream.
+    #[derive(Debug)]
+    pub struct CompressorSink<S> {
+        #[pin]
+        inner: S,
+        state: encode::ChunkState,
+        input: Bytes,
+        output: Option<Bytes>,
+    }
+}
+
+impl<S> CompressorSink<S> {
+    /// Creates a new `CompressorSink<S>` with a newly created encoder.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the encoder fails to be allocated or initialized
+    pub fn new(inner: S) -> Self {
+        CompressorSink::with_encoder(BrotliEncoder::new(), inner)
+    }
+
+    /// Attempts to create a new `CompressorSink<S>` with a newly created encoder.
+    ///
+    /// # Errors
+    ///
+    /// An [`AllocError`] will be returned if the encoder fails to be allocated or initialized.
+    pub fn try_new(inner: S) -> Result<Self, AllocError> {
+        Ok(CompressorSink::with_encoder(BrotliEncoder::try_new()?, inner))
+    }
+
+    /// Creates a new `CompressorSink<S>` with a specified encoder.
+    pub fn with_encoder(encoder: BrotliEncoder, inner: S) -> Self {
+        CompressorSink {
+            inner,
+            state: encode::ChunkState::new(encoder),
+            input: Bytes::new(),
+            output: None,
+        }
+    }
+
+    /// Gets a reference to the underlying sink.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Gets a mutable reference to the underlying sink.
+    ///
+    /// It is inadvisable to directly send to the underlying sink.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Gets a pinned mutable reference to the underlying sink.
+    ///
+    /// It is inadvisable to directly send to the underlying sink.
+    pub fn get_pin_mut(self: Pin<&mut Self>) -> Pin<&mut S> {
+        self.project().inner
+    }
+
+    /// Returns `true` if the compression stream has been finished.
+    pub fn is_finished(&self) -> bool {
+        self.state.encoder().is_finished()
+    }
+
+    /// Unwraps this `CompressorSink<S>`, returning the underlying sink.
+    ///
+    /// This makes no attempt to validate that the compression stream finished.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    /// Disassembles this `CompressorSink<S>`, returning the underlying sink and encoder.
+    pub fn into_parts(self) -> (S, BrotliEncoder) {
+        (self.inner, self.state.into_encoder())
+    }
+}
+
+impl<S> Sink<Bytes> for CompressorSink<S>
+where
+    S: Sink<Bytes>,
+    S::Error: From<io::Error>,
+{
+    type Error = S::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let mut this = self.project();
+
+        loop {
+            let state = &mut *this.state;
+            ready!(poll_send(this.inner.as_mut(), this.output, cx, || {
+                Ok(state.take_output())
+            }))?;
+
+            if this.input.is_empty() {
+                return Poll::Ready(Ok(()));
+            }
+
+            let bytes_read = this.state.write(this.input)?;
+            this.input.advance(bytes_read);
+        }
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: Bytes) -> Result<(), Self::Error> {
+        let this = self.project();
+        debug_assert!(this.input.is_empty(), "start_send called before poll_ready");
+        *this.input = item;
+
+        Ok(())
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        ready!(self.as_mut().poll_ready(cx))?;
+        let mut this = self.project();
+
+        while !this.state.flush()? {
+            let state = &mut *this.state;
+            ready!(poll_send(this.inner.as_mut(), this.output, cx, || {
+                Ok(state.take_output())
+            }))?;
+        }
+
+        this.inner.poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        ready!(self.as_mut().poll_ready(cx))?;
+        let mut this = self.project();
+
+        while !this.state.finish()? {
+            let state = &mut *this.state;
+            ready!(poll_send(this.inner.as_mut(), this.output, cx, || {
+                Ok(state.take_output())
+            }))?;
+        }
+
+        this.inner.poll_close(cx)
+    }
+}
+
+pin_project! {
+    /// Wraps a sink and decompresses the chunks sent to it.
+    ///
+    /// Chunks of decompressed output are sent to the underlying sink as the decoder produces them.
+    /// Closing the sink fails with an error of kind [`io::ErrorKind::UnexpectedEof`] if the
+    /// decompression stream is incomplete. Sending chunks after the end of the decompression stream
+    /// fails with an error of kind [`io::ErrorKind::InvalidData`].
+    #[derive(Debug)]
+    pub struct DecompressorSink<S> {
+        #[pin]
+        inner: S,
+        state: decode::ChunkState,
+        input: Bytes,
+        output: Option<Bytes>,
+    }
+}
+
+impl<S> DecompressorSink<S> {
+    /// Creates a new `DecompressorSink<S>` with a newly created decoder.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the decoder fails to be allocated or initialized
+    pub fn new(inner: S) -> Self {
+        DecompressorSink::with_decoder(BrotliDecoder::new(), inner)
+    }
+
+    /// Attempts to create a new `DecompressorSink<S>` with a newly created decoder.
+    ///
+    /// # Errors
+    ///
+    /// An [`AllocError`] will be returned if the decoder fails to be allocated or initialized.
+    pub fn try_new(inner: S) -> Result<Self, AllocError> {
+        Ok(DecompressorSink::with_decoder(BrotliDecoder::try_new()?, inner))
+    }
+
+    /// Creates a new `DecompressorSink<S>` with a specified decoder.
+    pub fn with_decoder(decoder: BrotliDecoder, inner: S) -> Self {
+        DecompressorSink {
+            inner,
+            state: decode::ChunkState::new(decoder),
+            input: Bytes::new(),
+            output: None,
+        }
+    }
+
+    /// Gets a reference to the underlying sink.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Gets a mutable reference to the underlying sink.
+    ///
+    /// It is inadvisable to directly send to the underlying sink.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Gets a pinned mutable reference to the underlying sink.
+    ///
+    /// It is inadvisable to directly send to the underlying sink.
+    pub fn get_pin_mut(self: Pin<&mut Self>) -> Pin<&mut S> {
+        self.project().inner
+    }
+
+    /// Returns `true` if the decompression stream has been finished.
+    pub fn is_finished(&self) -> bool {
+        self.state.decoder().is_finished()
+    }
+
+    /// Removes the oldest captured metadata block from the queue and returns it. For more
+    /// information see [`BrotliDecoder::pop_metadata`].
+    pub fn pop_metadata(&mut self) -> Option<Metadata> {
+        self.state.pop_metadata()
+    }
+
+    /// Unwraps this `DecompressorSink<S>`, returning the underlying sink.
+    ///
+    /// This makes no attempt to validate that the decompression stream finished.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    /// Disassembles this `DecompressorSink<S>`, returning the underlying sink and decoder.
+    pub fn into_parts(self) -> (S, BrotliDecoder) {
+        (self.inner, self.state.into_decoder())
+    }
+}
+
+impl<S> Sink<Bytes> for DecompressorSink<S>
+where
+    S: Sink<Bytes>,
+    S::Error: From<io::Error>,
+{
+    type Error = S::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let mut this = self.project();
+
+        loop {
+            let state = &mut *this.state;
+            ready!(poll_send(this.inner.as_mut(), this.output, cx, || {
+                state.take_output()
+            }))?;
+
+            if this.input.is_empty() {
+                return Poll::Ready(Ok(()));
+            }
+
+            let bytes_read = this.state.write(this.input)?;
+            this.input.advance(bytes_read);
+        }
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: Bytes) -> Result<(), Self::Error> {
+        let this = self.project();
+        debug_assert!(this.input.is_empty(), "start_send called before poll_ready");
+        *this.input = item;
+
+        Ok(())
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        ready!(self.as_mut().poll_ready(cx))?;
+        self.project().inner.poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        ready!(self.as_mut().poll_ready(cx))?;
+        let this = self.project();
+        this.state.finish()?;
+        this.inner.poll_close(cx)
+    }
+}
+
+/// Sends `output` followed by all output returned from `take_output` to `inner`, until
+/// `take_output` returns `None`.
+fn poll_send<S, F>(
+    mut inner: Pin<&mut S>,
+    output: &mut Option<Bytes>,
+    cx: &mut Context<'_>,
+    mut take_output: F,
+) -> Poll<Result<(), S::Error>>
+where
+    S: Sink<Bytes>,
+    S::Error: From<io::Error>,
+    F: FnMut() -> io::Result<Option<Bytes>>,
+{
+    loop {
+        if output.is_none() {
+            *output = take_output()?;
+
+            if output.is_none() {
+                return Poll::Ready(Ok(()));
+            }
+        }
+
+        ready!(inner.as_mut().poll_ready(cx))?;
+
+        if let Some(chunk) = output.take() {
+            inner.as_mut().start_send(chunk)?;
+        }
+    }
+}
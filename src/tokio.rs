@@ -0,0 +1,475 @@
+//! Module that contains async compression and decompression wrappers for tokio
+//!
+//! The wrappers in this module are the async counterparts of [`encode::CompressorReader`],
+//! [`encode::CompressorWriter`], [`decode::DecompressorReader`] and
+//! [`decode::DecompressorWriter`]. Readers wrap an [`AsyncBufRead`] and implement [`AsyncRead`],
+//! writers wrap an [`AsyncWrite`] and implement [`AsyncWrite`]. They are built on a
+//! [`BrotliEncoder`] or [`BrotliDecoder`], so options like checksums and decompression limits
+//! apply as well.
+//!
+//! Writers must be shut down using [`AsyncWriteExt::shutdown`] to finish the stream, as there is
+//! no async drop. Flushing a writer flushes the compression stream as well.
+//!
+//! This module requires the `tokio` feature.
+//!
+//! # Examples
+//!
+//! ```
+//! use brotlic::tokio::{CompressorWriter, DecompressorReader};
+//! use tokio::io::{AsyncReadExt, AsyncWriteExt};
+//!
+//! # let runtime = tokio::runtime::Builder::new_current_thread().build()?;
+//! # runtime.block_on(async {
+//! let mut compressor = CompressorWriter::new(Vec::new());
+//! compressor.write_all(b"hello world").await?;
+//! compressor.shutdown().await?;
+//!
+//! let compressed = compressor.into_inner();
+//! let mut decompressor = DecompressorReader::new(compressed.as_slice());
+//! let mut decompressed = String::new();
+//! decompressor.read_to_string(&mut decompressed).await?;
+//!
+//! assert_eq!(decompressed, "hello world");
+//! # Ok::<(), std::io::Error>(())
+//! # })?;
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! [`encode::CompressorReader`]: crate::encode::CompressorReader
+//! [`encode::CompressorWriter`]: crate::encode::CompressorWriter
+//! [`decode::DecompressorReader`]: crate::decode::DecompressorReader
+//! [`decode::DecompressorWriter`]: crate::decode::DecompressorWriter
+//! [`AsyncWriteExt::shutdown`]: ::tokio::io::AsyncWriteExt::shutdown
+
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+
+use ::tokio::io::{AsyncBufRead, AsyncRead, AsyncWrite, ReadBuf};
+use pin_project_lite::pin_project;
+
+use crate::AllocError;
+use crate::async_io;
+use crate::decode::{self, BrotliDecoder, Metadata};
+use crate::encode::{self, BrotliEncoder};
+
+pin_project! {
+    /// Wraps an async reader and compresses its output.
+    ///
+    /// The compression stream is finished when the underlying reader reaches EOF. This is the
+    /// async counterpart of [`encode::CompressorReader`].
+    ///
+    /// [`encode::CompressorReader`]: crate::encode::CompressorReader
+    #[derive(Debug)]
+    pub struct CompressorReader<R> {
+        #[pin]
+        inner: R,
+        state: encode::ReaderState,
+    }
+}
+
+impl<R: AsyncBufRead> CompressorReader<R> {
+    /// Creates a new `CompressorReader<R>` with a newly created encoder.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the encoder fails to be allocated or initialized
+    pub fn new(inner: R) -> Self {
+        CompressorReader::with_encoder(BrotliEncoder::new(), inner)
+    }
+
+    /// Attempts to create a new `CompressorReader<R>` with a newly created encoder.
+    ///
+    /// # Errors
+    ///
+    /// An [`AllocError`] will be returned if the encoder fails to be allocated or initialized.
+    pub fn try_new(inner: R) -> Result<Self, AllocError> {
+        Ok(CompressorReader::with_encoder(BrotliEncoder::try_new()?, inner))
+    }
+
+    /// Creates a new `CompressorReader<R>` with a specified encoder.
+    pub fn with_encoder(encoder: BrotliEncoder, inner: R) -> Self {
+        CompressorReader {
+            inner,
+            state: encode::ReaderState::new(encoder),
+        }
+    }
+
+    /// Gets a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Gets a mutable reference to the underlying reader.
+    ///
+    /// It is inadvisable to directly read from the underlying reader.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Gets a pinned mutable reference to the underlying reader.
+    ///
+    /// It is inadvisable to directly read from the underlying reader.
+    pub fn get_pin_mut(self: Pin<&mut Self>) -> Pin<&mut R> {
+        self.project().inner
+    }
+
+    /// Returns `true` if the compression stream has been finished.
+    pub fn is_finished(&self) -> bool {
+        self.state.encoder().is_finished()
+    }
+
+    /// Unwraps this `CompressorReader<R>`, returning the underlying reader.
+    ///
+    /// This makes no attempt to validate that the compression stream finished.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Disassembles this `CompressorReader<R>`, returning the underlying reader and encoder.
+    pub fn into_parts(self) -> (R, BrotliEncoder) {
+        (self.inner, self.state.into_encoder())
+    }
+}
+
+impl<R: AsyncBufRead> AsyncRead for CompressorReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.project();
+        let output = buf.initialize_unfilled();
+        let bytes_written = ready!(async_io::poll_read(
+            this.state,
+            this.inner,
+            cx,
+            output,
+            R::poll_fill_buf,
+            R::consume,
+        ))?;
+        buf.advance(bytes_written);
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+pin_project! {
+    /// Wraps an async writer and compresses its output.
+    ///
+    /// The compression stream is finished by [`AsyncWriteExt::shutdown`], which must be called
+    /// before the stream can be decompressed. Flushing flushes the compression stream, which may
+    /// decrease the compression ratio. This is the async counterpart of
+    /// [`encode::CompressorWriter`].
+    ///
+    /// [`AsyncWriteExt::shutdown`]: ::tokio::io::AsyncWriteExt::shutdown
+    /// [`encode::CompressorWriter`]: crate::encode::CompressorWriter
+    #[derive(Debug)]
+    pub struct CompressorWriter<W> {
+        #[pin]
+        inner: W,
+        state: encode::AsyncWriterState,
+    }
+}
+
+impl<W: AsyncWrite> CompressorWriter<W> {
+    /// Creates a new `CompressorWriter<W>` with a newly created encoder.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the encoder fails to be allocated or initialized
+    pub fn new(inner: W) -> Self {
+        CompressorWriter::with_encoder(BrotliEncoder::new(), inner)
+    }
+
+    /// Attempts to create a new `CompressorWriter<W>` with a newly created encoder.
+    ///
+    /// # Errors
+    ///
+    /// An [`AllocError`] will be returned if the encoder fails to be allocated or initialized.
+    pub fn try_new(inner: W) -> Result<Self, AllocError> {
+        Ok(CompressorWriter::with_encoder(BrotliEncoder::try_new()?, inner))
+    }
+
+    /// Creates a new `CompressorWriter<W>` with a specified encoder.
+    pub fn with_encoder(encoder: BrotliEncoder, inner: W) -> Self {
+        CompressorWriter {
+            inner,
+            state: encode::AsyncWriterState::new(encoder),
+        }
+    }
+
+    /// Gets a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Gets a mutable reference to the underlying writer.
+    ///
+    /// It is inadvisable to directly write to the underlying writer.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Gets a pinned mutable reference to the underlying writer.
+    ///
+    /// It is inadvisable to directly write to the underlying writer.
+    pub fn get_pin_mut(self: Pin<&mut Self>) -> Pin<&mut W> {
+        self.project().inner
+    }
+
+    /// Returns `true` if the compression stream has been finished.
+    pub fn is_finished(&self) -> bool {
+        self.state.encoder().is_finished()
+    }
+
+    /// Unwraps this `CompressorWriter<W>`, returning the underlying writer.
+    ///
+    /// This makes no attempt to finish the compression stream, use [`AsyncWriteExt::shutdown`]
+    /// beforehand.
+    ///
+    /// [`AsyncWriteExt::shutdown`]: ::tokio::io::AsyncWriteExt::shutdown
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    /// Disassembles this `CompressorWriter<W>`, returning the underlying writer and encoder.
+    pub fn into_parts(self) -> (W, BrotliEncoder) {
+        (self.inner, self.state.into_encoder())
+    }
+}
+
+impl<W: AsyncWrite> AsyncWrite for CompressorWriter<W> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.project();
+        async_io::poll_write(this.state, this.inner, cx, buf, W::poll_write)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.project();
+        async_io::poll_flush(this.state, this.inner, cx, W::poll_write, W::poll_flush)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.project();
+        async_io::poll_close(this.state, this.inner, cx, W::poll_write, W::poll_shutdown)
+    }
+}
+
+pin_project! {
+    /// Wraps an async reader and decompresses its output.
+    ///
+    /// This is the async counterpart of [`decode::DecompressorReader`].
+    ///
+    /// [`decode::DecompressorReader`]: crate::decode::DecompressorReader
+    #[derive(Debug)]
+    pub struct DecompressorReader<R> {
+        #[pin]
+        inner: R,
+        state: decode::ReaderState,
+    }
+}
+
+impl<R: AsyncBufRead> DecompressorReader<R> {
+    /// Creates a new `DecompressorReader<R>` with a newly created decoder.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the decoder fails to be allocated or initialized
+    pub fn new(inner: R) -> Self {
+        DecompressorReader::with_decoder(BrotliDecoder::new(), inner)
+    }
+
+    /// Attempts to create a new `DecompressorReader<R>` with a newly created decoder.
+    ///
+    /// # Errors
+    ///
+    /// An [`AllocError`] will be returned if the decoder fails to be allocated or initialized.
+    pub fn try_new(inner: R) -> Result<Self, AllocError> {
+        Ok(DecompressorReader::with_decoder(BrotliDecoder::try_new()?, inner))
+    }
+
+    /// Creates a new `DecompressorReader<R>` with a specified decoder.
+    pub fn with_decoder(decoder: BrotliDecoder, inner: R) -> Self {
+        DecompressorReader {
+            inner,
+            state: decode::ReaderState::new(decoder),
+        }
+    }
+
+    /// Gets a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Gets a mutable reference to the underlying reader.
+    ///
+    /// It is inadvisable to directly read from the underlying reader.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Gets a pinned mutable reference to the underlying reader.
+    ///
+    /// It is inadvisable to directly read from the underlying reader.
+    pub fn get_pin_mut(self: Pin<&mut Self>) -> Pin<&mut R> {
+        self.project().inner
+    }
+
+    /// Returns `true` if the decompression stream has been finished.
+    pub fn is_finished(&self) -> bool {
+        self.state.decoder().is_finished()
+    }
+
+    /// Removes the oldest captured metadata block from the queue and returns it. For more
+    /// information see [`BrotliDecoder::pop_metadata`].
+    pub fn pop_metadata(&mut self) -> Option<Metadata> {
+        self.state.pop_metadata()
+    }
+
+    /// Unwraps this `DecompressorReader<R>`, returning the underlying reader.
+    ///
+    /// This makes no attempt to validate that the decompression stream finished.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Disassembles this `DecompressorReader<R>`, returning the underlying reader and decoder.
+    pub fn into_parts(self) -> (R, BrotliDecoder) {
+        (self.inner, self.state.into_decoder())
+    }
+}
+
+impl<R: AsyncBufRead> AsyncRead for DecompressorReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.project();
+        let output = buf.initialize_unfilled();
+        let bytes_written = ready!(async_io::poll_read(
+            this.state,
+            this.inner,
+            cx,
+            output,
+            R::poll_fill_buf,
+            R::consume,
+        ))?;
+        buf.advance(bytes_written);
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+pin_project! {
+    /// Wraps an async writer and decompresses its output.
+    ///
+    /// [`AsyncWriteExt::shutdown`] fails with [`io::ErrorKind::UnexpectedEof`] if the
+    /// decompression stream is incomplete. This is the async counterpart of
+    /// [`decode::DecompressorWriter`].
+    ///
+    /// [`AsyncWriteExt::shutdown`]: ::tokio::io::AsyncWriteExt::shutdown
+    /// [`decode::DecompressorWriter`]: crate::decode::DecompressorWriter
+    #[derive(Debug)]
+    pub struct DecompressorWriter<W> {
+        #[pin]
+        inner: W,
+        state: decode::AsyncWriterState,
+    }
+}
+
+impl<W: AsyncWrite> DecompressorWriter<W> {
+    /// Creates a new `DecompressorWriter<W>` with a newly created decoder.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the decoder fails to be allocated or initialized
+    pub fn new(inner: W) -> Self {
+        DecompressorWriter::with_decoder(BrotliDecoder::new(), inner)
+    }
+
+    /// Attempts to create a new `DecompressorWriter<W>` with a newly created decoder.
+    ///
+    /// # Errors
+    ///
+    /// An [`AllocError`] will be returned if the decoder fails to be allocated or initialized.
+    pub fn try_new(inner: W) -> Result<Self, AllocError> {
+        Ok(DecompressorWriter::with_decoder(BrotliDecoder::try_new()?, inner))
+    }
+
+    /// Creates a new `DecompressorWriter<W>` with a specified decoder.
+    pub fn with_decoder(decoder: BrotliDecoder, inner: W) -> Self {
+        DecompressorWriter {
+            inner,
+            state: decode::AsyncWriterState::new(decoder),
+        }
+    }
+
+    /// Gets a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Gets a mutable reference to the underlying writer.
+    ///
+    /// It is inadvisable to directly write to the underlying writer.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Gets a pinned mutable reference to the underlying writer.
+    ///
+    /// It is inadvisable to directly write to the underlying writer.
+    pub fn get_pin_mut(self: Pin<&mut Self>) -> Pin<&mut W> {
+        self.project().inner
+    }
+
+    /// Returns `true` if the decompression stream has been finished.
+    pub fn is_finished(&self) -> bool {
+        self.state.decoder().is_finished()
+    }
+
+    /// Removes the oldest captured metadata block from the queue and returns it. For more
+    /// information see [`BrotliDecoder::pop_metadata`].
+    pub fn pop_metadata(&mut self) -> Option<Metadata> {
+        self.state.pop_metadata()
+    }
+
+    /// Unwraps this `DecompressorWriter<W>`, returning the underlying writer.
+    ///
+    /// This makes no attempt to validate that the decompression stream finished.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    /// Disassembles this `DecompressorWriter<W>`, returning the underlying writer and decoder.
+    pub fn into_parts(self) -> (W, BrotliDecoder) {
+        (self.inner, self.state.into_decoder())
+    }
+}
+
+impl<W: AsyncWrite> AsyncWrite for DecompressorWriter<W> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.project();
+        async_io::poll_write(this.state, this.inner, cx, buf, W::poll_write)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.project();
+        async_io::poll_flush(this.state, this.inner, cx, W::poll_write, W::poll_flush)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.project();
+        async_io::poll_close(this.state, this.inner, cx, W::poll_write, W::poll_shutdown)
+    }
+}
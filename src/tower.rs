@@ -0,0 +1,603 @@
+//! Module that contains a tower layer for brotli compression of HTTP bodies
+//!
+//! [`BrotliLayer`] wraps a [`Service`] handling HTTP requests. Request bodies with a
+//! `Content-Encoding` of `br` are decompressed before they are passed to the service, whereas
+//! response bodies are compressed if brotli is negotiated by the `Accept-Encoding` of the request,
+//! see [`AcceptEncoding::negotiate`]. Bodies are compressed and decompressed as they stream, they
+//! are never buffered as a whole. This is built on [`CompressorStream`] and [`DecompressorStream`],
+//! so encoder options like the quality and decoder options like limits apply as well.
+//!
+//! Responses are left as is if they already have a `Content-Encoding`, a `Content-Range` or
+//! `Cache-Control: no-transform`, if their `Content-Type` is compressed already, such as most
+//! images, or if they are known to be smaller than [`BrotliLayer::min_size`].
+//!
+//! If the encoder for a response fails to be allocated, the response is sent uncompressed. If the
+//! decoder for a request fails to be allocated, the request is answered with `503 Service
+//! Unavailable` without being passed to the service.
+//!
+//! This module requires the `tower` feature.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::convert::Infallible;
+//!
+//! use brotlic::tower::{BrotliBody, BrotliLayer};
+//! use brotlic::{BrotliDecoderOptions, BrotliEncoderOptions, Quality};
+//! use http::{Request, Response};
+//! use tower::ServiceBuilder;
+//!
+//! let layer = BrotliLayer::new()
+//!     .encoder_options(BrotliEncoderOptions::new().quality(Quality::new(5)?))?
+//!     .decoder_options(BrotliDecoderOptions::new().max_output_size(16 << 20))?
+//!     .min_size(256);
+//!
+//! let service = ServiceBuilder::new()
+//!     .layer(layer)
+//!     .service_fn(|request: Request<BrotliBody<String>>| async move {
+//!         // echoes the decompressed request body, compressing it if the client accepts it
+//!         Ok::<_, Infallible>(Response::new(request.into_body()))
+//!     });
+//! # Ok::<(), brotlic::SetParameterError>(())
+//! ```
+//!
+//! [`CompressorStream`]: crate::stream::CompressorStream
+//! [`DecompressorStream`]: crate::stream::DecompressorStream
+
+use std::error::Error;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, ready};
+
+use bytes::{Buf, Bytes};
+use futures_core::Stream;
+use http::header::{self, HeaderMap, HeaderValue};
+use http::{Request, Response, StatusCode};
+use http_body::{Body, Frame, SizeHint};
+use pin_project_lite::pin_project;
+use tower_layer::Layer;
+use tower_service::Service;
+
+use crate::stream::{CompressorStream, DecompressorStream};
+use crate::http::{AcceptEncoding, Encoding, Preset};
+use crate::{BrotliDecoderOptions, BrotliEncoderOptions, BuildError, SetParameterError};
+
+type BoxError = Box<dyn Error + Send + Sync>;
+
+/// Applies brotli compression to responses and decompression to requests.
+///
+/// See the [module level documentation](self) for details.
+#[derive(Debug, Clone)]
+pub struct BrotliLayer {
+    config: Arc<Config>,
+}
+
+#[derive(Debug, Clone)]
+struct Config {
+    encoder: BrotliEncoderOptions,
+    decoder: BrotliDecoderOptions,
+    min_size: u64,
+    flush_each_chunk: bool,
+}
+
+impl BrotliLayer {
+    /// The default minimum size of a response body to be compressed in bytes.
+    pub const DEFAULT_MIN_SIZE: u64 = 32;
+
+    /// Creates a new `BrotliLayer` compressing responses using [`Preset::Dynamic`] and default
+    /// options otherwise.
+    pub fn new() -> Self {
+        BrotliLayer {
+            config: Arc::new(Config {
+                encoder: Preset::Dynamic.options(),
+                decoder: BrotliDecoderOptions::new(),
+                min_size: Self::DEFAULT_MIN_SIZE,
+                flush_each_chunk: false,
+            }),
+        }
+    }
+
+    /// Sets the options of the encoder used to compress response bodies.
+    ///
+    /// # Errors
+    ///
+    /// An [`Err`] will be returned if the options are invalid.
+    pub fn encoder_options(
+        mut self,
+        options: &BrotliEncoderOptions,
+    ) -> Result<Self, SetParameterError> {
+        if let Err(BuildError::SetParameter(err)) = options.try_build() {
+            return Err(err);
+        }
+
+        self.config_mut().encoder = options.clone();
+
+        Ok(self)
+    }
+
+    /// Sets the options of the decoder used to decompress request bodies.
+    ///
+    /// Request bodies are provided by clients, so the limits of [`BrotliDecoderOptions`] should be
+    /// used to protect against decompression bombs. Exceeding a limit fails the request body with
+    /// a [`LimitError`].
+    ///
+    /// # Errors
+    ///
+    /// An [`Err`] will be returned if the options are invalid.
+    ///
+    /// [`LimitError`]: crate::decode::LimitError
+    pub fn decoder_options(
+        mut self,
+        options: &BrotliDecoderOptions,
+    ) -> Result<Self, SetParameterError> {
+        if let Err(BuildError::SetParameter(err)) = options.try_build() {
+            return Err(err);
+        }
+
+        self.config_mut().decoder = options.clone();
+
+        Ok(self)
+    }
+
+    /// Sets the minimum size of a response body to be compressed in bytes, defaulting to
+    /// [`Self::DEFAULT_MIN_SIZE`].
+    ///
+    /// Compressing tiny bodies rarely makes them smaller. Only bodies of a known size are skipped,
+    /// either by their `Content-Length` or their [`Body::size_hint`].
+    pub fn min_size(mut self, min_size: u64) -> Self {
+        self.config_mut().min_size = min_size;
+        self
+    }
+
+    /// Sets whether compressed response bodies are flushed after every chunk of the underlying
+    /// body, see [`CompressorStream::flush_each_chunk`].
+    ///
+    /// This should be enabled for long-lived streaming responses such as server-sent events, so
+    /// that clients receive every chunk as soon as it is produced.
+    pub fn flush_each_chunk(mut self, flush_each_chunk: bool) -> Self {
+        self.config_mut().flush_each_chunk = flush_each_chunk;
+        self
+    }
+
+    fn config_mut(&mut self) -> &mut Config {
+        Arc::make_mut(&mut self.config)
+    }
+}
+
+impl Default for BrotliLayer {
+    fn default() -> Self {
+        BrotliLayer::new()
+    }
+}
+
+impl<S> Layer<S> for BrotliLayer {
+    type Service = BrotliService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        BrotliService {
+            inner,
+            config: self.config.clone(),
+        }
+    }
+}
+
+/// Compresses responses and decompresses requests of the service it wraps.
+///
+/// This is created by [`BrotliLayer`], see the [module level documentation](self) for details.
+#[derive(Debug, Clone)]
+pub struct BrotliService<S> {
+    inner: S,
+    config: Arc<Config>,
+}
+
+impl<S> BrotliService<S> {
+    /// Gets a reference to the underlying service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Gets a mutable reference to the underlying service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Unwraps this `BrotliService<S>`, returning the underlying service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for BrotliService<S>
+where
+    S: Service<Request<BrotliBody<ReqBody>>, Response = Response<ResBody>>,
+    ReqBody: Body,
+    ReqBody::Error: Into<BoxError>,
+    ResBody: Body,
+    ResBody::Error: Into<BoxError>,
+{
+    type Response = Response<BrotliBody<ResBody>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<ReqBody>) -> Self::Future {
+        let accept_encoding = AcceptEncoding::from_headers(request.headers());
+        let accepts_br = accept_encoding.negotiate() == Encoding::Brotli;
+
+        let state = match self.config.decompress_request(request) {
+            Some(request) => State::Pending {
+                future: self.inner.call(request),
+            },
+            None => State::Unavailable,
+        };
+
+        ResponseFuture {
+            state,
+            config: self.config.clone(),
+            accepts_br,
+        }
+    }
+}
+
+pin_project! {
+    /// The response future of [`BrotliService`].
+    #[derive(Debug)]
+    pub struct ResponseFuture<F> {
+        #[pin]
+        state: State<F>,
+        config: Arc<Config>,
+        accepts_br: bool,
+    }
+}
+
+pin_project! {
+    #[project = StateProj]
+    #[derive(Debug)]
+    enum State<F> {
+        Pending {
+            #[pin]
+            future: F,
+        },
+        // the decoder of the request failed to be allocated
+        Unavailable,
+    }
+}
+
+impl<F, B, E> Future for ResponseFuture<F>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+    B: Body,
+    B::Error: Into<BoxError>,
+{
+    type Output = Result<Response<BrotliBody<B>>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+
+        let response = match this.state.project() {
+            StateProj::Pending { future } => ready!(future.poll(cx))?,
+            StateProj::Unavailable => {
+                let mut response = Response::new(BrotliBody::empty());
+                *response.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+
+                return Poll::Ready(Ok(response));
+            }
+        };
+
+        Poll::Ready(Ok(this
+            .config
+            .compress_response(response, *this.accepts_br)))
+    }
+}
+
+impl Config {
+    /// Prepares `request` to be decompressed, or returns `None` if the decoder fails to be
+    /// allocated.
+    fn decompress_request<B>(&self, request: Request<B>) -> Option<Request<BrotliBody<B>>> {
+        let (mut parts, body) = request.into_parts();
+
+        if !strip_br(&mut parts.headers) {
+            return Some(Request::from_parts(parts, BrotliBody::identity(body)));
+        }
+
+        parts.headers.remove(header::CONTENT_LENGTH);
+
+        let decoder = self.decoder.try_build().ok()?;
+        let body = DecompressorStream::with_decoder(decoder, BodyStream::new(body));
+
+        Some(Request::from_parts(parts, BrotliBody::decompress(body)))
+    }
+
+    fn compress_response<B: Body>(
+        &self,
+        response: Response<B>,
+        accepts_br: bool,
+    ) -> Response<BrotliBody<B>> {
+        let (mut parts, body) = response.into_parts();
+
+        if !self.is_compressible(parts.status, &parts.headers, &body) {
+            return Response::from_parts(parts, BrotliBody::identity(body));
+        }
+
+        // the response varies by the accepted encodings even if it is not compressed this time
+        add_vary(&mut parts.headers);
+
+        let encoder = match self.encoder.try_build() {
+            Ok(encoder) if accepts_br => encoder,
+            // the response is sent uncompressed if the encoder fails to be allocated
+            _ => return Response::from_parts(parts, BrotliBody::identity(body)),
+        };
+
+        parts.headers.remove(header::CONTENT_LENGTH);
+        parts.headers.remove(header::ACCEPT_RANGES);
+        parts
+            .headers
+            .insert(header::CONTENT_ENCODING, HeaderValue::from_static("br"));
+
+        let body = CompressorStream::with_encoder(encoder, BodyStream::new(body))
+            .flush_each_chunk(self.flush_each_chunk);
+
+        Response::from_parts(parts, BrotliBody::compress(body))
+    }
+
+    fn is_compressible<B: Body>(&self, status: StatusCode, headers: &HeaderMap, body: &B) -> bool {
+        if status.is_informational()
+            || status == StatusCode::NO_CONTENT
+            || status == StatusCode::NOT_MODIFIED
+            || headers.contains_key(header::CONTENT_ENCODING)
+            || headers.contains_key(header::CONTENT_RANGE)
+        {
+            return false;
+        }
+
+        let no_transform = headers
+            .get_all(header::CACHE_CONTROL)
+            .iter()
+            .flat_map(tokens)
+            .any(|directive| directive.eq_ignore_ascii_case("no-transform"));
+
+        let compressed = headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(is_compressed_type);
+
+        let len = headers
+            .get(header::CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok()?.parse().ok())
+            .or_else(|| body.size_hint().exact());
+
+        !no_transform && !compressed && len.is_none_or(|len| len >= self.min_size)
+    }
+}
+
+/// Removes `br` from the end of the `Content-Encoding` of `headers`, returning `true` if it was
+/// the outermost content coding.
+fn strip_br(headers: &mut HeaderMap) -> bool {
+    let mut codings: Vec<&str> = headers
+        .get_all(header::CONTENT_ENCODING)
+        .iter()
+        .flat_map(tokens)
+        .collect();
+
+    match codings.last() {
+        Some(coding) if coding.eq_ignore_ascii_case("br") => codings.pop(),
+        _ => return false,
+    };
+
+    match HeaderValue::from_str(&codings.join(", ")) {
+        Ok(value) if !codings.is_empty() => headers.insert(header::CONTENT_ENCODING, value),
+        _ => headers.remove(header::CONTENT_ENCODING),
+    };
+
+    true
+}
+
+/// Adds `Accept-Encoding` to the `Vary` of `headers`, unless it is already present.
+fn add_vary(headers: &mut HeaderMap) {
+    let present = headers
+        .get_all(header::VARY)
+        .iter()
+        .flat_map(tokens)
+        .any(|name| name == "*" || name.eq_ignore_ascii_case("accept-encoding"));
+
+    if !present {
+        headers.append(header::VARY, HeaderValue::from_static("accept-encoding"));
+    }
+}
+
+/// Splits a comma separated header value into its trimmed, non-empty tokens.
+fn tokens(value: &HeaderValue) -> impl Iterator<Item = &str> {
+    value
+        .to_str()
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+}
+
+/// Returns if `content_type` denotes data that is compressed already.
+fn is_compressed_type(content_type: &str) -> bool {
+    let essence = content_type.split(';').next().unwrap_or_default().trim();
+    let (kind, subtype) = essence.split_once('/').unwrap_or((essence, ""));
+    let is = |name: &str| kind.eq_ignore_ascii_case(name);
+
+    match subtype.to_ascii_lowercase().as_str() {
+        "svg+xml" => false,
+        _ if is("image") || is("audio") || is("video") => true,
+        "woff" | "woff2" => is("font") || is("application"),
+        "zip" | "gzip" | "x-gzip" | "zstd" | "x-bzip2" | "x-xz" | "x-7z-compressed" => {
+            is("application")
+        }
+        _ => false,
+    }
+}
+
+pin_project! {
+    /// The body of a request or response processed by [`BrotliService`].
+    ///
+    /// Depending on the headers, this is either the compressed, decompressed or unmodified
+    /// underlying body. Trailers of the underlying body are passed through.
+    #[derive(Debug)]
+    pub struct BrotliBody<B> {
+        #[pin]
+        inner: Inner<B>,
+    }
+}
+
+pin_project! {
+    #[project = InnerProj]
+    #[derive(Debug)]
+    enum Inner<B> {
+        Identity {
+            #[pin]
+            body: B,
+        },
+        Compress {
+            #[pin]
+            stream: CompressorStream<BodyStream<B>>,
+        },
+        Decompress {
+            #[pin]
+            stream: DecompressorStream<BodyStream<B>>,
+        },
+        Empty,
+    }
+}
+
+impl<B> BrotliBody<B> {
+    fn identity(body: B) -> Self {
+        BrotliBody {
+            inner: Inner::Identity { body },
+        }
+    }
+
+    fn compress(stream: CompressorStream<BodyStream<B>>) -> Self {
+        BrotliBody {
+            inner: Inner::Compress { stream },
+        }
+    }
+
+    fn decompress(stream: DecompressorStream<BodyStream<B>>) -> Self {
+        BrotliBody {
+            inner: Inner::Decompress { stream },
+        }
+    }
+
+    fn empty() -> Self {
+        BrotliBody {
+            inner: Inner::Empty,
+        }
+    }
+}
+
+impl<B> Body for BrotliBody<B>
+where
+    B: Body,
+    B::Error: Into<BoxError>,
+{
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        match self.project().inner.project() {
+            InnerProj::Identity { body } => match ready!(body.poll_frame(cx)) {
+                Some(Ok(frame)) => Poll::Ready(Some(Ok(frame.map_data(into_bytes)))),
+                Some(Err(err)) => Poll::Ready(Some(Err(err.into()))),
+                None => Poll::Ready(None),
+            },
+            InnerProj::Compress { mut stream } => match ready!(stream.as_mut().poll_next(cx)) {
+                Some(data) => Poll::Ready(Some(data.map(Frame::data))),
+                None => Poll::Ready(stream.get_pin_mut().take_trailers()),
+            },
+            InnerProj::Decompress { mut stream } => match ready!(stream.as_mut().poll_next(cx)) {
+                Some(data) => Poll::Ready(Some(data.map(Frame::data))),
+                None => Poll::Ready(stream.get_pin_mut().take_trailers()),
+            },
+            InnerProj::Empty => Poll::Ready(None),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match &self.inner {
+            Inner::Identity { body } => body.is_end_stream(),
+            Inner::Empty => true,
+            _ => false,
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match &self.inner {
+            Inner::Identity { body } => body.size_hint(),
+            Inner::Empty => SizeHint::with_exact(0),
+            _ => SizeHint::default(),
+        }
+    }
+}
+
+fn into_bytes<D: Buf>(mut data: D) -> Bytes {
+    data.copy_to_bytes(data.remaining())
+}
+
+pin_project! {
+    /// Adapts a body into a stream of its data, retaining its trailers.
+    #[derive(Debug)]
+    struct BodyStream<B> {
+        #[pin]
+        body: B,
+        trailers: Option<HeaderMap>,
+    }
+}
+
+impl<B> BodyStream<B> {
+    fn new(body: B) -> Self {
+        BodyStream {
+            body,
+            trailers: None,
+        }
+    }
+
+    fn take_trailers(self: Pin<&mut Self>) -> Option<Result<Frame<Bytes>, BoxError>> {
+        self.project()
+            .trailers
+            .take()
+            .map(|trailers| Ok(Frame::trailers(trailers)))
+    }
+}
+
+impl<B> Stream for BodyStream<B>
+where
+    B: Body,
+    B::Error: Into<BoxError>,
+{
+    type Item = Result<Bytes, BoxError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        loop {
+            let frame = match ready!(this.body.as_mut().poll_frame(cx)) {
+                Some(Ok(frame)) => frame,
+                Some(Err(err)) => return Poll::Ready(Some(Err(err.into()))),
+                None => return Poll::Ready(None),
+            };
+
+            match frame.into_data() {
+                Ok(data) => return Poll::Ready(Some(Ok(into_bytes(data)))),
+                Err(frame) => {
+                    if let Ok(trailers) = frame.into_trailers() {
+                        this.trailers
+                            .get_or_insert_with(HeaderMap::new)
+                            .extend(trailers);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use brotlic::{AllocError, BrotliDecoder, BrotliDecoderOptions, BrotliEncoder};
+use brotlic::{BrotliEncoderOptions, BuildError, CompressorWriter, DecompressorReader};
+use brotlic::{MultiDecompressorReader, Quality, SetParameterError, read};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+mod common;
+
+/// An allocator that refuses every allocation once `remaining` allocations were made.
+#[derive(Debug, Clone)]
+struct FailingAlloc {
+    remaining: Arc<AtomicUsize>,
+}
+
+impl FailingAlloc {
+    fn new(allocations: usize) -> Self {
+        FailingAlloc {
+            remaining: Arc::new(AtomicUsize::new(allocations)),
+        }
+    }
+}
+
+unsafe impl GlobalAlloc for FailingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let res = self
+            .remaining
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1));
+
+        match res {
+            Ok(_) => System.alloc(layout),
+            Err(_) => std::ptr::null_mut(),
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[test]
+fn test_try_new_in_refused() {
+    assert_eq!(
+        BrotliEncoder::try_new_in(FailingAlloc::new(0)).unwrap_err(),
+        AllocError
+    );
+    assert_eq!(
+        BrotliDecoder::try_new_in(FailingAlloc::new(0)).unwrap_err(),
+        AllocError
+    );
+
+    let err = CompressorWriter::try_new_in(Vec::new(), FailingAlloc::new(0)).unwrap_err();
+    assert_eq!(io::Error::from(err).kind(), io::ErrorKind::OutOfMemory);
+    assert!(DecompressorReader::try_new_in(&[][..], FailingAlloc::new(0)).is_err());
+
+    let res = read::CompressorReader::try_new_in(&[][..], FailingAlloc::new(0));
+    assert_eq!(res.unwrap_err(), AllocError);
+    let res = read::DecompressorReader::try_new_in(&[][..], FailingAlloc::new(0));
+    assert_eq!(res.unwrap_err(), AllocError);
+}
+
+#[test]
+fn test_multi_decompressor_refused() {
+    let options = BrotliDecoderOptions::new().max_memory(16).clone();
+    let res = MultiDecompressorReader::with_options(&options, &[][..]);
+
+    assert_eq!(res.unwrap_err(), BuildError::Alloc(AllocError));
+    assert!(MultiDecompressorReader::try_new(&[][..]).is_ok());
+}
+
+#[test]
+#[should_panic(expected = "failed to allocate or initialize")]
+fn test_new_in_refused() {
+    BrotliEncoder::new_in(FailingAlloc::new(0));
+}
+
+#[test]
+fn test_try_new_round_trip() {
+    let input = common::gen_medium_entropy(1 << 16);
+
+    let mut compressor = CompressorWriter::try_new(Vec::new()).unwrap();
+    compressor.write_all(&input).unwrap();
+    let compressed = compressor.into_inner().unwrap();
+
+    let alloc = FailingAlloc::new(usize::MAX);
+    let mut decompressor = DecompressorReader::try_new_in(compressed.as_slice(), alloc).unwrap();
+    let mut decompressed = Vec::new();
+    decompressor.read_to_end(&mut decompressed).unwrap();
+
+    assert_eq!(decompressed, input);
+}
+
+#[test]
+fn test_try_build() {
+    let mut encoder = BrotliEncoderOptions::new();
+    encoder.quality(Quality::best());
+
+    assert!(encoder.try_build().is_ok());
+    assert_eq!(
+        encoder.try_build_in(FailingAlloc::new(0)).unwrap_err(),
+        BuildError::Alloc(AllocError)
+    );
+
+    let err = BrotliEncoderOptions::new()
+        .stream_offset(u32::MAX)
+        .try_build()
+        .unwrap_err();
+    assert_eq!(
+        err,
+        BuildError::SetParameter(SetParameterError::InvalidStreamOffset)
+    );
+
+    // the decoder state alone exceeds the memory limit
+    let err = BrotliDecoderOptions::new()
+        .max_memory(16)
+        .try_build()
+        .unwrap_err();
+    assert_eq!(err, BuildError::Alloc(AllocError));
+}
+
+#[test]
+fn test_allocation_refused_while_compressing() {
+    let input = common::gen_medium_entropy(1 << 16);
+
+    // the encoder state is allocated, but the buffers allocated once compression starts are not
+    let encoder = BrotliEncoder::try_new_in(FailingAlloc::new(1)).unwrap();
+    let mut compressor = CompressorWriter::with_encoder(encoder, Vec::new());
+
+    let res = compressor
+        .write_all(&input)
+        .and_then(|_| compressor.flush());
+    assert!(res.is_err());
+}
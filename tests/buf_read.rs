@@ -0,0 +1,217 @@
+use brotlic::DecompressorReader;
+#[cfg(feature = "checksum")]
+use brotlic::checksum::{Checksum, ChecksumMode};
+use brotlic::{BrotliDecoderOptions, CompressorReader};
+#[cfg(feature = "checksum")]
+use brotlic::{BrotliEncoderOptions, CompressorWriter};
+#[cfg(feature = "checksum")]
+use std::io::Write;
+use std::io::{self, BufRead, Read};
+
+mod common;
+
+fn read_buffered(reader: &mut impl BufRead) -> io::Result<Vec<u8>> {
+    let mut output = Vec::new();
+
+    loop {
+        let buf = reader.fill_buf()?;
+
+        if buf.is_empty() {
+            return Ok(output);
+        }
+
+        // consume only part of the buffer to exercise partial consumption
+        let len = buf.len().min(1000);
+        output.extend_from_slice(&buf[..len]);
+        reader.consume(len);
+    }
+}
+
+#[test]
+fn test_decompressor_reader_fill_buf() {
+    for input in [
+        Vec::new(),
+        common::gen_min_entropy(1 << 20),
+        common::gen_medium_entropy(1 << 18),
+        common::gen_max_entropy(1 << 16),
+    ] {
+        let compressed = common::compress(&input);
+        let mut decompressor = DecompressorReader::new(compressed.as_slice());
+
+        assert_eq!(read_buffered(&mut decompressor).unwrap(), input);
+        assert!(decompressor.fill_buf().unwrap().is_empty());
+        assert!(decompressor.into_inner().unwrap().is_empty());
+    }
+}
+
+#[test]
+fn test_decompressor_reader_lines() {
+    let text: String = (0..10_000).map(|i| format!("line {}\n", i)).collect();
+    let compressed = common::compress(text.as_bytes());
+
+    let decompressor = DecompressorReader::new(compressed.as_slice());
+    let lines = decompressor
+        .lines()
+        .collect::<io::Result<Vec<_>>>()
+        .unwrap();
+
+    assert_eq!(lines.len(), 10_000);
+    assert!(
+        lines
+            .iter()
+            .enumerate()
+            .all(|(i, line)| *line == format!("line {}", i))
+    );
+}
+
+#[test]
+fn test_decompressor_reader_mixed_reads() {
+    let input = common::gen_medium_entropy(1 << 16);
+    let compressed = common::compress(&input);
+    let mut decompressor = DecompressorReader::new(compressed.as_slice());
+    let mut output = Vec::new();
+
+    let mut start = [0; 100];
+    decompressor.read_exact(&mut start).unwrap();
+    output.extend_from_slice(&start);
+
+    decompressor.read_until(b'\x7f', &mut output).unwrap();
+    decompressor.fill_buf().unwrap();
+    decompressor.read_to_end(&mut output).unwrap();
+
+    assert_eq!(output, input);
+}
+
+#[cfg(feature = "checksum")]
+#[test]
+fn test_decompressor_reader_fill_buf_checksum() {
+    let input = common::gen_medium_entropy(1 << 16);
+    let encoder = BrotliEncoderOptions::new()
+        .checksum(Checksum::Xxh64, ChecksumMode::Flush)
+        .build()
+        .unwrap();
+    let mut compressor = CompressorWriter::with_encoder(encoder, Vec::new());
+
+    for chunk in input.chunks(10_000) {
+        compressor.write_all(chunk).unwrap();
+        compressor.flush().unwrap();
+    }
+
+    let compressed = compressor.into_inner().unwrap();
+    let options = {
+        let mut options = BrotliDecoderOptions::new();
+        options.verify_checksums(true);
+        options
+    };
+
+    let decoder = options.build().unwrap();
+    let mut decompressor = DecompressorReader::with_decoder(decoder, compressed.as_slice());
+    assert_eq!(read_buffered(&mut decompressor).unwrap(), input);
+
+    // corrupt a literal in the middle of the stream
+    let mut corrupted = compressed.clone();
+    let len = corrupted.len();
+    corrupted[len / 2] ^= 0x01;
+
+    let decoder = options.build().unwrap();
+    let mut decompressor = DecompressorReader::with_decoder(decoder, corrupted.as_slice());
+    assert!(read_buffered(&mut decompressor).is_err());
+}
+
+#[test]
+fn test_decompressor_reader_fill_buf_truncated() {
+    let compressed = common::compress(&common::gen_medium_entropy(1 << 12));
+    let truncated = &compressed[..compressed.len() / 2];
+
+    let mut decompressor = DecompressorReader::new(truncated);
+    let err = read_buffered(&mut decompressor).unwrap_err();
+
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn test_decompressor_reader_fill_buf_limit() {
+    let compressed = common::compress(&common::gen_min_entropy(1 << 20));
+    let decoder = BrotliDecoderOptions::new()
+        .max_output_size(1 << 16)
+        .build()
+        .unwrap();
+
+    let mut decompressor = DecompressorReader::with_decoder(decoder, compressed.as_slice());
+    let err = read_buffered(&mut decompressor).unwrap_err();
+
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_compressor_reader_fill_buf() {
+    for input in [
+        Vec::new(),
+        common::gen_min_entropy(1 << 20),
+        common::gen_medium_entropy(1 << 18),
+        common::gen_max_entropy(1 << 16),
+    ] {
+        let mut compressor = CompressorReader::new(input.as_slice());
+        let compressed = read_buffered(&mut compressor).unwrap();
+
+        assert!(compressor.fill_buf().unwrap().is_empty());
+        assert!(compressor.into_inner().unwrap().is_empty());
+
+        assert_eq!(common::decompress(&compressed), input);
+    }
+}
+
+#[test]
+fn test_compressor_reader_chained() {
+    let text: String = (0..10_000).map(|i| format!("line {}\n", i)).collect();
+    let compressor = CompressorReader::new(text.as_bytes());
+    let decompressor = DecompressorReader::new(compressor);
+
+    let lines = decompressor
+        .lines()
+        .collect::<io::Result<Vec<_>>>()
+        .unwrap();
+
+    assert_eq!(lines.len(), 10_000);
+    assert_eq!(lines[9_999], "line 9999");
+}
+
+#[test]
+fn test_compressor_reader_mixed_reads() {
+    let input = common::gen_medium_entropy(1 << 16);
+    let mut compressor = CompressorReader::new(input.as_slice());
+    let mut compressed = Vec::new();
+
+    let mut start = [0; 10];
+    compressor.read_exact(&mut start).unwrap();
+    compressed.extend_from_slice(&start);
+
+    let buf = compressor.fill_buf().unwrap();
+    let len = buf.len() / 2;
+    compressed.extend_from_slice(&buf[..len]);
+    compressor.consume(len);
+
+    compressor.read_to_end(&mut compressed).unwrap();
+
+    assert_eq!(common::decompress(&compressed), input);
+}
+
+#[cfg(feature = "checksum")]
+#[test]
+fn test_compressor_reader_fill_buf_checksum() {
+    let input = common::gen_medium_entropy(1 << 16);
+    let encoder = BrotliEncoderOptions::new()
+        .checksum(Checksum::Xxh64, ChecksumMode::Trailer)
+        .build()
+        .unwrap();
+
+    let mut compressor = CompressorReader::with_encoder(encoder, input.as_slice());
+    let compressed = read_buffered(&mut compressor).unwrap();
+
+    let decoder = BrotliDecoderOptions::new()
+        .verify_checksums(true)
+        .build()
+        .unwrap();
+    let mut decompressor = DecompressorReader::with_decoder(decoder, compressed.as_slice());
+    assert_eq!(read_buffered(&mut decompressor).unwrap(), input);
+}
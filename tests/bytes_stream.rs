@@ -0,0 +1,237 @@
+#![cfg(feature = "stream")]
+
+#[cfg(feature = "checksum")]
+use brotlic::checksum::{Checksum, ChecksumMode};
+#[cfg(feature = "checksum")]
+use brotlic::stream::CompressorStream;
+use brotlic::stream::{compress_stream, decompress_stream};
+use brotlic::stream::{CompressorSink, DecompressorSink, DecompressorStream};
+use brotlic::BrotliDecoderOptions;
+#[cfg(feature = "checksum")]
+use brotlic::BrotliEncoderOptions;
+use bytes::Bytes;
+use futures::channel::mpsc;
+use futures::executor::block_on;
+use futures::{FutureExt, SinkExt, StreamExt, TryStreamExt, stream};
+use std::io;
+
+mod common;
+
+fn chunks(input: &[u8], size: usize) -> impl futures::Stream<Item = io::Result<Bytes>> {
+    let chunks: Vec<_> = input
+        .chunks(size)
+        .map(|chunk| Ok(Bytes::copy_from_slice(chunk)))
+        .collect();
+
+    stream::iter(chunks)
+}
+
+fn collect<S>(stream: S) -> io::Result<Vec<u8>>
+where
+    S: futures::Stream<Item = io::Result<Bytes>>,
+{
+    let chunks: Vec<Bytes> = block_on(stream.try_collect())?;
+    Ok(chunks.concat())
+}
+
+#[test]
+fn test_compress_stream() {
+    let input = common::gen_medium_entropy(1 << 18);
+    let compressed = collect(compress_stream(chunks(&input, 1000))).unwrap();
+
+    assert_eq!(common::decompress(&compressed), input);
+}
+
+#[test]
+fn test_compress_stream_empty() {
+    let compressed = collect(compress_stream(chunks(&[], 1))).unwrap();
+
+    assert!(common::decompress(&compressed).is_empty());
+}
+
+#[test]
+fn test_compress_stream_flush_each_chunk() {
+    let (sender, receiver) = mpsc::unbounded::<io::Result<Bytes>>();
+    let mut decompressor = decompress_stream(compress_stream(receiver).flush_each_chunk(true));
+
+    for chunk in [&b"hello "[..], b"brotli ", b"world"] {
+        sender.unbounded_send(Ok(Bytes::from(chunk))).unwrap();
+
+        // the chunk can be decompressed before the underlying stream ends
+        let decompressed = block_on(decompressor.next()).unwrap().unwrap();
+        assert_eq!(decompressed, chunk);
+    }
+
+    sender.close_channel();
+    assert!(block_on(decompressor.next()).is_none());
+    assert!(decompressor.is_finished());
+}
+
+#[cfg(feature = "checksum")]
+#[test]
+fn test_compress_stream_with_checksum() {
+    let input = common::gen_medium_entropy(1 << 16);
+    let encoder = BrotliEncoderOptions::new()
+        .checksum(Checksum::Crc32c, ChecksumMode::Flush)
+        .build()
+        .unwrap();
+    let decoder = BrotliDecoderOptions::new()
+        .verify_checksums(true)
+        .build()
+        .unwrap();
+
+    let compressor = CompressorStream::with_encoder(encoder, chunks(&input, 4096));
+    let decompressor = DecompressorStream::with_decoder(decoder, compressor.flush_each_chunk(true));
+
+    assert_eq!(collect(decompressor).unwrap(), input);
+}
+
+#[test]
+fn test_compress_stream_error() {
+    let input = stream::iter([
+        Ok(Bytes::from_static(b"hello")),
+        Err(io::Error::other("broken pipe")),
+    ]);
+    let mut compressor = compress_stream(input);
+
+    let err = block_on(compressor.try_next()).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::Other);
+    assert!(!compressor.is_finished());
+}
+
+#[test]
+fn test_decompress_stream() {
+    let input = common::gen_medium_entropy(1 << 18);
+    let compressed = common::compress(&input);
+    let decompressor = decompress_stream(chunks(&compressed, 1000));
+
+    assert_eq!(collect(decompressor).unwrap(), input);
+}
+
+#[test]
+fn test_decompress_stream_truncated() {
+    let compressed = common::compress(&common::gen_medium_entropy(1 << 12));
+    let truncated = &compressed[..compressed.len() / 2];
+    let err = collect(decompress_stream(chunks(truncated, 100))).unwrap_err();
+
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn test_decompress_stream_trailing_data() {
+    let mut compressed = common::compress(b"hello world");
+    compressed.extend_from_slice(b"trailing");
+    let err = collect(decompress_stream(chunks(&compressed, 4))).unwrap_err();
+
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_decompress_stream_limit() {
+    let compressed = common::compress(&common::gen_min_entropy(1 << 20));
+    let decoder = BrotliDecoderOptions::new()
+        .max_output_size(1 << 16)
+        .build()
+        .unwrap();
+    let decompressor = DecompressorStream::with_decoder(decoder, chunks(&compressed, 16));
+
+    let err = collect(decompressor).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_compressor_sink() {
+    let input = common::gen_medium_entropy(1 << 16);
+
+    let compressed = block_on(async {
+        let (sender, receiver) = mpsc::unbounded::<Bytes>();
+        let sender = sender.sink_map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe));
+        let mut compressor = CompressorSink::new(sender);
+
+        compressor
+            .send(Bytes::copy_from_slice(&input[..1000]))
+            .await
+            .unwrap();
+        compressor
+            .send(Bytes::copy_from_slice(&input[1000..]))
+            .await
+            .unwrap();
+        compressor.close().await.unwrap();
+
+        assert!(compressor.is_finished());
+        receiver.collect::<Vec<_>>().await.concat()
+    });
+
+    assert_eq!(common::decompress(&compressed), input);
+}
+
+#[test]
+fn test_compressor_sink_flush() {
+    block_on(async {
+        let (sender, mut receiver) = mpsc::unbounded::<Bytes>();
+        let sender = sender.sink_map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe));
+        let mut compressor = CompressorSink::new(sender);
+        compressor.send(Bytes::from_static(b"hello")).await.unwrap();
+
+        // `send` flushes, so the chunk can be decompressed before the sink is closed
+        let mut compressed = Vec::new();
+
+        while let Some(Some(chunk)) = receiver.next().now_or_never() {
+            compressed.extend_from_slice(&chunk);
+        }
+
+        let mut decoder = brotlic::BrotliDecoder::new();
+        let mut decompressed = Vec::with_capacity(64);
+        decoder
+            .decompress_into_vec(&compressed, &mut decompressed)
+            .unwrap();
+
+        assert_eq!(decompressed, b"hello");
+    });
+}
+
+#[test]
+fn test_decompressor_sink() {
+    let input = common::gen_medium_entropy(1 << 16);
+    let compressed = common::compress(&input);
+
+    let decompressed = block_on(async {
+        let (sender, receiver) = mpsc::unbounded::<Bytes>();
+        let sender = sender.sink_map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe));
+        let mut decompressor = DecompressorSink::new(sender);
+
+        for chunk in compressed.chunks(1000) {
+            decompressor
+                .feed(Bytes::copy_from_slice(chunk))
+                .await
+                .unwrap();
+        }
+
+        decompressor.close().await.unwrap();
+
+        assert!(decompressor.is_finished());
+        receiver.collect::<Vec<_>>().await.concat()
+    });
+
+    assert_eq!(decompressed, input);
+}
+
+#[test]
+fn test_decompressor_sink_truncated() {
+    let compressed = common::compress(&common::gen_medium_entropy(1 << 12));
+
+    let err = block_on(async {
+        let (sender, _receiver) = mpsc::unbounded::<Bytes>();
+        let sender = sender.sink_map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe));
+        let mut decompressor = DecompressorSink::new(sender);
+        let truncated = &compressed[..compressed.len() / 2];
+
+        decompressor
+            .send(Bytes::copy_from_slice(truncated))
+            .await
+            .unwrap();
+        decompressor.close().await.unwrap_err()
+    });
+
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+}
@@ -0,0 +1,188 @@
+#![cfg(feature = "checksum")]
+
+use brotlic::checksum::{Checksum, ChecksumError, ChecksumMode};
+use brotlic::{BrotliDecoderOptions, BrotliEncoderOptions, CompressorReader, CompressorWriter};
+use brotlic::{DecompressorReader, DecompressorWriter};
+use std::io::{self, Read, Write};
+
+mod common;
+
+const MAGIC: &[u8] = b"BRCS";
+
+fn compress(input: &[u8], checksum: Checksum, mode: ChecksumMode) -> Vec<u8> {
+    let encoder = BrotliEncoderOptions::new()
+        .checksum(checksum, mode)
+        .build()
+        .unwrap();
+    let mut compressor = CompressorWriter::with_encoder(encoder, Vec::new());
+
+    for chunk in input.chunks(10_000) {
+        compressor.write_all(chunk).unwrap();
+        compressor.flush().unwrap();
+    }
+
+    compressor.into_inner().unwrap()
+}
+
+fn decompress_reader(compressed: &[u8]) -> io::Result<Vec<u8>> {
+    let decoder = BrotliDecoderOptions::new()
+        .verify_checksums(true)
+        .build()
+        .unwrap();
+    let mut decompressor = DecompressorReader::with_decoder(decoder, compressed);
+    let mut decompressed = Vec::new();
+    decompressor.read_to_end(&mut decompressed)?;
+
+    Ok(decompressed)
+}
+
+fn decompress_writer(compressed: &[u8]) -> io::Result<Vec<u8>> {
+    let decoder = BrotliDecoderOptions::new()
+        .verify_checksums(true)
+        .build()
+        .unwrap();
+    let mut decompressor = DecompressorWriter::with_decoder(decoder, Vec::new());
+    decompressor.write_all(compressed)?;
+
+    Ok(decompressor.into_inner().unwrap())
+}
+
+fn checksum_error(err: io::Error) -> ChecksumError {
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+    *err.into_inner()
+        .unwrap()
+        .downcast::<ChecksumError>()
+        .unwrap()
+}
+
+fn count_records(compressed: &[u8]) -> usize {
+    compressed
+        .windows(MAGIC.len())
+        .filter(|w| *w == MAGIC)
+        .count()
+}
+
+#[test]
+fn test_checksum_round_trip() {
+    let input = common::gen_medium_entropy(64 * 1024);
+
+    for checksum in [Checksum::Crc32c, Checksum::Xxh64] {
+        for mode in [ChecksumMode::Trailer, ChecksumMode::Flush] {
+            let compressed = compress(&input, checksum, mode);
+
+            assert_eq!(decompress_reader(&compressed).unwrap(), input);
+            assert_eq!(decompress_writer(&compressed).unwrap(), input);
+        }
+    }
+}
+
+#[test]
+fn test_checksum_modes() {
+    let input = common::gen_medium_entropy(64 * 1024);
+
+    let trailer = compress(&input, Checksum::Crc32c, ChecksumMode::Trailer);
+    let flush = compress(&input, Checksum::Crc32c, ChecksumMode::Flush);
+
+    // both modes start with the record declaring the algorithm
+    assert_eq!(count_records(&trailer), 2);
+    assert_eq!(count_records(&flush), 8);
+}
+
+#[test]
+fn test_checksum_reader() {
+    let input = common::gen_medium_entropy(64 * 1024);
+
+    let encoder = BrotliEncoderOptions::new()
+        .checksum(Checksum::Xxh64, ChecksumMode::Trailer)
+        .build()
+        .unwrap();
+    let mut compressor = CompressorReader::with_encoder(encoder, input.as_slice());
+    let mut compressed = Vec::new();
+    compressor.read_to_end(&mut compressed).unwrap();
+
+    assert_eq!(count_records(&compressed), 2);
+    assert_eq!(decompress_reader(&compressed).unwrap(), input);
+
+    let encoder = BrotliEncoderOptions::new()
+        .checksum(Checksum::Crc32c, ChecksumMode::Trailer)
+        .build()
+        .unwrap();
+    let mut compressor = CompressorReader::with_encoder(encoder, [].as_slice());
+    let mut compressed = Vec::new();
+    compressor.read_to_end(&mut compressed).unwrap();
+
+    assert_eq!(decompress_writer(&compressed).unwrap(), []);
+}
+
+#[test]
+fn test_checksum_ignored_by_default() {
+    let input = common::gen_medium_entropy(4096);
+    let compressed = compress(&input, Checksum::Crc32c, ChecksumMode::Flush);
+
+    let mut decompressor = DecompressorReader::new(compressed.as_slice());
+    let mut decompressed = Vec::new();
+    decompressor.read_to_end(&mut decompressed).unwrap();
+
+    assert_eq!(decompressed, input);
+}
+
+#[test]
+fn test_checksum_mismatch() {
+    let input = common::gen_medium_entropy(64 * 1024);
+
+    for mode in [ChecksumMode::Trailer, ChecksumMode::Flush] {
+        let mut compressed = compress(&input, Checksum::Xxh64, mode);
+        let pos = compressed.windows(MAGIC.len()).rposition(|w| w == MAGIC);
+        compressed[pos.unwrap() + 20] ^= 1;
+
+        let err = decompress_reader(&compressed).unwrap_err();
+        assert_eq!(checksum_error(err), ChecksumError::Mismatch);
+
+        let err = decompress_writer(&compressed).unwrap_err();
+        assert_eq!(checksum_error(err), ChecksumError::Mismatch);
+    }
+}
+
+#[test]
+fn test_checksum_missing() {
+    let mut compressor = CompressorWriter::new(Vec::new());
+    compressor.write_all(b"hello world").unwrap();
+    let compressed = compressor.into_inner().unwrap();
+
+    let err = decompress_reader(&compressed).unwrap_err();
+    assert_eq!(checksum_error(err), ChecksumError::Missing);
+
+    let err = decompress_writer(&compressed).unwrap_err();
+    assert_eq!(checksum_error(err), ChecksumError::Missing);
+}
+
+#[test]
+fn test_checksum_with_captured_metadata() {
+    let encoder = BrotliEncoderOptions::new()
+        .checksum(Checksum::Crc32c, ChecksumMode::Flush)
+        .build()
+        .unwrap();
+    let mut compressor = CompressorWriter::with_encoder(encoder, Vec::new());
+    compressor.write_all(b"hello").unwrap();
+    compressor.flush().unwrap();
+    compressor.write_metadata(b"marker").unwrap();
+    compressor.write_all(b" world").unwrap();
+    let compressed = compressor.into_inner().unwrap();
+
+    let decoder = BrotliDecoderOptions::new()
+        .capture_metadata(true)
+        .verify_checksums(true)
+        .build()
+        .unwrap();
+    let mut decompressor = DecompressorReader::with_decoder(decoder, compressed.as_slice());
+    let mut decompressed = String::new();
+    decompressor.read_to_string(&mut decompressed).unwrap();
+
+    let metadata = decompressor.pop_metadata().unwrap();
+
+    assert_eq!(decompressed, "hello world");
+    assert_eq!(metadata.offset(), 5);
+    assert_eq!(metadata.data(), b"marker");
+    assert_eq!(decompressor.pop_metadata(), None);
+}
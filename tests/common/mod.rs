@@ -1,3 +1,6 @@
+#![allow(dead_code)]
+
+use brotlic::{CompressionMode, Quality, WindowSize};
 use rand::{Rng, SeedableRng};
 
 pub fn gen_min_entropy(len: usize) -> Vec<u8> {
@@ -19,3 +22,17 @@ pub fn gen_max_entropy(len: usize) -> Vec<u8> {
     rng.fill(res.as_mut_slice());
     res
 }
+
+pub fn compress(input: &[u8]) -> Vec<u8> {
+    brotlic::compress_to_vec(
+        input,
+        Quality::default(),
+        WindowSize::default(),
+        CompressionMode::default(),
+    )
+    .unwrap()
+}
+
+pub fn decompress(input: &[u8]) -> Vec<u8> {
+    brotlic::decompress_to_vec(input).unwrap()
+}
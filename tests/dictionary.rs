@@ -0,0 +1,176 @@
+use brotlic::decode::DecodeError;
+use brotlic::encode::PreparedDictionary;
+use brotlic::{BrotliDecoderOptions, BrotliEncoderOptions, CompressorWriter, DecompressorReader};
+use brotlic::{DecompressorWriter, DictionaryType, Quality, SetParameterError};
+use std::alloc::System;
+use std::io::{Read, Write};
+use std::sync::Arc;
+use std::thread;
+
+mod common;
+
+fn boilerplate() -> Vec<u8> {
+    let mut boilerplate = Vec::new();
+
+    for i in 0..64 {
+        write!(boilerplate, "<li class=\"item-{}\">", i * 7919 % 1000).unwrap();
+    }
+
+    boilerplate
+}
+
+fn compress(input: &[u8], options: &BrotliEncoderOptions) -> Vec<u8> {
+    let encoder = options.build().unwrap();
+    let mut compressor = CompressorWriter::with_encoder(encoder, Vec::new());
+
+    compressor.write_all(input).unwrap();
+    compressor.into_inner().unwrap()
+}
+
+#[test]
+fn test_prepared_dictionary_improves_ratio() {
+    let dictionary = boilerplate();
+    let input = [dictionary.as_slice(), b"payload"].concat();
+
+    let prepared =
+        PreparedDictionary::new(DictionaryType::Raw, dictionary.as_slice(), Quality::best());
+    let prepared = Arc::new(prepared.unwrap());
+
+    let without = compress(&input, &BrotliEncoderOptions::new());
+    let with = compress(
+        &input,
+        BrotliEncoderOptions::new().prepared_dictionary(prepared),
+    );
+
+    assert!(with.len() < without.len());
+}
+
+#[test]
+fn test_prepared_dictionary_shared_across_threads() {
+    let dictionary = boilerplate();
+    let prepared =
+        PreparedDictionary::new_in(DictionaryType::Raw, dictionary, Quality::best(), System);
+    let prepared = Arc::new(prepared.unwrap());
+
+    let handles: Vec<_> = (0..4)
+        .map(|_| {
+            let prepared = prepared.clone();
+
+            thread::spawn(move || {
+                let input = [prepared.data(), common::gen_medium_entropy(512).as_slice()].concat();
+                let mut options = BrotliEncoderOptions::new();
+
+                compress(&input, options.prepared_dictionary(prepared))
+            })
+        })
+        .collect();
+
+    let outputs: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+
+    assert!(outputs.windows(2).all(|w| w[0] == w[1]));
+}
+
+#[test]
+fn test_too_many_prepared_dictionaries() {
+    let prepared = PreparedDictionary::new(DictionaryType::Raw, boilerplate(), Quality::best());
+    let prepared = Arc::new(prepared.unwrap());
+
+    let mut options = BrotliEncoderOptions::new();
+
+    for _ in 0..16 {
+        options.prepared_dictionary(prepared.clone());
+    }
+
+    assert_eq!(
+        options.build().unwrap_err(),
+        SetParameterError::InvalidDictionary
+    );
+}
+
+fn compress_with_dictionary(input: &[u8], dictionary: &[u8]) -> Vec<u8> {
+    let prepared = PreparedDictionary::new(DictionaryType::Raw, dictionary, Quality::best());
+    let prepared = Arc::new(prepared.unwrap());
+
+    compress(
+        input,
+        BrotliEncoderOptions::new().prepared_dictionary(prepared),
+    )
+}
+
+#[test]
+fn test_dictionary_reader_round_trip() {
+    let dictionary = boilerplate();
+    let input = [dictionary.as_slice(), b"payload"].concat();
+    let compressed = compress_with_dictionary(&input, &dictionary);
+
+    let decoder = BrotliDecoderOptions::new()
+        .dictionary(DictionaryType::Raw, dictionary)
+        .build()
+        .unwrap();
+    let mut decompressor = DecompressorReader::with_decoder(decoder, compressed.as_slice());
+    let mut decompressed = Vec::new();
+    decompressor.read_to_end(&mut decompressed).unwrap();
+
+    assert_eq!(input, decompressed);
+}
+
+#[test]
+fn test_dictionary_writer_round_trip() {
+    let dictionary = boilerplate();
+    let input = [b"payload", dictionary.as_slice()].concat();
+    let compressed = compress_with_dictionary(&input, &dictionary);
+
+    let decoder = BrotliDecoderOptions::new()
+        .dictionary(DictionaryType::Raw, dictionary)
+        .build_in(System)
+        .unwrap();
+    let mut decompressor = DecompressorWriter::with_decoder(decoder, Vec::new());
+    decompressor.write_all(compressed.as_slice()).unwrap();
+
+    assert_eq!(input, decompressor.into_inner().unwrap());
+}
+
+#[test]
+fn test_missing_dictionary() {
+    let dictionary = boilerplate();
+    let input = [dictionary.as_slice(), b"payload"].concat();
+    let compressed = compress_with_dictionary(&input, &dictionary);
+
+    let mut decompressor = DecompressorReader::new(compressed.as_slice());
+    let err = decompressor.read_to_end(&mut Vec::new()).unwrap_err();
+    let err = err.into_inner().unwrap().downcast::<DecodeError>().unwrap();
+
+    assert!(matches!(
+        *err,
+        DecodeError::FormatDictionary
+            | DecodeError::FormatDistance
+            | DecodeError::CompoundDictionary
+    ));
+}
+
+#[test]
+fn test_dictionary_missing_message() {
+    let dictionary = boilerplate();
+    let input = [dictionary.as_slice(), b"payload"].concat();
+    let compressed = compress_with_dictionary(&input, &dictionary);
+
+    let mut decompressor = DecompressorReader::new(compressed.as_slice());
+    let err = decompressor.read_to_end(&mut Vec::new()).unwrap_err();
+
+    // a missing custom dictionary is reported as a format error rather than a missing dictionary
+    assert_eq!(err.to_string(), "brotli decoder error: _ERROR_FORMAT_DICTIONARY");
+    assert_eq!(
+        *err.into_inner().unwrap().downcast::<DecodeError>().unwrap(),
+        DecodeError::FormatDictionary
+    );
+}
+
+#[test]
+fn test_dictionary_after_decoding_started() {
+    let mut decoder = BrotliDecoderOptions::new().build().unwrap();
+    decoder.give_input(&[0x0b]).unwrap();
+
+    let res = decoder.attach_dictionary(DictionaryType::Raw, boilerplate());
+
+    assert_eq!(res.unwrap_err(), SetParameterError::InvalidDictionary);
+}
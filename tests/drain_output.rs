@@ -0,0 +1,91 @@
+use brotlic::decode::BrotliDecoder;
+use brotlic::encode::{BrotliEncoder, BrotliOperation};
+use std::io;
+
+mod common;
+
+fn compress_drained(input: &[u8]) -> Vec<u8> {
+    let mut encoder = BrotliEncoder::new();
+    let mut compressed = Vec::new();
+
+    for chunk in input.chunks(10_000) {
+        let mut chunk = chunk;
+
+        while !chunk.is_empty() {
+            let bytes_read = encoder.give_input(chunk, BrotliOperation::Process).unwrap();
+            chunk = &chunk[bytes_read..];
+
+            while let Some(output) = encoder.next_output() {
+                compressed.extend_from_slice(output);
+            }
+        }
+    }
+
+    while !encoder.is_finished() {
+        encoder.give_input(&[], BrotliOperation::Finish).unwrap();
+        encoder
+            .drain_output(|output| {
+                compressed.extend_from_slice(output);
+                Ok::<(), io::Error>(())
+            })
+            .unwrap();
+    }
+
+    compressed
+}
+
+fn decompress_drained(input: &[u8]) -> Vec<u8> {
+    decompress_with(&mut BrotliDecoder::new(), input)
+}
+
+fn decompress_with(decoder: &mut BrotliDecoder, mut input: &[u8]) -> Vec<u8> {
+    let mut decompressed = Vec::new();
+
+    while !decoder.is_finished() {
+        let (bytes_read, _) = decoder.give_input(input).unwrap();
+        input = &input[bytes_read..];
+
+        decoder
+            .drain_output(|output| {
+                decompressed.extend_from_slice(output);
+                Ok::<(), io::Error>(())
+            })
+            .unwrap();
+    }
+
+    decompressed
+}
+
+#[test]
+fn test_drain_output_round_trip() {
+    for input in [
+        Vec::new(),
+        common::gen_min_entropy(1 << 20),
+        common::gen_medium_entropy(1 << 18),
+        common::gen_max_entropy(1 << 16),
+    ] {
+        assert_eq!(decompress_drained(&compress_drained(&input)), input);
+    }
+}
+
+#[test]
+fn test_drain_output_error() {
+    let input = common::gen_min_entropy(1 << 20);
+    let compressed = common::compress(&input);
+    let mut decoder = BrotliDecoder::new();
+    let (bytes_read, _) = decoder.give_input(&compressed).unwrap();
+
+    let mut first = Vec::new();
+    let result = decoder.drain_output(|output| {
+        first.extend_from_slice(output);
+        Err("stop")
+    });
+
+    // draining stops at the first error, the bytes passed to the closure are consumed
+    assert_eq!(result, Err("stop"));
+    assert!(!first.is_empty());
+
+    let mut rest = decompress_with(&mut decoder, &compressed[bytes_read..]);
+    first.append(&mut rest);
+    assert_eq!(first, input);
+}
@@ -0,0 +1,172 @@
+#![cfg(feature = "futures-io")]
+
+use brotlic::futures_io::{CompressorReader, CompressorWriter};
+use brotlic::futures_io::{DecompressorReader, DecompressorWriter};
+use futures::executor::block_on;
+use futures::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader, Cursor};
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+mod common;
+
+/// Reads and writes at most a few bytes at a time, returning `Pending` on every other call.
+struct Trickle<T> {
+    inner: T,
+    pending: bool,
+}
+
+impl<T> Trickle<T> {
+    fn new(inner: T) -> Self {
+        Trickle {
+            inner,
+            pending: false,
+        }
+    }
+
+    fn poll_pending(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        self.pending = !self.pending;
+
+        if self.pending {
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        } else {
+            Poll::Ready(())
+        }
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for Trickle<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        futures::ready!(self.poll_pending(cx));
+
+        let len = buf.len().min(7);
+        Pin::new(&mut self.inner).poll_read(cx, &mut buf[..len])
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for Trickle<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        futures::ready!(self.poll_pending(cx));
+
+        let len = buf.len().min(7);
+        Pin::new(&mut self.inner).poll_write(cx, &buf[..len])
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        futures::ready!(self.poll_pending(cx));
+
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        futures::ready!(self.poll_pending(cx));
+
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+}
+
+#[test]
+fn test_compressor_writer() {
+    let input = common::gen_medium_entropy(1 << 16);
+
+    let compressed = block_on(async {
+        let mut compressor = CompressorWriter::new(Trickle::new(Vec::new()));
+        compressor.write_all(&input[..1000]).await.unwrap();
+        compressor.flush().await.unwrap();
+        compressor.write_all(&input[1000..]).await.unwrap();
+        compressor.close().await.unwrap();
+
+        assert!(compressor.is_finished());
+        compressor.into_inner().inner
+    });
+
+    assert_eq!(common::decompress(&compressed), input);
+}
+
+#[test]
+fn test_compressor_reader() {
+    let input = common::gen_medium_entropy(1 << 16);
+
+    let compressed = block_on(async {
+        let source = BufReader::new(Trickle::new(Cursor::new(input.as_slice())));
+        let mut compressor = CompressorReader::new(source);
+        let mut compressed = Vec::new();
+        compressor.read_to_end(&mut compressed).await.unwrap();
+
+        assert!(compressor.is_finished());
+        compressed
+    });
+
+    assert_eq!(common::decompress(&compressed), input);
+}
+
+#[test]
+fn test_decompressor_writer() {
+    let input = common::gen_medium_entropy(1 << 16);
+    let compressed = common::compress(&input);
+
+    let decompressed = block_on(async {
+        let mut decompressor = DecompressorWriter::new(Trickle::new(Vec::new()));
+        decompressor.write_all(&compressed).await.unwrap();
+        decompressor.close().await.unwrap();
+
+        assert!(decompressor.is_finished());
+        decompressor.into_inner().inner
+    });
+
+    assert_eq!(decompressed, input);
+}
+
+#[test]
+fn test_decompressor_writer_truncated() {
+    let compressed = common::compress(&common::gen_medium_entropy(1 << 12));
+
+    let err = block_on(async {
+        let mut decompressor = DecompressorWriter::new(Vec::new());
+        let truncated = &compressed[..compressed.len() / 2];
+        decompressor.write_all(truncated).await.unwrap();
+        decompressor.close().await.unwrap_err()
+    });
+
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn test_decompressor_reader() {
+    let input = common::gen_medium_entropy(1 << 16);
+    let compressed = common::compress(&input);
+
+    let decompressed = block_on(async {
+        let source = BufReader::new(Trickle::new(Cursor::new(compressed.as_slice())));
+        let mut decompressor = DecompressorReader::new(source);
+        let mut decompressed = Vec::new();
+        decompressor.read_to_end(&mut decompressed).await.unwrap();
+
+        assert!(decompressor.is_finished());
+        decompressed
+    });
+
+    assert_eq!(decompressed, input);
+}
+
+#[test]
+fn test_decompressor_reader_truncated() {
+    let compressed = common::compress(&common::gen_medium_entropy(1 << 12));
+
+    let err = block_on(async {
+        let truncated = &compressed[..compressed.len() / 2];
+        let mut decompressor = DecompressorReader::new(truncated);
+        decompressor.read_to_end(&mut Vec::new()).await.unwrap_err()
+    });
+
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+}
@@ -0,0 +1,135 @@
+use brotlic::encode::BrotliEncoderOptions;
+use brotlic::{BrotliDecoderOptions, CompressorWriter, DecompressorReader, HeaderBuilder, Quality};
+use std::io::{self, BufReader, Read, Write};
+
+mod common;
+
+fn compress_with_header(builder: &HeaderBuilder, input: &[u8]) -> Vec<u8> {
+    let mut compressor = builder.write(Vec::new()).unwrap();
+    compressor.write_all(input).unwrap();
+    compressor.into_inner().unwrap()
+}
+
+#[test]
+fn test_header_round_trip() {
+    let input = common::gen_medium_entropy(64 * 1024);
+    let compressed = compress_with_header(
+        HeaderBuilder::new()
+            .filename("data.bin")
+            .mtime(1_650_000_000)
+            .mode(0o644)
+            .uncompressed_len(input.len() as u64)
+            .entry("content-type", "application/octet-stream")
+            .entry("origin", vec![0, 1, 2, 3]),
+        &input,
+    );
+
+    let reader = BufReader::with_capacity(3, compressed.as_slice());
+    let mut decompressor = DecompressorReader::new(reader);
+    let header = decompressor.header().unwrap().unwrap().clone();
+
+    assert_eq!(header.filename(), Some(b"data.bin".as_slice()));
+    assert_eq!(header.mtime(), Some(1_650_000_000));
+    assert_eq!(header.mode(), Some(0o644));
+    assert_eq!(header.uncompressed_len(), Some(input.len() as u64));
+    assert_eq!(header.get("origin"), Some([0, 1, 2, 3].as_slice()));
+    assert_eq!(header.get("missing"), None);
+    assert_eq!(
+        header.entries().map(|(k, _)| k).collect::<Vec<_>>(),
+        ["content-type", "origin"]
+    );
+    assert_eq!(decompressor.header().unwrap(), Some(&header));
+
+    let mut decompressed = Vec::new();
+    decompressor.read_to_end(&mut decompressed).unwrap();
+
+    assert_eq!(decompressed, input);
+}
+
+#[test]
+fn test_header_ignored_by_plain_decoder() {
+    let input = common::gen_medium_entropy(4096);
+    let compressed = compress_with_header(HeaderBuilder::new().filename("a.txt"), &input);
+
+    let mut decompressor = DecompressorReader::new(compressed.as_slice());
+    let mut decompressed = Vec::new();
+    decompressor.read_to_end(&mut decompressed).unwrap();
+
+    assert_eq!(decompressed, input);
+
+    let mut decompressed = vec![0; input.len()];
+    let len = brotlic::decompress(&compressed, &mut decompressed).unwrap();
+
+    assert_eq!(&decompressed[..len], input);
+}
+
+#[test]
+fn test_empty_header() {
+    let compressed = compress_with_header(&HeaderBuilder::new(), b"hello world");
+
+    let mut decompressor = DecompressorReader::new(compressed.as_slice());
+    let header = decompressor.header().unwrap().unwrap();
+
+    assert_eq!(header, &HeaderBuilder::new().build());
+    assert_eq!(header.filename(), None);
+}
+
+#[test]
+fn test_header_missing() {
+    let mut compressor = CompressorWriter::new(Vec::new());
+    compressor.write_all(b"hello world").unwrap();
+    compressor.write_metadata(b"BRHD not at the start").unwrap();
+    let compressed = compressor.into_inner().unwrap();
+
+    let mut decompressor = DecompressorReader::new(compressed.as_slice());
+    assert_eq!(decompressor.header().unwrap(), None);
+
+    let mut decompressed = String::new();
+    decompressor.read_to_string(&mut decompressed).unwrap();
+
+    assert_eq!(decompressed, "hello world");
+}
+
+#[test]
+fn test_header_after_reading() {
+    let compressed = compress_with_header(HeaderBuilder::new().filename("a.txt"), b"hello");
+
+    let mut decompressor = DecompressorReader::new(compressed.as_slice());
+    decompressor.read_exact(&mut [0; 2]).unwrap();
+
+    assert_eq!(decompressor.header().unwrap(), None);
+}
+
+#[test]
+fn test_header_with_captured_metadata() {
+    let builder = HeaderBuilder::new().filename("a.txt").clone();
+    let encoder = BrotliEncoderOptions::new()
+        .quality(Quality::worst())
+        .build()
+        .unwrap();
+    let mut compressor = builder.write_with_encoder(encoder, Vec::new()).unwrap();
+    compressor.write_all(b"hello").unwrap();
+    let compressed = compressor.into_inner().unwrap();
+
+    let decoder = BrotliDecoderOptions::new()
+        .capture_metadata(true)
+        .build()
+        .unwrap();
+    let mut decompressor = DecompressorReader::with_decoder(decoder, compressed.as_slice());
+
+    assert_eq!(decompressor.header().unwrap(), Some(&builder.build()));
+    assert_eq!(decompressor.pop_metadata().unwrap().offset(), 0);
+}
+
+#[test]
+fn test_malformed_header() {
+    let mut compressor = CompressorWriter::new(Vec::new());
+    compressor.write_metadata(b"BRHD\x01\x02\x08\x00").unwrap();
+    compressor.write_all(b"hello world").unwrap();
+    let compressed = compressor.into_inner().unwrap();
+
+    let mut decompressor = DecompressorReader::new(compressed.as_slice());
+    let err = decompressor.header().unwrap_err();
+
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
@@ -0,0 +1,116 @@
+#![cfg(feature = "http")]
+
+use brotlic::http::{AcceptEncoding, Encoding, Preset};
+use http::header::{ACCEPT_ENCODING, HeaderMap, HeaderValue};
+
+#[test]
+fn test_parse() {
+    let accept_encoding = AcceptEncoding::parse("gzip, deflate, br");
+
+    assert!(accept_encoding.accepts("gzip"));
+    assert!(accept_encoding.accepts("br"));
+    assert!(accept_encoding.accepts("identity"));
+    assert!(!accept_encoding.accepts("zstd"));
+    assert_eq!(accept_encoding.negotiate(), Encoding::Brotli);
+}
+
+#[test]
+fn test_parse_quality() {
+    let accept_encoding = AcceptEncoding::parse("gzip;q=1.0, br; Q=0.5, deflate;q=0.125");
+
+    assert_eq!(accept_encoding.quality("gzip"), 1.0);
+    assert_eq!(accept_encoding.quality("BR"), 0.5);
+    assert_eq!(accept_encoding.quality("deflate"), 0.125);
+    assert_eq!(accept_encoding.negotiate(), Encoding::Brotli);
+
+    let accept_encoding = AcceptEncoding::parse("br;q=0.5, identity;q=0.1");
+    assert_eq!(accept_encoding.negotiate(), Encoding::Brotli);
+
+    let accept_encoding = AcceptEncoding::parse("br;q=0.5, identity");
+    assert_eq!(accept_encoding.negotiate(), Encoding::Identity);
+
+    let accept_encoding = AcceptEncoding::parse("br;q=0.5, *;q=0.8");
+    assert_eq!(accept_encoding.negotiate(), Encoding::Identity);
+}
+
+#[test]
+fn test_parse_invalid_quality() {
+    for value in [
+        "br;q=2",
+        "br;q=1.5",
+        "br;q=0.1234",
+        "br;q=-1",
+        "br;q=",
+        "br;q=.5",
+    ] {
+        let accept_encoding = AcceptEncoding::parse(value);
+
+        assert!(!accept_encoding.accepts("br"), "{}", value);
+        assert_eq!(accept_encoding.negotiate(), Encoding::Identity);
+    }
+}
+
+#[test]
+fn test_parse_wildcard() {
+    let accept_encoding = AcceptEncoding::parse("*");
+    assert!(accept_encoding.accepts("br"));
+    assert_eq!(accept_encoding.negotiate(), Encoding::Brotli);
+
+    let accept_encoding = AcceptEncoding::parse("gzip, *;q=0");
+    assert!(accept_encoding.accepts("gzip"));
+    assert!(!accept_encoding.accepts("identity"));
+    assert_eq!(accept_encoding.negotiate(), Encoding::NotAcceptable);
+
+    let accept_encoding = AcceptEncoding::parse("br;q=0, *");
+    assert!(!accept_encoding.accepts("br"));
+    assert_eq!(accept_encoding.negotiate(), Encoding::Identity);
+}
+
+#[test]
+fn test_parse_identity() {
+    let accept_encoding = AcceptEncoding::parse("identity;q=0");
+    assert_eq!(accept_encoding.negotiate(), Encoding::NotAcceptable);
+
+    let accept_encoding = AcceptEncoding::parse("identity;q=0, br;q=0.1");
+    assert_eq!(accept_encoding.negotiate(), Encoding::Brotli);
+
+    let accept_encoding = AcceptEncoding::parse("");
+    assert!(accept_encoding.accepts("identity"));
+    assert_eq!(accept_encoding.negotiate(), Encoding::Identity);
+}
+
+#[test]
+fn test_from_headers() {
+    let mut headers = HeaderMap::new();
+    assert_eq!(
+        AcceptEncoding::from_headers(&headers).negotiate(),
+        Encoding::Identity
+    );
+
+    headers.append(ACCEPT_ENCODING, HeaderValue::from_static("gzip"));
+    headers.append(ACCEPT_ENCODING, HeaderValue::from_static(" , BR;q=0.9"));
+    headers.append(ACCEPT_ENCODING, HeaderValue::from_bytes(b"\xff").unwrap());
+
+    let accept_encoding = AcceptEncoding::from_headers(&headers);
+    assert!(accept_encoding.accepts("gzip"));
+    assert_eq!(accept_encoding.quality("br"), 0.9);
+    assert_eq!(accept_encoding.negotiate(), Encoding::Brotli);
+}
+
+#[test]
+fn test_brotli_options() {
+    let accept_encoding = AcceptEncoding::parse("br");
+
+    for preset in [Preset::Dynamic, Preset::Static] {
+        let options = accept_encoding.brotli_options(preset).unwrap();
+        let compressed = options.compress_to_vec(b"hello world").unwrap();
+
+        assert_eq!(
+            brotlic::decompress_to_vec(&compressed).unwrap(),
+            b"hello world"
+        );
+    }
+
+    let accept_encoding = AcceptEncoding::parse("gzip");
+    assert!(accept_encoding.brotli_options(Preset::Static).is_none());
+}
@@ -0,0 +1,148 @@
+use brotlic::decode::{DecodeError, LimitError};
+use brotlic::{BrotliDecoderOptions, BrotliEncoderOptions, CompressorWriter};
+use brotlic::{DecompressorReader, DecompressorWriter, DictionaryType, SetParameterError};
+use brotlic::WindowSize;
+use std::alloc::System;
+use std::io::{self, Read, Write};
+
+mod common;
+
+fn compress(input: &[u8], window_size: WindowSize) -> Vec<u8> {
+    let encoder = BrotliEncoderOptions::new()
+        .window_size(window_size)
+        .build()
+        .unwrap();
+    let mut compressor = CompressorWriter::with_encoder(encoder, Vec::new());
+
+    compressor.write_all(input).unwrap();
+    compressor.into_inner().unwrap()
+}
+
+fn limit_error(err: io::Error) -> LimitError {
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+    *err.into_inner().unwrap().downcast::<LimitError>().unwrap()
+}
+
+fn read(compressed: &[u8], options: &BrotliDecoderOptions) -> io::Result<Vec<u8>> {
+    let decoder = options.build().unwrap();
+    let mut decompressor = DecompressorReader::with_decoder(decoder, compressed);
+    let mut decompressed = Vec::new();
+
+    decompressor.read_to_end(&mut decompressed)?;
+    Ok(decompressed)
+}
+
+fn write(compressed: &[u8], options: &BrotliDecoderOptions) -> (Vec<u8>, io::Result<()>) {
+    let decoder = options.build().unwrap();
+    let mut decompressor = DecompressorWriter::with_decoder(decoder, Vec::new());
+    let res = compressed
+        .chunks(256)
+        .try_for_each(|chunk| decompressor.write_all(chunk));
+
+    (decompressor.into_parts().0, res)
+}
+
+#[test]
+fn test_max_output_size_reader() {
+    let input = common::gen_min_entropy(1 << 20);
+    let compressed = compress(&input, WindowSize::default());
+
+    let options = BrotliDecoderOptions::new()
+        .max_output_size(input.len() as u64)
+        .clone();
+    assert_eq!(read(&compressed, &options).unwrap(), input);
+
+    let options = BrotliDecoderOptions::new()
+        .max_output_size(input.len() as u64 - 1)
+        .clone();
+    let err = read(&compressed, &options).unwrap_err();
+    assert_eq!(limit_error(err), LimitError::OutputSize);
+}
+
+#[test]
+fn test_max_output_size_writer() {
+    let input = common::gen_min_entropy(1 << 20);
+    let compressed = compress(&input, WindowSize::default());
+
+    let options = BrotliDecoderOptions::new()
+        .max_output_size(input.len() as u64)
+        .clone();
+    let (output, res) = write(&compressed, &options);
+    res.unwrap();
+    assert_eq!(output, input);
+
+    let options = BrotliDecoderOptions::new().max_output_size(4096).clone();
+    let (output, res) = write(&compressed, &options);
+    assert_eq!(limit_error(res.unwrap_err()), LimitError::OutputSize);
+    assert!(output.len() <= 4096);
+}
+
+#[test]
+fn test_max_ratio() {
+    let bomb = compress(&common::gen_min_entropy(1 << 20), WindowSize::default());
+    let options = BrotliDecoderOptions::new().max_ratio(100).clone();
+
+    let err = read(&bomb, &options).unwrap_err();
+    assert_eq!(limit_error(err), LimitError::Ratio);
+
+    let (_, res) = write(&bomb, &options);
+    assert_eq!(limit_error(res.unwrap_err()), LimitError::Ratio);
+
+    let input = common::gen_medium_entropy(1 << 16);
+    let compressed = compress(&input, WindowSize::default());
+    assert_eq!(read(&compressed, &options).unwrap(), input);
+
+    let (output, res) = write(&compressed, &options);
+    res.unwrap();
+    assert_eq!(output, input);
+}
+
+#[test]
+fn test_max_memory() {
+    let input = common::gen_medium_entropy(1 << 20);
+    let compressed = compress(&input, WindowSize::new(22).unwrap());
+
+    let options = BrotliDecoderOptions::new().max_memory(1 << 16).clone();
+    let err = read(&compressed, &options).unwrap_err();
+    assert_eq!(limit_error(err), LimitError::Memory);
+
+    let (_, res) = write(&compressed, &options);
+    assert_eq!(limit_error(res.unwrap_err()), LimitError::Memory);
+
+    let options = BrotliDecoderOptions::new().max_memory(16 << 20).clone();
+    assert_eq!(read(&compressed, &options).unwrap(), input);
+}
+
+#[test]
+fn test_max_memory_custom_allocator() {
+    let input = common::gen_medium_entropy(1 << 20);
+    let compressed = compress(&input, WindowSize::new(22).unwrap());
+
+    let decoder = BrotliDecoderOptions::new()
+        .max_memory(1 << 16)
+        .build_in(System)
+        .unwrap();
+    let mut decompressor = DecompressorReader::with_decoder(decoder, compressed.as_slice());
+    let err = decompressor.read_to_end(&mut Vec::new()).unwrap_err();
+
+    assert_eq!(limit_error(err), LimitError::Memory);
+}
+
+#[test]
+fn test_max_memory_not_reported_for_later_errors() {
+    // find the smallest limit that fits the decoder state, which leaves no room for a dictionary
+    let mut decoder = (0..)
+        .step_by(8)
+        .find_map(|max| BrotliDecoderOptions::new().max_memory(max).try_build().ok())
+        .unwrap();
+
+    let res = decoder.attach_dictionary(DictionaryType::Raw, vec![0; 64]);
+    assert_eq!(res.unwrap_err(), SetParameterError::InvalidDictionary);
+
+    // the refused allocation of the dictionary must not be reported for the invalid window bits
+    let mut decompressor = DecompressorReader::with_decoder(decoder, &[0x11, 0x00, 0x00, 0x00][..]);
+    let err = decompressor.read_to_end(&mut Vec::new()).unwrap_err();
+
+    assert!(err.into_inner().unwrap().is::<DecodeError>());
+}
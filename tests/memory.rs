@@ -1,4 +1,9 @@
-use brotlic::{CompressionMode, Quality, WindowSize};
+use brotlic::encode::PreparedDictionary;
+use brotlic::{BlockSize, BrotliDecoder, BrotliDecoderOptions, BrotliEncoderOptions};
+use brotlic::{CompressionMode, DecompressorReader, DictionaryType, LargeWindowSize};
+use brotlic::{DecompressToVecError, Quality, WindowSize};
+use std::io::Read;
+use std::sync::Arc;
 
 mod common;
 
@@ -72,3 +77,186 @@ fn test_medium_entropy_large() {
 fn test_max_entropy_large() {
     verify(common::gen_max_entropy(8192).as_slice());
 }
+
+fn verify_options(input: &[u8], options: &BrotliEncoderOptions, decoder: BrotliDecoder) {
+    let bound = brotlic::compress_bound(input.len(), Quality::best()).unwrap();
+    let compressed = {
+        let mut buf = vec![0; bound];
+        let size = options.compress(input, buf.as_mut_slice()).unwrap();
+
+        buf.truncate(size);
+        buf
+    };
+
+    assert_eq!(options.compress_to_vec(input).unwrap(), compressed);
+
+    let mut decompressor = DecompressorReader::with_decoder(decoder, compressed.as_slice());
+    let mut decompressed = Vec::new();
+    decompressor.read_to_end(&mut decompressed).unwrap();
+
+    assert_eq!(input, decompressed);
+}
+
+#[test]
+fn test_options_large_window_and_block_size() {
+    let input = common::gen_medium_entropy(1 << 16);
+    let options = BrotliEncoderOptions::new()
+        .quality(Quality::best())
+        .large_window_size(LargeWindowSize::new(26).unwrap())
+        .block_size(BlockSize::new(20).unwrap())
+        .clone();
+    let decoder = BrotliDecoderOptions::new()
+        .large_window_size(true)
+        .build()
+        .unwrap();
+
+    verify_options(input.as_slice(), &options, decoder);
+}
+
+#[test]
+fn test_options_prepared_dictionary() {
+    let dictionary = common::gen_max_entropy(4096);
+    let input = [dictionary.as_slice(), b"payload"].concat();
+
+    let prepared =
+        PreparedDictionary::new(DictionaryType::Raw, dictionary.as_slice(), Quality::best());
+    let options = BrotliEncoderOptions::new()
+        .prepared_dictionary(Arc::new(prepared.unwrap()))
+        .clone();
+
+    let mut decoder = BrotliDecoderOptions::new().build().unwrap();
+    decoder
+        .attach_dictionary(DictionaryType::Raw, dictionary.clone())
+        .unwrap();
+
+    verify_options(input.as_slice(), &options, decoder);
+
+    let compressed = options.compress_to_vec(input.as_slice()).unwrap();
+    assert!(compressed.len() < dictionary.len() / 2);
+}
+
+#[test]
+fn test_options_bound_max_entropy() {
+    for len in [0, 1, 32, 8192, 1 << 17] {
+        let input = common::gen_max_entropy(len);
+        let bound = brotlic::compress_bound(len, Quality::best()).unwrap();
+
+        for quality in 0..=11 {
+            let options = BrotliEncoderOptions::new()
+                .quality(Quality::new(quality).unwrap())
+                .clone();
+
+            let mut output = vec![0; bound];
+            let size = options
+                .compress(input.as_slice(), output.as_mut_slice())
+                .unwrap();
+            let compressed = options.compress_to_vec(input.as_slice()).unwrap();
+
+            assert!(compressed.len() <= bound);
+
+            let mut decompressed = vec![0; len];
+            let bytes_written = brotlic::decompress(&output[..size], &mut decompressed).unwrap();
+            assert_eq!(&decompressed[..bytes_written], input.as_slice());
+
+            let bytes_written = brotlic::decompress(&compressed, &mut decompressed).unwrap();
+            assert_eq!(&decompressed[..bytes_written], input.as_slice());
+        }
+    }
+}
+
+#[test]
+fn test_options_output_too_small() {
+    let input = common::gen_max_entropy(512);
+    let mut output = vec![0; 256];
+
+    let res = BrotliEncoderOptions::new().compress(input.as_slice(), output.as_mut_slice());
+
+    assert!(res.is_err());
+}
+
+#[test]
+fn test_to_vec_round_trip() {
+    for input in [
+        common::gen_min_entropy(0),
+        common::gen_min_entropy(1 << 20),
+        common::gen_medium_entropy(8192),
+        common::gen_max_entropy(8192),
+    ] {
+        let compressed = brotlic::compress_to_vec(
+            input.as_slice(),
+            Quality::default(),
+            WindowSize::default(),
+            CompressionMode::Generic,
+        )
+        .unwrap();
+
+        assert_eq!(brotlic::decompress_to_vec(&compressed).unwrap(), input);
+        assert_eq!(
+            brotlic::decompress_to_vec_with_limit(&compressed, input.len()).unwrap(),
+            input
+        );
+    }
+}
+
+#[test]
+fn test_to_vec_limit_exceeded() {
+    let input = common::gen_min_entropy(1 << 20);
+    let compressed = brotlic::compress_to_vec(
+        input.as_slice(),
+        Quality::default(),
+        WindowSize::default(),
+        CompressionMode::Generic,
+    )
+    .unwrap();
+
+    for limit in [0, 1, 4096, input.len() - 1] {
+        let res = brotlic::decompress_to_vec_with_limit(&compressed, limit);
+
+        assert_eq!(res, Err(DecompressToVecError::OutputLimitExceeded));
+    }
+}
+
+#[test]
+fn test_to_vec_corrupted() {
+    let input = common::gen_medium_entropy(8192);
+    let compressed = brotlic::compress_to_vec(
+        input.as_slice(),
+        Quality::default(),
+        WindowSize::default(),
+        CompressionMode::Generic,
+    )
+    .unwrap();
+
+    let truncated = &compressed[..compressed.len() / 2];
+    let res = brotlic::decompress_to_vec_with_limit(truncated, usize::MAX);
+
+    assert_eq!(res, Err(DecompressToVecError::Decompress));
+    assert!(brotlic::decompress_to_vec(truncated).is_err());
+    assert!(brotlic::decompress_to_vec(&[0xff; 16]).is_err());
+}
+
+#[test]
+fn test_decompress_into_vec_spare_capacity() {
+    let input = common::gen_medium_entropy(8192);
+    let compressed = brotlic::compress_to_vec(
+        input.as_slice(),
+        Quality::default(),
+        WindowSize::default(),
+        CompressionMode::Generic,
+    )
+    .unwrap();
+
+    let mut decoder = BrotliDecoder::new();
+    let mut output = b"prefix".to_vec();
+    let mut remaining = compressed.as_slice();
+
+    while !decoder.is_finished() {
+        output.reserve(1000);
+
+        let res = decoder.decompress_into_vec(remaining, &mut output).unwrap();
+        remaining = &remaining[res.bytes_read..];
+    }
+
+    assert_eq!(&output[..6], b"prefix");
+    assert_eq!(&output[6..], input.as_slice());
+}
@@ -0,0 +1,241 @@
+use brotlic::decode::Metadata;
+use brotlic::encode::{BrotliOperation, MAX_METADATA_SIZE};
+use brotlic::{BrotliDecoderOptions, BrotliEncoderOptions, CompressorWriter, DecompressorReader};
+use brotlic::{DecompressorWriter, Quality, WindowSize};
+use std::io::{self, BufReader, Read, Write};
+use std::iter;
+
+mod common;
+
+fn decompress(compressed: &[u8]) -> Vec<u8> {
+    let mut decompressor = DecompressorReader::new(compressed);
+    let mut decompressed = Vec::new();
+    decompressor.read_to_end(&mut decompressed).unwrap();
+
+    decompressed
+}
+
+fn compress_with_metadata(quality: Quality, input: &[u8], metadata: &[u8]) -> Vec<u8> {
+    let encoder = BrotliEncoderOptions::new()
+        .quality(quality)
+        .build()
+        .unwrap();
+    let mut compressor = CompressorWriter::with_encoder(encoder, Vec::new());
+
+    compressor.write_metadata(metadata).unwrap();
+    compressor.write_all(&input[..input.len() / 2]).unwrap();
+    compressor.write_metadata(metadata).unwrap();
+    compressor.write_all(&input[input.len() / 2..]).unwrap();
+    compressor.write_metadata(metadata).unwrap();
+    compressor.into_inner().unwrap()
+}
+
+#[test]
+fn test_metadata_round_trip() {
+    let input = common::gen_medium_entropy(64 * 1024);
+    let metadata = b"some metadata that is carried along";
+
+    for quality in [0, 1, 5, 11] {
+        let quality = Quality::new(quality).unwrap();
+        let compressed = compress_with_metadata(quality, &input, metadata);
+
+        assert_eq!(decompress(&compressed), input);
+    }
+}
+
+#[test]
+fn test_metadata_stored_verbatim() {
+    let metadata = b"a metadata block stored verbatim";
+
+    let mut compressor = CompressorWriter::new(Vec::new());
+    compressor.write_all(b"hello world").unwrap();
+    compressor.write_metadata(metadata).unwrap();
+    let compressed = compressor.into_inner().unwrap();
+
+    assert!(compressed.windows(metadata.len()).any(|w| w == metadata));
+    assert_eq!(decompress(&compressed), b"hello world");
+}
+
+#[test]
+fn test_empty_metadata() {
+    let mut compressor = CompressorWriter::new(Vec::new());
+    compressor.write_metadata(&[]).unwrap();
+    compressor.write_all(b"hello world").unwrap();
+    let compressed = compressor.into_inner().unwrap();
+
+    assert_eq!(decompress(&compressed), b"hello world");
+}
+
+/// Counts the writes to the underlying writer.
+#[derive(Debug)]
+struct CountingWriter {
+    inner: Vec<u8>,
+    writes: usize,
+}
+
+impl Write for CountingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writes += 1;
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_large_metadata_written_in_chunks() {
+    let metadata = common::gen_max_entropy(1 << 20);
+    let writer = CountingWriter {
+        inner: Vec::new(),
+        writes: 0,
+    };
+
+    let mut compressor = CompressorWriter::new(writer);
+    compressor.write_all(b"hello").unwrap();
+    compressor.write_metadata(&metadata).unwrap();
+    compressor.write_all(b" world").unwrap();
+    let writer = compressor.into_inner().unwrap();
+
+    // the metadata is written in buffer sized chunks rather than 16 bytes at a time
+    assert!(writer.writes < 100, "{} writes", writer.writes);
+    assert!(writer.inner.windows(1024).any(|w| w == &metadata[..1024]));
+    assert_eq!(decompress(&writer.inner), b"hello world");
+}
+
+#[test]
+fn test_metadata_too_large() {
+    let metadata = vec![0; MAX_METADATA_SIZE + 1];
+    let mut compressor = CompressorWriter::new(Vec::new());
+
+    let err = compressor.write_metadata(&metadata).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+    let mut encoder = BrotliEncoderOptions::new().build().unwrap();
+    assert!(encoder.emit_metadata(&metadata).is_err());
+}
+
+#[test]
+fn test_metadata_interrupted() {
+    let mut encoder = BrotliEncoderOptions::new().build().unwrap();
+    let metadata = [0xab; 128];
+
+    let bytes_read = encoder.emit_metadata(&metadata).unwrap();
+    assert!(bytes_read < metadata.len());
+    assert!(
+        encoder
+            .give_input(b"data", BrotliOperation::Process)
+            .is_err()
+    );
+}
+
+fn decompress_with_metadata(compressed: &[u8], capacity: usize) -> (Vec<u8>, Vec<Metadata>) {
+    let decoder = BrotliDecoderOptions::new()
+        .capture_metadata(true)
+        .build()
+        .unwrap();
+    let reader = BufReader::with_capacity(capacity, compressed);
+    let mut decompressor = DecompressorReader::with_decoder(decoder, reader);
+    let mut decompressed = Vec::new();
+    decompressor.read_to_end(&mut decompressed).unwrap();
+
+    let metadata = iter::from_fn(|| decompressor.pop_metadata()).collect();
+
+    (decompressed, metadata)
+}
+
+#[test]
+fn test_capture_metadata_offsets() {
+    let input = common::gen_medium_entropy(256 * 1024);
+    let splits = [0, 1000, 70_000, 150_000, input.len()];
+
+    let encoder = BrotliEncoderOptions::new()
+        .quality(Quality::new(5).unwrap())
+        .window_size(WindowSize::new(16).unwrap())
+        .build()
+        .unwrap();
+    let mut compressor = CompressorWriter::with_encoder(encoder, Vec::new());
+    let mut written = 0;
+
+    for (i, split) in splits.iter().enumerate() {
+        compressor.write_all(&input[written..*split]).unwrap();
+        compressor
+            .write_metadata(format!("marker {}", i).as_bytes())
+            .unwrap();
+        compressor.flush().unwrap();
+        written = *split;
+    }
+
+    let compressed = compressor.into_inner().unwrap();
+
+    for capacity in [1, 7, 8192] {
+        let (decompressed, metadata) = decompress_with_metadata(&compressed, capacity);
+
+        assert_eq!(decompressed, input);
+        assert_eq!(metadata.len(), splits.len());
+
+        for (i, (metadata, split)) in metadata.iter().zip(splits).enumerate() {
+            assert_eq!(metadata.offset(), split);
+            assert_eq!(metadata.data(), format!("marker {}", i).as_bytes());
+        }
+    }
+}
+
+#[test]
+fn test_capture_large_metadata() {
+    let metadata = common::gen_max_entropy(100_000);
+
+    let mut compressor = CompressorWriter::new(Vec::new());
+    compressor.write_all(b"hello").unwrap();
+    compressor.write_metadata(&metadata).unwrap();
+    let compressed = compressor.into_inner().unwrap();
+
+    let decoder = BrotliDecoderOptions::new()
+        .capture_metadata(true)
+        .build()
+        .unwrap();
+    let mut decompressor = DecompressorWriter::with_decoder(decoder, Vec::new());
+
+    for chunk in compressed.chunks(999) {
+        decompressor.write_all(chunk).unwrap();
+    }
+
+    let captured = decompressor.pop_metadata().unwrap();
+    assert_eq!(captured.offset(), 5);
+    assert_eq!(captured.into_data(), metadata);
+    assert_eq!(decompressor.pop_metadata(), None);
+    assert_eq!(decompressor.into_inner().unwrap(), b"hello");
+}
+
+#[test]
+fn test_metadata_not_captured_by_default() {
+    let compressed = compress_with_metadata(Quality::default(), b"hello world", b"metadata");
+
+    let mut decompressor = DecompressorReader::new(compressed.as_slice());
+    decompressor.read_to_end(&mut Vec::new()).unwrap();
+
+    assert_eq!(decompressor.pop_metadata(), None);
+}
+
+#[test]
+fn test_capture_metadata_toggled() {
+    let compressed = compress_with_metadata(Quality::default(), b"hello world", b"metadata");
+
+    let mut decoder = BrotliDecoderOptions::new().build().unwrap();
+    decoder.capture_metadata(true);
+    decoder.capture_metadata(true);
+
+    let mut decompressor = DecompressorReader::with_decoder(decoder, compressed.as_slice());
+    decompressor.read_to_end(&mut Vec::new()).unwrap();
+
+    let (_, mut decoder) = decompressor.into_parts();
+    let offsets: Vec<_> = iter::from_fn(|| decoder.pop_metadata())
+        .map(|m| m.offset())
+        .collect();
+
+    assert_eq!(offsets, [0, 5, 11]);
+
+    decoder.capture_metadata(false);
+    assert_eq!(decoder.pop_metadata(), None);
+}
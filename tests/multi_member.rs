@@ -0,0 +1,126 @@
+use brotlic::{BrotliDecoderOptions, DecompressorReader, MultiDecompressorReader};
+use std::io::{self, BufRead, Read};
+
+mod common;
+
+#[test]
+fn test_decompressor_reader_bytes_consumed() {
+    let compressed = common::compress(&common::gen_medium_entropy(1 << 14));
+    let mut input = compressed.clone();
+    input.extend_from_slice(b"trailing");
+
+    let mut decompressor = DecompressorReader::new(input.as_slice());
+    io::copy(&mut decompressor, &mut io::sink()).unwrap();
+
+    assert_eq!(decompressor.bytes_consumed(), compressed.len() as u64);
+    assert_eq!(decompressor.get_mut().fill_buf().unwrap(), b"trailing");
+    assert_eq!(decompressor.into_inner().unwrap(), b"trailing");
+}
+
+#[test]
+fn test_multi_member() {
+    let parts = [
+        common::gen_medium_entropy(1 << 14),
+        Vec::new(),
+        common::gen_min_entropy(1 << 16),
+        common::gen_max_entropy(1000),
+    ];
+
+    let compressed: Vec<_> = parts.iter().map(|part| common::compress(part)).collect();
+    let input = compressed.concat();
+
+    let mut decompressor = MultiDecompressorReader::new(input.as_slice());
+    let mut decompressed = Vec::new();
+    decompressor.read_to_end(&mut decompressed).unwrap();
+
+    assert_eq!(decompressed, parts.concat());
+    assert_eq!(decompressor.members(), 4);
+    assert_eq!(decompressor.bytes_consumed(), input.len() as u64);
+    assert_eq!(
+        decompressor.member_offset(),
+        (input.len() - compressed[3].len()) as u64
+    );
+}
+
+#[test]
+fn test_multi_member_offsets() {
+    let compressed = [common::compress(b"hello "), common::compress(b"world")];
+    let input = compressed.concat();
+
+    // reading byte by byte reveals where each member ends
+    let mut decompressor = MultiDecompressorReader::new(input.as_slice());
+    let mut ends = Vec::new();
+    let mut buf = [0; 1];
+
+    loop {
+        let bytes_read = decompressor.read(&mut buf).unwrap();
+
+        if decompressor.members() > ends.len() as u64 {
+            ends.push(decompressor.bytes_consumed());
+        }
+
+        if bytes_read == 0 {
+            break;
+        }
+    }
+
+    assert_eq!(ends, [compressed[0].len() as u64, input.len() as u64]);
+}
+
+#[test]
+fn test_multi_member_single() {
+    let input = common::gen_medium_entropy(1 << 12);
+    let compressed = common::compress(&input);
+
+    let mut decompressor = MultiDecompressorReader::new(compressed.as_slice());
+    let mut decompressed = Vec::new();
+    decompressor.read_to_end(&mut decompressed).unwrap();
+
+    assert_eq!(decompressed, input);
+    assert_eq!(decompressor.members(), 1);
+    assert!(decompressor.into_inner().unwrap().is_empty());
+}
+
+#[test]
+fn test_multi_member_truncated() {
+    let mut input = common::compress(b"hello world");
+    let second = common::compress(&common::gen_medium_entropy(1 << 12));
+    input.extend_from_slice(&second[..second.len() / 2]);
+
+    let mut decompressor = MultiDecompressorReader::new(input.as_slice());
+    let err = io::copy(&mut decompressor, &mut io::sink()).unwrap_err();
+
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    assert!(decompressor.into_inner().is_err());
+}
+
+#[test]
+fn test_multi_member_trailing_garbage() {
+    let mut input = common::compress(b"hello world");
+    input.extend_from_slice(&[0xff; 16]);
+
+    let mut decompressor = MultiDecompressorReader::new(input.as_slice());
+    let mut decompressed = Vec::new();
+
+    assert!(decompressor.read_to_end(&mut decompressed).is_err());
+    assert_eq!(decompressed, b"hello world");
+}
+
+#[test]
+fn test_multi_member_limits() {
+    let part = common::gen_min_entropy(1 << 12);
+    let input = [common::compress(&part), common::compress(&part)].concat();
+    let options = {
+        let mut options = BrotliDecoderOptions::new();
+        options.max_output_size(1 << 12);
+        options
+    };
+
+    // limits apply to each member individually
+    let mut decompressor =
+        MultiDecompressorReader::with_options(&options, input.as_slice()).unwrap();
+    let mut decompressed = Vec::new();
+    decompressor.read_to_end(&mut decompressed).unwrap();
+
+    assert_eq!(decompressed, [part.clone(), part].concat());
+}
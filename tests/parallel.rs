@@ -0,0 +1,320 @@
+#[cfg(feature = "checksum")]
+use brotlic::checksum::{Checksum, ChecksumMode};
+use brotlic::parallel::{
+    ParallelCompressorOptions, ParallelCompressorWriter, ParallelDecompressorOptions,
+};
+use brotlic::seekable::SeekableCompressorOptions;
+use brotlic::{BrotliDecoder, BrotliDecoderOptions, BrotliEncoderOptions, DecompressorReader};
+use brotlic::{LargeWindowSize, Quality, WindowSize};
+use std::io::{self, Cursor, Read, Write};
+
+mod common;
+
+fn compress(options: &ParallelCompressorOptions, input: &[u8]) -> Vec<u8> {
+    let mut compressor = options.build(Vec::new()).unwrap();
+
+    for chunk in input.chunks(10_000) {
+        compressor.write_all(chunk).unwrap();
+    }
+
+    compressor.into_inner().unwrap()
+}
+
+#[test]
+fn test_parallel_compress() {
+    let input = common::gen_medium_entropy(1 << 18);
+
+    for quality in [0, 1, 2, 5, 9, 11] {
+        let mut encoder = BrotliEncoderOptions::new();
+        encoder
+            .quality(Quality::new(quality).unwrap())
+            .window_size(WindowSize::new(16).unwrap());
+
+        let compressed = compress(
+            ParallelCompressorOptions::new()
+                .encoder_options(&encoder)
+                .block_size(1 << 15)
+                .threads(3),
+            &input,
+        );
+
+        assert!(compressed.len() < input.len());
+        assert_eq!(common::decompress(&compressed), input, "quality {}", quality);
+    }
+}
+
+#[test]
+fn test_parallel_compress_ratio() {
+    let input = common::gen_medium_entropy(1 << 20);
+
+    let mut encoder = BrotliEncoderOptions::new();
+    encoder.quality(Quality::new(5).unwrap());
+
+    let mut sequential = brotlic::CompressorWriter::with_encoder(encoder.build().unwrap(), vec![]);
+    sequential.write_all(&input).unwrap();
+    let sequential = sequential.into_inner().unwrap();
+
+    let parallel = compress(
+        ParallelCompressorOptions::new()
+            .encoder_options(&encoder)
+            .block_size(1 << 18),
+        &input,
+    );
+
+    // splitting into a few blocks costs only a little compression ratio
+    assert!(parallel.len() < sequential.len() + sequential.len() / 20);
+    assert_eq!(common::decompress(&parallel), input);
+}
+
+#[test]
+fn test_parallel_compress_block_boundaries() {
+    let options = {
+        let mut options = ParallelCompressorOptions::new();
+        options.block_size(1000).threads(2);
+        options
+    };
+
+    for len in [0, 1, 999, 1000, 1001, 4000] {
+        let input = common::gen_medium_entropy(len);
+        assert_eq!(
+            common::decompress(&compress(&options, &input)),
+            input,
+            "len {}",
+            len
+        );
+    }
+}
+
+#[test]
+fn test_parallel_compress_large_window() {
+    let input = common::gen_medium_entropy(1 << 18);
+    let mut encoder = BrotliEncoderOptions::new();
+    encoder.large_window_size(LargeWindowSize::new(26).unwrap());
+
+    let compressed = compress(
+        ParallelCompressorOptions::new()
+            .encoder_options(&encoder)
+            .block_size(1 << 16),
+        &input,
+    );
+
+    let decoder = BrotliDecoderOptions::new()
+        .large_window_size(true)
+        .build()
+        .unwrap();
+    let mut decompressor = DecompressorReader::with_decoder(decoder, compressed.as_slice());
+    let mut decompressed = Vec::new();
+    decompressor.read_to_end(&mut decompressed).unwrap();
+
+    assert_eq!(decompressed, input);
+}
+
+#[test]
+fn test_parallel_compress_flush() {
+    let mut compressor = ParallelCompressorOptions::new()
+        .block_size(1 << 12)
+        .build(Vec::new())
+        .unwrap();
+    let mut decoder = BrotliDecoder::new();
+    let mut decompressed = Vec::with_capacity(1 << 16);
+    let mut written = Vec::new();
+
+    for chunk in [
+        &b"hello "[..],
+        &common::gen_medium_entropy(10_000),
+        b"world",
+    ] {
+        compressor.write_all(chunk).unwrap();
+        compressor.flush().unwrap();
+        written.extend_from_slice(chunk);
+
+        // everything written so far can be decompressed after a flush
+        let compressed = std::mem::take(compressor.get_mut());
+        decoder
+            .decompress_into_vec(&compressed, &mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, written);
+    }
+
+    let compressed = compressor.into_inner().unwrap();
+    decoder
+        .decompress_into_vec(&compressed, &mut decompressed)
+        .unwrap();
+
+    assert!(decoder.is_finished());
+    assert_eq!(decompressed, written);
+}
+
+#[cfg(feature = "checksum")]
+#[test]
+fn test_parallel_compress_checksum() {
+    let input = common::gen_medium_entropy(1 << 16);
+
+    for mode in [ChecksumMode::Trailer, ChecksumMode::Flush] {
+        let mut encoder = BrotliEncoderOptions::new();
+        encoder.checksum(Checksum::Xxh64, mode);
+
+        let mut compressor = ParallelCompressorOptions::new()
+            .encoder_options(&encoder)
+            .block_size(1 << 12)
+            .build(Vec::new())
+            .unwrap();
+
+        // flush both at and in between block boundaries
+        compressor.write_all(&input[..1 << 13]).unwrap();
+        compressor.flush().unwrap();
+        compressor.write_all(&input[1 << 13..10_000]).unwrap();
+        compressor.flush().unwrap();
+        compressor.write_all(&input[10_000..]).unwrap();
+        let compressed = compressor.into_inner().unwrap();
+
+        let decoder = BrotliDecoderOptions::new()
+            .verify_checksums(true)
+            .build()
+            .unwrap();
+        let mut decompressor = DecompressorReader::with_decoder(decoder, compressed.as_slice());
+        let mut decompressed = Vec::new();
+        decompressor.read_to_end(&mut decompressed).unwrap();
+
+        assert_eq!(decompressed, input);
+    }
+}
+
+#[test]
+fn test_parallel_compress_drop() {
+    let input = common::gen_medium_entropy(1 << 14);
+    let mut output = Vec::new();
+
+    {
+        let mut compressor = ParallelCompressorWriter::new(&mut output);
+        compressor.write_all(&input).unwrap();
+    }
+
+    assert_eq!(common::decompress(&output), input);
+}
+
+#[test]
+fn test_parallel_compress_writer_error() {
+    #[derive(Debug)]
+    struct FailingWriter;
+
+    impl Write for FailingWriter {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::Error::other("disk full"))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut compressor = ParallelCompressorWriter::new(FailingWriter);
+    compressor.write_all(b"hello world").unwrap();
+
+    let err = compressor.into_inner().unwrap_err();
+    assert_eq!(err.error().kind(), io::ErrorKind::Other);
+}
+
+fn compress_seekable(encoder: &BrotliEncoderOptions, frame_size: usize, input: &[u8]) -> Vec<u8> {
+    let mut compressor = SeekableCompressorOptions::new()
+        .encoder_options(encoder)
+        .frame_size(frame_size)
+        .build(Vec::new())
+        .unwrap();
+
+    compressor.write_all(input).unwrap();
+    compressor.into_inner().unwrap()
+}
+
+#[test]
+fn test_parallel_decompress() {
+    let input = common::gen_medium_entropy(1 << 18);
+
+    for quality in [0, 5, 11] {
+        let mut encoder = BrotliEncoderOptions::new();
+        encoder
+            .quality(Quality::new(quality).unwrap())
+            .window_size(WindowSize::new(16).unwrap());
+
+        let compressed = compress_seekable(&encoder, 1 << 14, &input);
+
+        for threads in [1, 3] {
+            let mut decompressed = Vec::new();
+            let len = ParallelDecompressorOptions::new()
+                .threads(threads)
+                .decompress(Cursor::new(&compressed), &mut decompressed)
+                .unwrap();
+
+            assert_eq!(len, input.len() as u64);
+            assert_eq!(decompressed, input, "quality {}", quality);
+        }
+    }
+}
+
+#[test]
+fn test_parallel_decompress_into() {
+    let input = common::gen_min_entropy(1 << 18);
+    let mut encoder = BrotliEncoderOptions::new();
+    encoder.large_window_size(LargeWindowSize::new(26).unwrap());
+
+    let compressed = compress_seekable(&encoder, 1 << 15, &input);
+    let mut decompressed = vec![0xff; input.len() + 100];
+    let len = ParallelDecompressorOptions::new()
+        .decompress_into(Cursor::new(&compressed), &mut decompressed)
+        .unwrap();
+
+    assert_eq!(len, input.len());
+    assert_eq!(&decompressed[..len], input);
+    assert!(decompressed[len..].iter().all(|&b| b == 0xff));
+
+    let err = ParallelDecompressorOptions::new()
+        .decompress_into(Cursor::new(&compressed), &mut decompressed[..len - 1])
+        .unwrap_err();
+
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn test_parallel_decompress_empty() {
+    let compressed = compress_seekable(&BrotliEncoderOptions::new(), 1 << 10, &[]);
+    let mut decompressed = Vec::new();
+    let len = ParallelDecompressorOptions::new()
+        .decompress(Cursor::new(&compressed), &mut decompressed)
+        .unwrap();
+
+    assert_eq!(len, 0);
+    assert!(decompressed.is_empty());
+}
+
+#[test]
+fn test_parallel_decompress_corrupt() {
+    let input = common::gen_medium_entropy(1 << 16);
+    let mut compressed = compress_seekable(&BrotliEncoderOptions::new(), 1 << 12, &input);
+
+    // claim that the first frame is one byte longer than it is
+    let pos = compressed.windows(4).position(|w| w == b"BRSI").unwrap();
+    compressed[pos + 12] += 1;
+
+    let result = ParallelDecompressorOptions::new()
+        .threads(2)
+        .decompress(Cursor::new(&compressed), io::sink());
+
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_parallel_decompress_huge_frame() {
+    let input = common::gen_medium_entropy(1 << 16);
+    let mut compressed = compress_seekable(&BrotliEncoderOptions::new(), 1 << 12, &input);
+
+    // claim that the first frame is far too long to be allocated up front
+    let pos = compressed.windows(4).position(|w| w == b"BRSI").unwrap();
+    compressed[pos + 19] = 0x7f;
+
+    let err = ParallelDecompressorOptions::new()
+        .threads(2)
+        .decompress(Cursor::new(&compressed), io::sink())
+        .unwrap_err();
+
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
@@ -0,0 +1,259 @@
+#![cfg(feature = "precompress")]
+
+use brotlic::precompress::{self, Manifest, PrecompressOptions, MANIFEST_NAME};
+use brotlic::{CompressionMode, DecompressorReader, Quality, WindowSize};
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+mod common;
+
+/// Creates an empty directory for a test, removing what a previous run left behind.
+fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("brotlic-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("assets")).unwrap();
+    dir
+}
+
+fn write(path: &Path, data: &[u8], modified_secs: u64) {
+    fs::write(path, data).unwrap();
+    File::options()
+        .write(true)
+        .open(path)
+        .unwrap()
+        .set_modified(UNIX_EPOCH + Duration::from_secs(modified_secs))
+        .unwrap();
+}
+
+fn decompress(path: &Path) -> Vec<u8> {
+    let mut decompressor = DecompressorReader::new(io::BufReader::new(File::open(path).unwrap()));
+    let mut output = Vec::new();
+    decompressor.read_to_end(&mut output).unwrap();
+    output
+}
+
+fn text(len: usize) -> Vec<u8> {
+    b"<p>hello brotli world</p>\n"
+        .iter()
+        .copied()
+        .cycle()
+        .take(len)
+        .collect()
+}
+
+#[test]
+fn test_precompress_dir() {
+    let dir = test_dir("precompress");
+    let html = text(1 << 12);
+    let random = common::gen_max_entropy(1 << 12);
+
+    write(&dir.join("index.html"), &html, 1000);
+    write(&dir.join("assets/app.js"), &html, 1000);
+    write(&dir.join("assets/random.css"), &random, 1000);
+    write(&dir.join("assets/image.png"), &html, 1000);
+
+    let report = precompress::precompress_dir(&dir).unwrap();
+    assert_eq!(report.compressed(), 2);
+    assert_eq!(report.skipped(), 1);
+
+    assert_eq!(decompress(&dir.join("index.html.br")), html);
+    assert_eq!(decompress(&dir.join("assets/app.js.br")), html);
+    assert!(!dir.join("assets/random.css.br").exists());
+    assert!(!dir.join("assets/image.png.br").exists());
+
+    let manifest = report.manifest();
+    let paths: Vec<_> = manifest.entries().iter().map(|e| e.path()).collect();
+    assert_eq!(paths, ["assets/app.js", "assets/random.css", "index.html"]);
+
+    let entry = manifest.get("index.html").unwrap();
+    assert_eq!(entry.size(), html.len() as u64);
+    assert_eq!(
+        entry.compressed_size(),
+        Some(fs::metadata(dir.join("index.html.br")).unwrap().len())
+    );
+    assert_eq!(manifest.get("assets/random.css").unwrap().compressed_size(), None);
+
+    let file = io::BufReader::new(File::open(dir.join(MANIFEST_NAME)).unwrap());
+    assert_eq!(&Manifest::read(file).unwrap(), manifest);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_precompress_incremental() {
+    let dir = test_dir("precompress-incremental");
+    let mut options = PrecompressOptions::new();
+    options.quality(Quality::worst());
+
+    write(&dir.join("index.html"), &text(1 << 12), 1000);
+    write(&dir.join("assets/style.css"), &text(1 << 10), 1000);
+
+    let report = options.precompress_dir(&dir).unwrap();
+    assert_eq!(report.compressed(), 2);
+
+    let report = options.precompress_dir(&dir).unwrap();
+    assert_eq!(report.compressed(), 0);
+    assert_eq!(report.unchanged(), 2);
+
+    // a new modification time alone does not regenerate the output, as the hash is the same
+    write(&dir.join("index.html"), &text(1 << 12), 2000);
+    let report = options.precompress_dir(&dir).unwrap();
+    assert_eq!(report.compressed(), 0);
+    assert_eq!(report.unchanged(), 2);
+    assert_eq!(
+        report.manifest().get("index.html").unwrap().modified(),
+        Some(UNIX_EPOCH + Duration::from_secs(2000))
+    );
+
+    let mut changed = text(1 << 12);
+    changed[0] = b'P';
+    write(&dir.join("index.html"), &changed, 3000);
+    let report = options.precompress_dir(&dir).unwrap();
+    assert_eq!(report.compressed(), 1);
+    assert_eq!(report.unchanged(), 1);
+    assert_eq!(decompress(&dir.join("index.html.br")), changed);
+
+    // a missing output is regenerated even if the asset is unchanged
+    fs::remove_file(dir.join("assets/style.css.br")).unwrap();
+    let report = options.precompress_dir(&dir).unwrap();
+    assert_eq!(report.compressed(), 1);
+    assert!(dir.join("assets/style.css.br").exists());
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_precompress_settings_changed() {
+    let dir = test_dir("precompress-settings");
+    let mut options = PrecompressOptions::new();
+    options.quality(Quality::worst());
+
+    write(&dir.join("index.html"), &text(1 << 12), 1000);
+    let report = options.precompress_dir(&dir).unwrap();
+    assert_eq!(report.compressed(), 1);
+
+    let entry = report.manifest().get("index.html").unwrap();
+    assert_eq!(entry.quality(), Quality::worst());
+    assert_eq!(entry.window_size(), WindowSize::best());
+    assert_eq!(entry.mode(), CompressionMode::Text);
+
+    options.quality(Quality::best());
+    let report = options.precompress_dir(&dir).unwrap();
+    assert_eq!(report.compressed(), 1);
+    assert_eq!(report.manifest().get("index.html").unwrap().quality(), Quality::best());
+
+    options.window_size(WindowSize::worst());
+    let report = options.precompress_dir(&dir).unwrap();
+    assert_eq!(report.compressed(), 1);
+    assert_eq!(decompress(&dir.join("index.html.br")), text(1 << 12));
+
+    let report = options.precompress_dir(&dir).unwrap();
+    assert_eq!(report.compressed(), 0);
+    assert_eq!(report.unchanged(), 1);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_precompress_removed() {
+    let dir = test_dir("precompress-removed");
+
+    write(&dir.join("index.html"), &text(1 << 12), 1000);
+    write(&dir.join("assets/app.js"), &text(1 << 12), 1000);
+    precompress::precompress_dir(&dir).unwrap();
+
+    fs::remove_file(dir.join("assets/app.js")).unwrap();
+    let report = precompress::precompress_dir(&dir).unwrap();
+
+    assert_eq!(report.removed(), 1);
+    assert_eq!(report.unchanged(), 1);
+    assert!(!dir.join("assets/app.js.br").exists());
+    assert!(report.manifest().get("assets/app.js").is_none());
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_precompress_manifest_outside_dir() {
+    let dir = test_dir("precompress-outside");
+    let outside = dir.join("outside.js.br");
+    fs::write(&outside, b"not ours").unwrap();
+
+    // only the directory below `dir` is precompressed, the output next to it must stay
+    let assets = dir.join("assets");
+    write(&assets.join("index.html"), &text(1 << 12), 1000);
+    let manifest = "00 1 1 - 11 22 text ../outside.js\n";
+    fs::write(assets.join(MANIFEST_NAME), manifest).unwrap();
+
+    let err = precompress::precompress_dir(&assets).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert!(outside.exists());
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_precompress_not_smaller() {
+    let dir = test_dir("precompress-not-smaller");
+
+    write(&dir.join("index.html"), &text(1 << 12), 1000);
+    precompress::precompress_dir(&dir).unwrap();
+    assert!(dir.join("index.html.br").exists());
+
+    // the stale output of the previous contents must not be served anymore
+    write(&dir.join("index.html"), &common::gen_max_entropy(64), 2000);
+    let report = precompress::precompress_dir(&dir).unwrap();
+
+    assert_eq!(report.skipped(), 1);
+    assert!(!dir.join("index.html.br").exists());
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_manifest_malformed() {
+    for manifest in [
+        "0 1 2 3",
+        "zz 1 - - 11 22 text a",
+        "00 1 - 1 11 22 text a",
+        "00 1 x - 11 22 text a",
+        "00 1 - - 12 22 text a",
+        "00 1 - - 11 9 text a",
+        "00 1 - - 11 22 html a",
+        "00 1 - - a",
+        "00 1 - - 11 22 text /etc/passwd",
+        "00 1 - - 11 22 text ../outside.js",
+        "00 1 - - 11 22 text assets/../../outside.js",
+        "00 1 - - 11 22 text ./index.html",
+    ] {
+        let err = Manifest::read(manifest.as_bytes()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{}", manifest);
+    }
+
+    let manifest = Manifest::read(&b"# comment\n00ff 10 - 1.5 5 18 font a b\n"[..]).unwrap();
+    let entry = &manifest.entries()[0];
+    assert_eq!(entry.hash(), 0xff);
+    assert_eq!(entry.quality(), Quality::new(5).unwrap());
+    assert_eq!(entry.window_size(), WindowSize::new(18).unwrap());
+    assert_eq!(entry.mode(), CompressionMode::Font);
+    assert_eq!(entry.path(), "a b");
+}
+
+#[test]
+fn test_compression_mode() {
+    for (path, mode) in [
+        ("index.html", Some(CompressionMode::Text)),
+        ("STYLE.CSS", Some(CompressionMode::Text)),
+        ("app.min.js", Some(CompressionMode::Text)),
+        ("font.woff", Some(CompressionMode::Font)),
+        ("font.ttf", Some(CompressionMode::Font)),
+        ("font.woff2", None),
+        ("image.png", None),
+        ("index.html.br", None),
+        ("README", None),
+    ] {
+        assert_eq!(precompress::compression_mode(Path::new(path)), mode, "{}", path);
+    }
+}
@@ -0,0 +1,111 @@
+use brotlic::read::{CompressorReader, DecompressorReader};
+use brotlic::{BrotliDecoder, BrotliEncoder, CompressorWriter, HeaderBuilder};
+use std::io::{self, Read, Write};
+
+mod common;
+
+/// A reader that implements [`Read`] only and returns at most `chunk` bytes per read.
+#[derive(Debug)]
+struct ChunkedReader<'a> {
+    data: &'a [u8],
+    chunk: usize,
+}
+
+impl<'a> ChunkedReader<'a> {
+    fn new(data: &'a [u8], chunk: usize) -> Self {
+        ChunkedReader { data, chunk }
+    }
+}
+
+impl Read for ChunkedReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf.len().min(self.chunk);
+
+        self.data.read(&mut buf[..len])
+    }
+}
+
+#[test]
+fn test_read_round_trip() {
+    for input in [
+        Vec::new(),
+        common::gen_min_entropy(1 << 20),
+        common::gen_medium_entropy(1 << 18),
+        common::gen_max_entropy(1 << 16),
+    ] {
+        let mut compressor = CompressorReader::new(ChunkedReader::new(&input, 1000));
+        let mut compressed = Vec::new();
+        compressor.read_to_end(&mut compressed).unwrap();
+        assert!(compressor.into_inner().unwrap().data.is_empty());
+
+        let mut decompressor = DecompressorReader::new(ChunkedReader::new(&compressed, 1000));
+        let mut decompressed = Vec::new();
+        decompressor.read_to_end(&mut decompressed).unwrap();
+
+        assert_eq!(decompressed, input);
+        assert_eq!(decompressor.bytes_consumed(), compressed.len() as u64);
+        assert!(decompressor.into_inner().is_ok());
+    }
+}
+
+#[test]
+fn test_read_with_capacity() {
+    let input = common::gen_medium_entropy(1 << 16);
+
+    for capacity in [1, 7, 1 << 16] {
+        let mut compressor = CompressorReader::with_capacity(
+            capacity,
+            BrotliEncoder::new(),
+            ChunkedReader::new(&input, 5000),
+        );
+        let mut compressed = Vec::new();
+        compressor.read_to_end(&mut compressed).unwrap();
+
+        let mut decompressor = DecompressorReader::with_capacity(
+            capacity,
+            BrotliDecoder::new(),
+            ChunkedReader::new(&compressed, 5000),
+        );
+        let mut decompressed = Vec::new();
+        decompressor.read_to_end(&mut decompressed).unwrap();
+
+        assert_eq!(decompressed, input, "capacity {}", capacity);
+    }
+}
+
+#[test]
+fn test_read_header() {
+    let mut compressor = HeaderBuilder::new()
+        .filename("test.txt")
+        .write(Vec::new())
+        .unwrap();
+    compressor.write_all(b"hello world").unwrap();
+    let compressed = compressor.into_inner().unwrap();
+
+    let mut decompressor = DecompressorReader::new(ChunkedReader::new(&compressed, 3));
+    let header = decompressor.header().unwrap().unwrap();
+    assert_eq!(header.filename(), Some(&b"test.txt"[..]));
+
+    let mut decompressed = String::new();
+    decompressor.read_to_string(&mut decompressed).unwrap();
+    assert_eq!(decompressed, "hello world");
+}
+
+#[test]
+fn test_read_into_inner_unfinished() {
+    let compressed = {
+        let mut compressor = CompressorWriter::new(Vec::new());
+        compressor
+            .write_all(&common::gen_medium_entropy(1 << 14))
+            .unwrap();
+        compressor.into_inner().unwrap()
+    };
+
+    let truncated = &compressed[..compressed.len() / 2];
+    let mut decompressor = DecompressorReader::new(ChunkedReader::new(truncated, 1000));
+    let err = decompressor.read_to_end(&mut Vec::new()).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+    let err = decompressor.into_inner().unwrap_err();
+    assert_eq!(err.error().kind(), io::ErrorKind::UnexpectedEof);
+}
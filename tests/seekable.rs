@@ -0,0 +1,233 @@
+#[cfg(feature = "checksum")]
+use brotlic::checksum::{Checksum, ChecksumMode};
+use brotlic::seekable::{
+    Index, SeekableCompressorOptions, SeekableCompressorWriter, SeekableDecompressorReader,
+};
+use brotlic::BrotliEncoderOptions;
+#[cfg(feature = "checksum")]
+use brotlic::{BrotliDecoderOptions, DecompressorReader};
+use brotlic::{LargeWindowSize, Quality, WindowSize};
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+
+mod common;
+
+fn compress(options: &SeekableCompressorOptions, input: &[u8]) -> Vec<u8> {
+    let mut compressor = options.build(Vec::new()).unwrap();
+
+    for chunk in input.chunks(10_000) {
+        compressor.write_all(chunk).unwrap();
+    }
+
+    compressor.into_inner().unwrap()
+}
+
+fn check_seeks(compressed: Vec<u8>, input: &[u8]) {
+    let mut decompressor = SeekableDecompressorReader::new(Cursor::new(compressed)).unwrap();
+    let len = input.len() as u64;
+    let mut state = 0x2545_f491_4f6c_dd1du64;
+
+    assert_eq!(decompressor.index().uncompressed_len(), len);
+
+    for _ in 0..50 {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+
+        let offset = state % (len + 1);
+        let mut buf = vec![0; (state >> 40) as usize % 5000];
+
+        decompressor.seek(SeekFrom::Start(offset)).unwrap();
+        let end = input.len().min(offset as usize + buf.len());
+        let bytes_read = read_full(&mut decompressor, &mut buf);
+
+        assert_eq!(
+            &buf[..bytes_read],
+            &input[offset as usize..end],
+            "offset {}",
+            offset
+        );
+    }
+}
+
+fn read_full(reader: &mut impl Read, mut buf: &mut [u8]) -> usize {
+    let mut total = 0;
+
+    while !buf.is_empty() {
+        match reader.read(buf).unwrap() {
+            0 => break,
+            n => {
+                total += n;
+                buf = &mut buf[n..];
+            }
+        }
+    }
+
+    total
+}
+
+#[test]
+fn test_seekable_round_trip() {
+    let input = common::gen_medium_entropy(1 << 18);
+
+    for quality in [0, 1, 2, 5, 9, 11] {
+        let mut encoder = BrotliEncoderOptions::new();
+        encoder
+            .quality(Quality::new(quality).unwrap())
+            .window_size(WindowSize::new(16).unwrap());
+
+        let compressed = compress(
+            SeekableCompressorOptions::new()
+                .encoder_options(&encoder)
+                .frame_size(1 << 15),
+            &input,
+        );
+
+        // a seekable stream is a regular brotli stream
+        assert!(compressed.len() < input.len());
+        assert_eq!(common::decompress(&compressed), input, "quality {}", quality);
+
+        let index = Index::read(&mut Cursor::new(&compressed)).unwrap();
+        assert_eq!(index.frames().len(), 8);
+        assert_eq!(index.uncompressed_len(), input.len() as u64);
+
+        check_seeks(compressed, &input);
+    }
+}
+
+#[test]
+fn test_seekable_read_sequential() {
+    let input = common::gen_medium_entropy(100_000);
+    let compressed = compress(SeekableCompressorOptions::new().frame_size(7000), &input);
+
+    let mut decompressor = SeekableDecompressorReader::new(Cursor::new(compressed)).unwrap();
+    let mut decompressed = Vec::new();
+    decompressor.read_to_end(&mut decompressed).unwrap();
+    assert_eq!(decompressed, input);
+
+    // seeking backwards restarts at the containing frame
+    decompressor.seek(SeekFrom::End(-10_500)).unwrap();
+    decompressed.clear();
+    decompressor.read_to_end(&mut decompressed).unwrap();
+    assert_eq!(decompressed, &input[input.len() - 10_500..]);
+
+    assert_eq!(decompressor.seek(SeekFrom::Current(-1)).unwrap(), 99_999);
+    assert!(decompressor.seek(SeekFrom::Current(-100_000)).is_err());
+}
+
+#[test]
+fn test_seekable_large_window() {
+    let input = common::gen_min_entropy(1 << 18);
+    let mut encoder = BrotliEncoderOptions::new();
+    encoder.large_window_size(LargeWindowSize::new(26).unwrap());
+
+    let compressed = compress(
+        SeekableCompressorOptions::new()
+            .encoder_options(&encoder)
+            .frame_size(1 << 16),
+        &input,
+    );
+
+    check_seeks(compressed, &input);
+}
+
+#[test]
+fn test_seekable_empty() {
+    let compressed = compress(&SeekableCompressorOptions::new(), &[]);
+    assert!(common::decompress(&compressed).is_empty());
+
+    let mut decompressor = SeekableDecompressorReader::new(Cursor::new(compressed)).unwrap();
+    let mut decompressed = Vec::new();
+    decompressor.read_to_end(&mut decompressed).unwrap();
+
+    assert!(decompressor.index().frames().is_empty());
+    assert!(decompressed.is_empty());
+}
+
+#[test]
+fn test_seekable_flush() {
+    let input = common::gen_medium_entropy(30_000);
+    let mut compressor = SeekableCompressorOptions::new()
+        .frame_size(1 << 16)
+        .build(Vec::new())
+        .unwrap();
+
+    compressor.write_all(&input[..10_000]).unwrap();
+    compressor.flush().unwrap();
+    compressor.flush().unwrap();
+    compressor.write_all(&input[10_000..]).unwrap();
+    let compressed = compressor.into_inner().unwrap();
+
+    let index = Index::read(&mut Cursor::new(&compressed)).unwrap();
+    let lens: Vec<_> = index
+        .frames()
+        .iter()
+        .map(|f| f.uncompressed_len())
+        .collect();
+    assert_eq!(lens, [10_000, 20_000]);
+
+    assert_eq!(common::decompress(&compressed), input);
+    check_seeks(compressed, &input);
+}
+
+#[cfg(feature = "checksum")]
+#[test]
+fn test_seekable_checksum() {
+    let input = common::gen_medium_entropy(1 << 16);
+
+    for mode in [ChecksumMode::Trailer, ChecksumMode::Flush] {
+        let mut encoder = BrotliEncoderOptions::new();
+        encoder.checksum(Checksum::Xxh64, mode);
+
+        let mut compressor = SeekableCompressorOptions::new()
+            .encoder_options(&encoder)
+            .frame_size(1 << 12)
+            .build(Vec::new())
+            .unwrap();
+
+        compressor.write_all(&input[..10_000]).unwrap();
+        compressor.flush().unwrap();
+        compressor.write_all(&input[10_000..]).unwrap();
+        let compressed = compressor.into_inner().unwrap();
+
+        let decoder = BrotliDecoderOptions::new()
+            .verify_checksums(true)
+            .build()
+            .unwrap();
+        let mut decompressor = DecompressorReader::with_decoder(decoder, compressed.as_slice());
+        let mut decompressed = Vec::new();
+        decompressor.read_to_end(&mut decompressed).unwrap();
+
+        assert_eq!(decompressed, input);
+        check_seeks(compressed, &input);
+    }
+}
+
+#[test]
+fn test_seekable_drop() {
+    let input = common::gen_medium_entropy(1 << 14);
+    let mut output = Vec::new();
+
+    {
+        let mut compressor = SeekableCompressorWriter::new(&mut output);
+        compressor.write_all(&input).unwrap();
+    }
+
+    check_seeks(output, &input);
+}
+
+#[test]
+fn test_seekable_invalid_index() {
+    let mut compressor = brotlic::CompressorWriter::new(Vec::new());
+    compressor.write_all(b"hello world").unwrap();
+    let compressed = compressor.into_inner().unwrap();
+
+    let err = SeekableDecompressorReader::new(Cursor::new(compressed)).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+    // corrupt the compressed length of the first frame
+    let mut compressed = compress(&SeekableCompressorOptions::new(), b"hello world");
+    let pos = compressed.windows(4).position(|w| w == b"BRSI").unwrap();
+    compressed[pos + 4] ^= 0xff;
+
+    assert!(SeekableDecompressorReader::new(Cursor::new(compressed)).is_err());
+}
@@ -0,0 +1,239 @@
+#![cfg(feature = "tokio")]
+
+#[cfg(feature = "checksum")]
+use brotlic::checksum::{Checksum, ChecksumMode};
+use brotlic::decode::LimitError;
+use brotlic::tokio::{CompressorReader, CompressorWriter, DecompressorReader, DecompressorWriter};
+use brotlic::BrotliDecoderOptions;
+#[cfg(feature = "checksum")]
+use brotlic::BrotliEncoderOptions;
+use std::io;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, BufReader};
+
+mod common;
+
+fn run<F: std::future::Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap()
+        .block_on(future)
+}
+
+#[test]
+fn test_compressor_writer_duplex() {
+    let input = common::gen_medium_entropy(1 << 18);
+
+    let compressed = run(async {
+        let (client, mut server) = tokio::io::duplex(64);
+
+        let writer = async {
+            let mut compressor = CompressorWriter::new(client);
+            compressor.write_all(&input).await?;
+            compressor.shutdown().await
+        };
+        let reader = async {
+            let mut compressed = Vec::new();
+            server.read_to_end(&mut compressed).await?;
+            Ok::<_, io::Error>(compressed)
+        };
+
+        let (written, compressed) = tokio::join!(writer, reader);
+        written.unwrap();
+        compressed.unwrap()
+    });
+
+    assert_eq!(common::decompress(&compressed), input);
+}
+
+#[test]
+fn test_compressor_writer_flush() {
+    run(async {
+        let (client, server) = tokio::io::duplex(1 << 16);
+        let mut compressor = CompressorWriter::new(client);
+        let mut decompressor = DecompressorReader::new(BufReader::new(server));
+
+        for chunk in [b"hello".as_slice(), b" async", b" world"] {
+            compressor.write_all(chunk).await.unwrap();
+            compressor.flush().await.unwrap();
+
+            let mut buf = vec![0; chunk.len()];
+            decompressor.read_exact(&mut buf).await.unwrap();
+            assert_eq!(buf, chunk);
+        }
+
+        compressor.shutdown().await.unwrap();
+        drop(compressor);
+
+        let mut rest = Vec::new();
+        decompressor.read_to_end(&mut rest).await.unwrap();
+        assert!(rest.is_empty());
+        assert!(decompressor.is_finished());
+    });
+}
+
+#[test]
+fn test_compressor_reader_duplex() {
+    let input = common::gen_medium_entropy(1 << 18);
+
+    let compressed = run(async {
+        let (mut client, server) = tokio::io::duplex(64);
+
+        let writer = async {
+            client.write_all(&input).await?;
+            client.shutdown().await
+        };
+        let reader = async {
+            let mut compressor = CompressorReader::new(BufReader::new(server));
+            let mut compressed = Vec::new();
+            compressor.read_to_end(&mut compressed).await?;
+            assert!(compressor.is_finished());
+            Ok::<_, io::Error>(compressed)
+        };
+
+        let (written, compressed) = tokio::join!(writer, reader);
+        written.unwrap();
+        compressed.unwrap()
+    });
+
+    assert_eq!(common::decompress(&compressed), input);
+}
+
+#[test]
+fn test_decompressor_writer_duplex() {
+    let input = common::gen_medium_entropy(1 << 18);
+    let compressed = common::compress(&input);
+
+    let decompressed = run(async {
+        let (client, mut server) = tokio::io::duplex(64);
+
+        let writer = async {
+            let mut decompressor = DecompressorWriter::new(client);
+            decompressor.write_all(&compressed).await?;
+            decompressor.shutdown().await
+        };
+        let reader = async {
+            let mut decompressed = Vec::new();
+            server.read_to_end(&mut decompressed).await?;
+            Ok::<_, io::Error>(decompressed)
+        };
+
+        let (written, decompressed) = tokio::join!(writer, reader);
+        written.unwrap();
+        decompressed.unwrap()
+    });
+
+    assert_eq!(decompressed, input);
+}
+
+#[test]
+fn test_decompressor_writer_truncated() {
+    let compressed = common::compress(&common::gen_medium_entropy(1 << 12));
+
+    let err = run(async {
+        let mut decompressor = DecompressorWriter::new(Vec::new());
+        decompressor
+            .write_all(&compressed[..compressed.len() / 2])
+            .await
+            .unwrap();
+        decompressor.shutdown().await.unwrap_err()
+    });
+
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn test_decompressor_reader_duplex() {
+    let input = common::gen_medium_entropy(1 << 18);
+    let compressed = common::compress(&input);
+
+    let decompressed = run(async {
+        let (mut client, server) = tokio::io::duplex(64);
+
+        let writer = async {
+            client.write_all(&compressed).await?;
+            client.shutdown().await
+        };
+        let reader = async {
+            let mut decompressor = DecompressorReader::new(BufReader::new(server));
+            let mut decompressed = Vec::new();
+            decompressor.read_to_end(&mut decompressed).await?;
+            Ok::<_, io::Error>(decompressed)
+        };
+
+        let (written, decompressed) = tokio::join!(writer, reader);
+        written.unwrap();
+        decompressed.unwrap()
+    });
+
+    assert_eq!(decompressed, input);
+}
+
+#[test]
+fn test_decompressor_reader_truncated() {
+    let compressed = common::compress(&common::gen_medium_entropy(1 << 12));
+
+    let err = run(async {
+        let mut decompressor = DecompressorReader::new(&compressed[..compressed.len() / 2]);
+        decompressor.read_to_end(&mut Vec::new()).await.unwrap_err()
+    });
+
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+}
+
+#[cfg(feature = "checksum")]
+#[test]
+fn test_checksums() {
+    let input = common::gen_medium_entropy(1 << 16);
+
+    run(async {
+        let encoder = BrotliEncoderOptions::new()
+            .checksum(Checksum::Xxh64, ChecksumMode::Flush)
+            .build()
+            .unwrap();
+        let mut compressor = CompressorWriter::with_encoder(encoder, Vec::new());
+        compressor.write_all(&input[..1000]).await.unwrap();
+        compressor.flush().await.unwrap();
+        compressor.write_all(&input[1000..]).await.unwrap();
+        compressor.shutdown().await.unwrap();
+        let compressed = compressor.into_inner();
+
+        let decoder = BrotliDecoderOptions::new()
+            .verify_checksums(true)
+            .build()
+            .unwrap();
+        let mut decompressor = DecompressorReader::with_decoder(decoder, compressed.as_slice());
+        let mut decompressed = Vec::new();
+        decompressor.read_to_end(&mut decompressed).await.unwrap();
+        assert_eq!(decompressed, input);
+
+        let decoder = BrotliDecoderOptions::new()
+            .verify_checksums(true)
+            .build()
+            .unwrap();
+        let mut decompressor = DecompressorWriter::with_decoder(decoder, Vec::new());
+        decompressor.write_all(&compressed).await.unwrap();
+        decompressor.shutdown().await.unwrap();
+        assert_eq!(decompressor.into_inner(), input);
+    });
+}
+
+#[test]
+fn test_limits() {
+    let input = common::gen_medium_entropy(1 << 16);
+
+    run(async {
+        let mut compressor = CompressorWriter::new(Vec::new());
+        compressor.write_all(&input).await.unwrap();
+        compressor.shutdown().await.unwrap();
+        let compressed = compressor.into_inner();
+
+        let decoder = BrotliDecoderOptions::new()
+            .max_output_size(1 << 10)
+            .build()
+            .unwrap();
+        let mut decompressor = DecompressorWriter::with_decoder(decoder, Vec::new());
+        let err = decompressor.write_all(&compressed).await.unwrap_err();
+        let err = err.into_inner().unwrap().downcast::<LimitError>().unwrap();
+        assert_eq!(*err, LimitError::OutputSize);
+    });
+}
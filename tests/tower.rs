@@ -0,0 +1,310 @@
+#![cfg(feature = "tower")]
+
+use brotlic::BrotliDecoderOptions;
+use brotlic::tower::{BrotliBody, BrotliLayer};
+use bytes::Bytes;
+use futures::channel::mpsc;
+use http::header::{self, HeaderValue};
+use http::{Request, Response, StatusCode};
+use http_body_util::{BodyExt, Full, StreamBody};
+use hyper::body::{Frame, Incoming};
+use hyper_util::rt::TokioIo;
+use hyper_util::service::TowerToHyperService;
+use std::convert::Infallible;
+use std::error::Error;
+use tower::{Layer, Service, ServiceExt, service_fn};
+
+mod common;
+
+type BoxError = Box<dyn Error + Send + Sync>;
+
+/// Sends `request` to `service` served by hyper over an in-memory connection, returning the
+/// response with its body collected.
+async fn send<S, B>(service: S, request: Request<Full<Bytes>>) -> Response<Bytes>
+where
+    S: Service<Request<Incoming>, Response = Response<B>> + Clone + 'static,
+    S::Error: Into<BoxError>,
+    B: http_body::Body + 'static,
+    B::Error: Into<BoxError>,
+{
+    let (client_io, server_io) = tokio::io::duplex(1 << 16);
+    let server = hyper::server::conn::http1::Builder::new()
+        .serve_connection(TokioIo::new(server_io), TowerToHyperService::new(service));
+    let (mut sender, connection) = hyper::client::conn::http1::handshake(TokioIo::new(client_io))
+        .await
+        .unwrap();
+
+    let client = async move {
+        let response = sender.send_request(request).await.unwrap();
+        let (parts, body) = response.into_parts();
+        let body = body.collect().await.unwrap().to_bytes();
+
+        Response::from_parts(parts, body)
+    };
+
+    let (response, server, connection) = tokio::join!(client, server, connection);
+    server.unwrap();
+    connection.unwrap();
+
+    response
+}
+
+fn get(accept_encoding: Option<&'static str>) -> Request<Full<Bytes>> {
+    let mut request = Request::new(Full::default());
+
+    if let Some(accept_encoding) = accept_encoding {
+        request.headers_mut().insert(
+            header::ACCEPT_ENCODING,
+            HeaderValue::from_static(accept_encoding),
+        );
+    }
+
+    request
+}
+
+fn respond_with(
+    body: Vec<u8>,
+    content_type: &'static str,
+) -> impl Service<
+    Request<BrotliBody<Incoming>>,
+    Response = Response<Full<Bytes>>,
+    Error = Infallible,
+    Future = impl Send,
+> + Clone {
+    service_fn(move |_request| {
+        let mut response = Response::new(Full::new(Bytes::from(body.clone())));
+        response
+            .headers_mut()
+            .insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+
+        async move { Ok(response) }
+    })
+}
+
+#[tokio::test]
+async fn test_compress_response() {
+    let input = common::gen_medium_entropy(1 << 16);
+    let service = BrotliLayer::new().layer(respond_with(input.clone(), "text/plain"));
+    let response = send(service, get(Some("gzip, deflate, br"))).await;
+
+    let headers = response.headers();
+    assert_eq!(headers[header::CONTENT_ENCODING], "br");
+    assert_eq!(headers[header::VARY], "accept-encoding");
+    assert!(!headers.contains_key(header::CONTENT_LENGTH));
+    assert!(response.body().len() < input.len());
+    assert_eq!(common::decompress(response.body()), input);
+}
+
+#[tokio::test]
+async fn test_compress_response_not_accepted() {
+    let input = common::gen_medium_entropy(1 << 12);
+
+    for accept_encoding in [None, Some("gzip"), Some("br;q=0, *"), Some("*;q=0")] {
+        let service = BrotliLayer::new().layer(respond_with(input.clone(), "text/plain"));
+        let response = send(service, get(accept_encoding)).await;
+
+        let headers = response.headers();
+        assert!(!headers.contains_key(header::CONTENT_ENCODING));
+        assert_eq!(headers[header::VARY], "accept-encoding");
+        assert_eq!(response.body().as_ref(), input);
+    }
+
+    let service = BrotliLayer::new().layer(respond_with(input.clone(), "text/plain"));
+    let response = send(service, get(Some("gzip;q=1.0, *;q=0.5"))).await;
+    assert_eq!(response.headers()[header::CONTENT_ENCODING], "br");
+}
+
+#[tokio::test]
+async fn test_compress_response_skipped() {
+    let input = common::gen_medium_entropy(1 << 12);
+
+    let service = BrotliLayer::new().layer(respond_with(input.clone(), "image/png"));
+    let response = send(service, get(Some("br"))).await;
+    assert!(!response.headers().contains_key(header::CONTENT_ENCODING));
+    assert!(!response.headers().contains_key(header::VARY));
+    assert_eq!(response.body().as_ref(), input);
+
+    let service = BrotliLayer::new().layer(respond_with(b"tiny".to_vec(), "text/plain"));
+    let response = send(service, get(Some("br"))).await;
+    assert!(!response.headers().contains_key(header::CONTENT_ENCODING));
+    assert_eq!(response.body().as_ref(), b"tiny");
+
+    let compressed = common::compress(&input);
+    let service = BrotliLayer::new().layer(service_fn(move |_request| {
+        let response = Response::builder()
+            .header(header::CONTENT_ENCODING, "br")
+            .body(Full::new(Bytes::from(compressed.clone())))
+            .unwrap();
+
+        async move { Ok::<_, Infallible>(response) }
+    }));
+    let response = send(service, get(Some("br"))).await;
+    assert_eq!(response.headers()[header::CONTENT_ENCODING], "br");
+    assert_eq!(common::decompress(response.body()), input);
+}
+
+#[tokio::test]
+async fn test_decompress_request() {
+    let input = common::gen_medium_entropy(1 << 16);
+    let service = BrotliLayer::new().layer(service_fn(
+        |request: Request<BrotliBody<Incoming>>| async move {
+            assert!(!request.headers().contains_key(header::CONTENT_ENCODING));
+            let body = request.into_body().collect().await?.to_bytes();
+
+            Ok::<_, BoxError>(Response::new(Full::new(body)))
+        },
+    ));
+
+    let request = Request::builder()
+        .header(header::CONTENT_ENCODING, "br")
+        .body(Full::new(Bytes::from(common::compress(&input))))
+        .unwrap();
+    let response = send(service, request).await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.body().as_ref(), input);
+}
+
+#[tokio::test]
+async fn test_decompress_request_limit() {
+    let input = common::gen_min_entropy(1 << 20);
+    let layer = BrotliLayer::new()
+        .decoder_options(BrotliDecoderOptions::new().max_output_size(1 << 16))
+        .unwrap();
+    let service = layer.layer(service_fn(
+        |request: Request<BrotliBody<Incoming>>| async move {
+            let status = match request.into_body().collect().await {
+                Ok(_) => StatusCode::OK,
+                Err(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            };
+
+            let mut response = Response::new(Full::<Bytes>::default());
+            *response.status_mut() = status;
+            Ok::<_, Infallible>(response)
+        },
+    ));
+
+    let request = Request::builder()
+        .header(header::CONTENT_ENCODING, "br")
+        .body(Full::new(Bytes::from(common::compress(&input))))
+        .unwrap();
+    let response = send(service, request).await;
+
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+}
+
+#[tokio::test]
+async fn test_decompress_request_unavailable() {
+    // valid options, but far too little memory to allocate a decoder
+    let layer = BrotliLayer::new()
+        .decoder_options(BrotliDecoderOptions::new().max_memory(16))
+        .unwrap();
+    let service = layer.layer(service_fn(
+        |_request: Request<BrotliBody<Full<Bytes>>>| async move {
+            Ok::<_, Infallible>(Response::new(Full::<Bytes>::default()))
+        },
+    ));
+
+    let request = Request::builder()
+        .header(header::CONTENT_ENCODING, "br")
+        .body(Full::new(Bytes::from(common::compress(b"hello"))))
+        .unwrap();
+    let response = service.oneshot(request).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    assert!(body.is_empty());
+}
+
+#[tokio::test]
+async fn test_compress_streaming_response() {
+    let (sender, receiver) = mpsc::unbounded::<Result<Frame<Bytes>, Infallible>>();
+    let mut receiver = Some(receiver);
+    let service = BrotliLayer::new().flush_each_chunk(true).layer(service_fn(
+        move |_request: Request<BrotliBody<Full<Bytes>>>| {
+            let body = StreamBody::new(receiver.take().unwrap());
+            async move { Ok::<_, Infallible>(Response::new(body)) }
+        },
+    ));
+
+    // no chunk is sent up front, so the response body must be streamed to receive it
+    sender
+        .unbounded_send(Ok(Frame::data(Bytes::from_static(b"hello "))))
+        .unwrap();
+
+    let response = service.oneshot(get(Some("br"))).await.unwrap();
+    let mut body = response.into_body();
+    let mut decoder = brotlic::BrotliDecoder::new();
+    let mut decompressed = Vec::with_capacity(64);
+
+    for chunk in [&b"brotli "[..], b"world"] {
+        let frame = body.frame().await.unwrap().unwrap();
+        let data = frame.into_data().unwrap();
+        decoder
+            .decompress_into_vec(&data, &mut decompressed)
+            .unwrap();
+        sender
+            .unbounded_send(Ok(Frame::data(Bytes::from(chunk))))
+            .unwrap();
+    }
+
+    sender.close_channel();
+
+    while let Some(frame) = body.frame().await {
+        let data = frame.unwrap().into_data().unwrap();
+        decoder
+            .decompress_into_vec(&data, &mut decompressed)
+            .unwrap();
+    }
+
+    assert!(decoder.is_finished());
+    assert_eq!(decompressed, b"hello brotli world");
+}
+
+#[tokio::test]
+async fn test_compress_response_trailers() {
+    let trailers = {
+        let mut trailers = http::HeaderMap::new();
+        trailers.insert("checksum", HeaderValue::from_static("abc"));
+        trailers
+    };
+
+    let frames = vec![
+        Ok::<_, Infallible>(Frame::data(Bytes::from_static(b"hello world"))),
+        Ok(Frame::trailers(trailers.clone())),
+    ];
+    let mut frames = Some(frames);
+    let service = BrotliLayer::new().layer(service_fn(
+        move |_request: Request<BrotliBody<Full<Bytes>>>| {
+            let body = StreamBody::new(futures::stream::iter(frames.take().unwrap()));
+            async move { Ok::<_, Infallible>(Response::new(body)) }
+        },
+    ));
+
+    let response = service.oneshot(get(Some("br"))).await.unwrap();
+    assert_eq!(response.headers()[header::CONTENT_ENCODING], "br");
+
+    let collected = response.into_body().collect().await.unwrap();
+    assert_eq!(collected.trailers(), Some(&trailers));
+    assert_eq!(common::decompress(&collected.to_bytes()), b"hello world");
+}
+
+#[tokio::test]
+async fn test_vary_preserved() {
+    let service = BrotliLayer::new().layer(service_fn(
+        |_request: Request<BrotliBody<Full<Bytes>>>| async {
+            let response = Response::builder()
+                .header(header::VARY, "Origin, Accept-Encoding")
+                .body(Full::new(Bytes::from(vec![b'a'; 1024])))
+                .unwrap();
+
+            Ok::<_, Infallible>(response)
+        },
+    ));
+
+    let response = service.oneshot(get(Some("br"))).await.unwrap();
+    let vary: Vec<_> = response.headers().get_all(header::VARY).iter().collect();
+
+    assert_eq!(vary, ["Origin, Accept-Encoding"]);
+    assert_eq!(response.headers()[header::CONTENT_ENCODING], "br");
+}